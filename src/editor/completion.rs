@@ -0,0 +1,427 @@
+use super::width::text_width;
+use super::{columns, last_line, LineEditor, Screen};
+use crate::syscalls::{open, close, access, getdents64, write, STDOUT, O_RDONLY, O_DIRECTORY, O_CLOEXEC, X_OK, DT_DIR, DT_LNK, DT_UNKNOWN};
+use crate::shell::parser::{expand_tilde, DirentParser};
+use crate::shell::builtins::BUILTIN_NAMES;
+use crate::shell::storage::{ENV_STORAGE, ALIASES, FUNCTIONS};
+use crate::io::LineBuffer;
+
+/// Most names one completion considers; the rest are left out.
+const MAX_CANDIDATES: usize = 256;
+/// Room for the text of those names.
+const NAMES_SIZE: usize = 16384;
+/// Longest path, or `PATH`, completion works with.
+const PATH_SIZE: usize = 4096;
+/// Where commands are looked for when `PATH` is not set.
+const DEFAULT_PATH: &[u8] = b"/bin:/usr/bin:/usr/local/bin";
+
+/// Bytes that end a word, unless quoted or escaped.
+fn is_separator(b: u8) -> bool {
+    matches!(b, b' ' | b'\t' | b'\n' | b';' | b'|' | b'&' | b'<' | b'>' | b'(' | b')')
+}
+
+/// Bytes of a completed name that need a backslash to stand for
+/// themselves.
+fn needs_escape(b: u8) -> bool {
+    is_separator(b) || matches!(b, b'\\' | b'\'' | b'"' | b'$' | b'`' | b'*' | b'?' | b'[' | b']' | b'{' | b'}' | b'#')
+}
+
+/// Reserved words after which a command name is expected.
+fn starts_command(word: &[u8]) -> bool {
+    matches!(word, b"if" | b"then" | b"elif" | b"else" | b"while" | b"until" | b"do" | b"!" | b"{")
+}
+
+/// The word the cursor is at the end of, as far as completion needs it.
+struct Word {
+    /// Offset in the line where it starts
+    start: usize,
+    /// The quote left open in it, if any
+    quote: Option<u8>,
+    /// The word without its quotes and backslashes
+    text: [u8; PATH_SIZE],
+    len: usize,
+}
+
+impl Word {
+    /// Finds the word that ends at `cursor`. `None` if it is too long.
+    fn before(line: &[u8], cursor: usize) -> Option<Self> {
+        let mut word = Self { start: 0, quote: None, text: [0u8; PATH_SIZE], len: 0 };
+        let mut i = 0;
+        while i < cursor {
+            let b = line[i];
+            match word.quote {
+                Some(quote) if b == quote => word.quote = None,
+                Some(b'"') if b == b'\\' && i + 1 < cursor => {
+                    i += 1;
+                    word.push(line[i])?;
+                }
+                Some(_) => word.push(b)?,
+                None if b == b'\'' || b == b'"' => word.quote = Some(b),
+                None if b == b'\\' && i + 1 < cursor => {
+                    i += 1;
+                    word.push(line[i])?;
+                }
+                None if is_separator(b) => {
+                    word.start = i + 1;
+                    word.len = 0;
+                }
+                None => word.push(b)?,
+            }
+            i += 1;
+        }
+        Some(word)
+    }
+    
+    fn push(&mut self, b: u8) -> Option<()> {
+        *self.text.get_mut(self.len)? = b;
+        self.len += 1;
+        Some(())
+    }
+    
+    fn text(&self) -> &[u8] {
+        &self.text[..self.len]
+    }
+}
+
+/// Whether a command name goes at offset `start` of `line`: at the start
+/// of the line, after an operator, or after a reserved word like `then`
+/// that is itself where a command goes.
+fn is_command_position(line: &[u8], start: usize) -> bool {
+    let before = &line[..start];
+    let end = match before.iter().rposition(|&b| !matches!(b, b' ' | b'\t')) {
+        Some(last) => last + 1,
+        None => return true,
+    };
+    if matches!(before[end - 1], b';' | b'|' | b'&' | b'(' | b'\n') {
+        return true;
+    }
+    let word_start = before[..end].iter().rposition(|&b| is_separator(b)).map_or(0, |i| i + 1);
+    starts_command(&before[word_start..end]) && is_command_position(line, word_start)
+}
+
+/// Names that complete a word, kept sorted and without repeats.
+struct Candidates {
+    names: [u8; NAMES_SIZE],
+    names_len: usize,
+    /// Where each name is in `names`, its length, and whether it is a
+    /// directory, in sorted order
+    index: [(usize, usize, bool); MAX_CANDIDATES],
+    count: usize,
+}
+
+impl Candidates {
+    fn new() -> Self {
+        Self {
+            names: [0u8; NAMES_SIZE],
+            names_len: 0,
+            index: [(0, 0, false); MAX_CANDIDATES],
+            count: 0,
+        }
+    }
+    
+    fn get(&self, i: usize) -> &[u8] {
+        let (start, len, _) = self.index[i];
+        &self.names[start..start + len]
+    }
+    
+    fn is_dir(&self, i: usize) -> bool {
+        self.index[i].2
+    }
+    
+    fn add(&mut self, name: &[u8], dir: bool) {
+        let at = (0..self.count).find(|&i| self.get(i) >= name).unwrap_or(self.count);
+        if at < self.count && self.get(at) == name {
+            return;
+        }
+        if self.count == MAX_CANDIDATES || self.names_len + name.len() > NAMES_SIZE {
+            return;
+        }
+        
+        let start = self.names_len;
+        self.names[start..start + name.len()].copy_from_slice(name);
+        self.names_len += name.len();
+        self.index.copy_within(at..self.count, at + 1);
+        self.index[at] = (start, name.len(), dir);
+        self.count += 1;
+    }
+    
+    /// Length of the start all the names share.
+    fn common_len(&self) -> usize {
+        if self.count == 0 {
+            return 0;
+        }
+        let first = self.get(0);
+        (1..self.count).fold(first.len(), |len, i| {
+            first[..len].iter().zip(self.get(i)).take_while(|(a, b)| a == b).count()
+        })
+    }
+}
+
+/// Copies `parts` one after the other into `out`, ending with a NUL.
+/// `None` if they don't fit.
+fn c_path<'a>(parts: &[&[u8]], out: &'a mut [u8]) -> Option<&'a [u8]> {
+    let mut len = 0;
+    for part in parts {
+        out.get_mut(len..len + part.len())?.copy_from_slice(part);
+        len += part.len();
+    }
+    *out.get_mut(len)? = 0;
+    Some(&out[..len + 1])
+}
+
+fn is_directory(path: &[u8]) -> bool {
+    let fd = open(path, O_RDONLY | O_DIRECTORY | O_CLOEXEC, 0);
+    if fd < 0 {
+        return false;
+    }
+    close(fd as i32);
+    true
+}
+
+/// Calls `f` with the name of every entry of directory `dir` that starts
+/// with `prefix`, and whether it is a directory itself. `dir` is empty or
+/// ends with a slash. Dotfiles only match a prefix that starts with a `.`.
+fn for_each_entry<F>(dir: &[u8], prefix: &[u8], mut f: F) where F: FnMut(&[u8], bool) {
+    let mut path = [0u8; PATH_SIZE];
+    let opened = match c_path(&[if dir.is_empty() { b"." } else { dir }], &mut path) {
+        Some(dir) => open(dir, O_RDONLY | O_DIRECTORY | O_CLOEXEC, 0),
+        None => return,
+    };
+    if opened < 0 {
+        return;
+    }
+    let fd = opened as i32;
+    
+    let mut buf = [0u8; 1024];
+    loop {
+        let nread = getdents64(fd, &mut buf);
+        if nread <= 0 {
+            break;
+        }
+        
+        let mut parser = DirentParser::new(&buf[..nread as usize]);
+        while let Some(entry) = parser.next() {
+            let name = entry.name;
+            if name.is_empty() || name == b"." || name == b".." || !name.starts_with(prefix) {
+                continue;
+            }
+            if name[0] == b'.' && prefix.first() != Some(&b'.') {
+                continue;
+            }
+            
+            // Links, and entries of filesystems that don't say, are looked at
+            let dir = match entry.kind {
+                DT_DIR => true,
+                DT_LNK | DT_UNKNOWN => c_path(&[dir, name], &mut path).is_some_and(is_directory),
+                _ => false,
+            };
+            f(name, dir);
+        }
+    }
+    
+    close(fd);
+}
+
+/// Filenames that complete `word`, a path whose last part is being typed.
+/// Returns the length of that part.
+fn complete_file(word: &[u8], candidates: &mut Candidates) -> usize {
+    let split = word.iter().rposition(|&b| b == b'/').map_or(0, |slash| slash + 1);
+    let (dir, prefix) = word.split_at(split);
+    
+    let mut expanded = LineBuffer::new();
+    let dir = match expand_tilde(dir, &mut expanded) {
+        Some(end) if expanded.push(&dir[end..]) => expanded.as_slice(),
+        Some(_) => return prefix.len(),
+        None => dir,
+    };
+    
+    for_each_entry(dir, prefix, |name, is_dir| candidates.add(name, is_dir));
+    prefix.len()
+}
+
+/// Builtins, functions, aliases and the programs in the `PATH`
+/// directories whose names start with `prefix`.
+fn complete_command(prefix: &[u8], candidates: &mut Candidates) {
+    for &name in BUILTIN_NAMES {
+        if name.starts_with(prefix) {
+            candidates.add(name, false);
+        }
+    }
+    FUNCTIONS.for_each(|name, _| {
+        if name.starts_with(prefix) {
+            candidates.add(name, false);
+        }
+    });
+    ALIASES.for_each(|name, _| {
+        if name.starts_with(prefix) {
+            candidates.add(name, false);
+        }
+    });
+    
+    let mut path = [0u8; PATH_SIZE];
+    let len = ENV_STORAGE.get(b"PATH", &mut path).unwrap_or(0);
+    let dirs = if len > 0 { &path[..len] } else { DEFAULT_PATH };
+    
+    let mut program = [0u8; PATH_SIZE];
+    for dir in dirs.split(|&b| b == b':') {
+        // An empty entry is the current directory
+        let dir = if dir.is_empty() { b"." } else { dir };
+        let slash: &[u8] = if dir.ends_with(b"/") { b"" } else { b"/" };
+        let mut with_slash = [0u8; PATH_SIZE];
+        let dir = match c_path(&[dir, slash], &mut with_slash) {
+            Some(dir) => &dir[..dir.len() - 1],
+            None => continue,
+        };
+        
+        for_each_entry(dir, prefix, |name, is_dir| {
+            if !is_dir && c_path(&[dir, name], &mut program).is_some_and(|program| access(program, X_OK) == 0) {
+                candidates.add(name, false);
+            }
+        });
+    }
+}
+
+/// Names of variables that start with `prefix`.
+fn complete_variable(prefix: &[u8], candidates: &mut Candidates) {
+    ENV_STORAGE.for_each(|name, value, _| {
+        if value.is_some() && name.starts_with(prefix) {
+            candidates.add(name, false);
+        }
+    });
+}
+
+/// The `$` expansion at the end of `word` if a variable name is being
+/// typed there: whether it is `${`, and the part of the name typed.
+fn variable_prefix(word: &[u8]) -> Option<(bool, &[u8])> {
+    let dollar = word.iter().rposition(|&b| b == b'$')?;
+    let (braced, name) = match &word[dollar + 1..] {
+        [b'{', name @ ..] => (true, name),
+        name => (false, name),
+    };
+    if !name.iter().all(|&b| b.is_ascii_alphanumeric() || b == b'_') {
+        return None;
+    }
+    Some((braced, name))
+}
+
+impl LineEditor {
+    /// Tab completes the word before the cursor: the first word of a
+    /// command against builtins, functions, aliases and programs on the
+    /// `PATH`, later words against filenames, and `$` words against
+    /// variable names. The start all the matches share is filled in;
+    /// when that adds nothing and there are several, they are listed.
+    pub(super) fn complete(&mut self, prompt: &[u8]) {
+        let line = self.line.as_slice();
+        let word = match Word::before(line, self.cursor) {
+            Some(word) => word,
+            None => {
+                write(STDOUT, b"\x07");
+                return;
+            }
+        };
+        
+        let mut candidates = Candidates::new();
+        let text = word.text();
+        // Whether a `${` name is being completed, and how much of the
+        // matching names is typed already
+        let (braced, typed) = if let Some((braced, name)) = variable_prefix(text).filter(|_| word.quote != Some(b'\'')) {
+            complete_variable(name, &mut candidates);
+            (braced, name.len())
+        } else if is_command_position(line, word.start) && !text.contains(&b'/') {
+            complete_command(text, &mut candidates);
+            (false, text.len())
+        } else {
+            (false, complete_file(text, &mut candidates))
+        };
+        
+        let common = candidates.common_len();
+        if candidates.count == 0 || (common == typed && candidates.count > 1) {
+            if candidates.count > 1 {
+                self.list(prompt, &candidates);
+            } else {
+                write(STDOUT, b"\x07");
+            }
+            return;
+        }
+        
+        let name = candidates.get(0);
+        let mut insert = [0u8; PATH_SIZE * 2];
+        let mut len = 0;
+        let mut push = |b: u8| {
+            if len < insert.len() {
+                insert[len] = b;
+                len += 1;
+            }
+        };
+        for &b in &name[typed..common] {
+            if word.quote.is_none() && needs_escape(b) {
+                push(b'\\');
+            }
+            push(b);
+        }
+        
+        // A single match ends the word, unless it is a directory to go on in
+        if candidates.count == 1 {
+            if braced {
+                push(b'}');
+            } else if candidates.is_dir(0) {
+                push(b'/');
+            } else {
+                if let Some(quote) = word.quote {
+                    push(quote);
+                }
+                push(b' ');
+            }
+        }
+        
+        if !self.line.insert(self.cursor, &insert[..len]) {
+            write(STDOUT, b"\x07");
+            return;
+        }
+        self.cursor += len;
+        self.refresh(prompt);
+    }
+    
+    /// Lists `candidates` in columns below the line, like `ls`, and draws
+    /// the prompt and line again under them.
+    fn list(&mut self, prompt: &[u8], candidates: &Candidates) {
+        let cursor = self.cursor;
+        if !self.move_to_end(prompt) {
+            write(STDOUT, b"\n");
+        }
+        
+        let width = (0..candidates.count)
+            .map(|i| text_width(candidates.get(i)) + candidates.is_dir(i) as usize)
+            .max()
+            .unwrap_or(0) + 2;
+        let per_row = ((columns() + 2) / width).max(1);
+        let rows = candidates.count.div_ceil(per_row);
+        
+        let mut screen = Screen::new();
+        for row in 0..rows {
+            let mut column = 0;
+            let mut i = row;
+            while i < candidates.count {
+                for _ in 0..column {
+                    screen.push(b" ");
+                }
+                let name = candidates.get(i);
+                screen.push_text(name);
+                column = width - text_width(name);
+                if candidates.is_dir(i) {
+                    screen.push(b"/");
+                    column -= 1;
+                }
+                i += rows;
+            }
+            screen.push(b"\n");
+        }
+        // Only the last row of the prompt is drawn by `refresh`
+        screen.push_prompt(&prompt[..prompt.len() - last_line(prompt).len()]);
+        screen.flush();
+        self.cursor = cursor;
+        self.rows = 1;
+        self.cursor_row = 0;
+        self.refresh(prompt);
+    }
+}
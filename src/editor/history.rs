@@ -0,0 +1,195 @@
+use super::keys::{read_key, Key};
+use super::LineEditor;
+use crate::syscalls::{write, STDOUT};
+use crate::shell::storage::{HISTORY, HISTORY_LINE_SIZE};
+
+/// Longest text an incremental search looks for.
+const SEARCH_SIZE: usize = 256;
+
+/// The last blank-separated word of `command`.
+fn last_word(command: &[u8]) -> &[u8] {
+    let is_blank = |b: &u8| matches!(b, b' ' | b'\t' | b'\n');
+    let end = command.iter().rposition(|b| !is_blank(b)).map_or(0, |i| i + 1);
+    let start = command[..end].iter().rposition(is_blank).map_or(0, |i| i + 1);
+    &command[start..end]
+}
+
+impl LineEditor {
+    /// Replaces the line with history entry `number`, or with the line
+    /// being typed if `number` is past the newest entry. Leaving that
+    /// line keeps it, so coming back with Down brings it back.
+    pub(super) fn recall(&mut self, prompt: &[u8], number: usize) {
+        let next = HISTORY.next_number();
+        if !self.keep_typed() {
+            write(STDOUT, b"\x07");
+            return;
+        }
+        
+        let recalled = if number >= next {
+            self.line.clear();
+            self.line.push(self.typed.as_slice())
+        } else {
+            self.replace_line(|out| HISTORY.get(number, out))
+        };
+        if !recalled {
+            write(STDOUT, b"\x07");
+            return;
+        }
+        
+        self.history_number = number;
+        self.cursor = self.line.len();
+        self.refresh(prompt);
+    }
+    
+    /// Keeps the line being typed before it is replaced by a history entry.
+    /// Returns `false` if there is no room for it.
+    fn keep_typed(&mut self) -> bool {
+        if self.history_number < HISTORY.next_number() {
+            return true;
+        }
+        self.typed.clear();
+        self.typed.push(self.line.as_slice())
+    }
+    
+    /// Replaces the line with the history entry `fill` copies into the
+    /// buffer it is given, unless there is no such entry.
+    fn replace_line<F>(&mut self, fill: F) -> bool where F: FnOnce(&mut [u8]) -> Option<usize> {
+        let old_len = self.line.len();
+        if !self.line.reserve(HISTORY_LINE_SIZE) {
+            return false;
+        }
+        match fill(self.line.spare()) {
+            Some(len) => {
+                self.line.extend_by(len);
+                self.line.remove(0..old_len);
+                true
+            }
+            None => false,
+        }
+    }
+    
+    /// Alt-. inserts the last word of the previous command. Pressed again
+    /// right away, it replaces that word with the last word of the command
+    /// before, given as `previous`: the entry used last time and where its
+    /// word was inserted.
+    pub(super) fn insert_last_word(&mut self, prompt: &[u8], previous: Option<(usize, usize)>) {
+        let (number, start) = match previous {
+            Some((number, start)) => (number.saturating_sub(1), start),
+            None => (HISTORY.next_number() - 1, self.cursor),
+        };
+        
+        let mut command = [0u8; HISTORY_LINE_SIZE];
+        let len = match HISTORY.get(number, &mut command) {
+            Some(len) => len,
+            None => {
+                self.last_word = previous;
+                write(STDOUT, b"\x07");
+                return;
+            }
+        };
+        let word = last_word(&command[..len]);
+        
+        self.line.remove(start..self.cursor);
+        self.cursor = start;
+        if self.line.insert(start, word) {
+            self.cursor += word.len();
+        }
+        self.last_word = Some((number, start));
+        self.refresh(prompt);
+    }
+    
+    /// Moves to the entry containing `query` nearest to `from`, with the
+    /// cursor on the match. Returns `false` if there is none.
+    fn find(&mut self, query: &[u8], from: usize, backward: bool) -> bool {
+        let mut number = 0;
+        let found = self.replace_line(|out| {
+            let (found, len) = HISTORY.search(query, from, backward, out)?;
+            number = found;
+            Some(len)
+        });
+        if !found {
+            return false;
+        }
+        
+        self.history_number = number;
+        let line = self.line.as_slice();
+        self.cursor = line.windows(query.len().max(1)).position(|window| window == query).unwrap_or(0);
+        true
+    }
+    
+    /// Shows the search prompt, `(reverse-i-search)`query': `, with the
+    /// line found so far.
+    fn draw_search(&mut self, query: &[u8], backward: bool, failed: bool) {
+        let mut prompt = [0u8; SEARCH_SIZE + 32];
+        let mut len = 0;
+        let parts: [&[u8]; 4] = [
+            if failed { b"(failed " } else { b"(" },
+            if backward { b"reverse-i-search)`" } else { b"i-search)`" },
+            query,
+            b"': ",
+        ];
+        for part in parts {
+            prompt[len..len + part.len()].copy_from_slice(part);
+            len += part.len();
+        }
+        self.refresh(&prompt[..len]);
+    }
+    
+    /// Ctrl-R and Ctrl-S: searches the history as the text to look for is
+    /// typed. Ctrl-R and Ctrl-S move on to the next older or newer match;
+    /// Ctrl-G gives up and puts the line back. Any other key ends the
+    /// search on the line found and is returned to be handled as usual.
+    pub(super) fn search(&mut self, prompt: &[u8], mut backward: bool) -> Option<Key> {
+        let mut query = [0u8; SEARCH_SIZE];
+        let mut query_len = 0;
+        let start = self.history_number;
+        let mut failed = false;
+        
+        self.searched.clear();
+        if !self.keep_typed() || !self.searched.push(self.line.as_slice()) {
+            write(STDOUT, b"\x07");
+            return Some(Key::Unknown);
+        }
+        let (searched_cursor, searched_number) = (self.cursor, self.history_number);
+        
+        loop {
+            self.draw_search(&query[..query_len], backward, failed);
+            let key = read_key()?;
+            
+            match key {
+                Key::Ctrl(b'r') | Key::Ctrl(b's') => {
+                    backward = matches!(key, Key::Ctrl(b'r'));
+                    if query_len > 0 {
+                        let from = if backward { self.history_number.checked_sub(1) } else { Some(self.history_number + 1) };
+                        failed = !from.is_some_and(|from| self.find(&query[..query_len], from, backward));
+                    }
+                }
+                Key::Text(bytes, count) if query_len + count <= query.len() => {
+                    query[query_len..query_len + count].copy_from_slice(&bytes[..count]);
+                    query_len += count;
+                    failed = !self.find(&query[..query_len], self.history_number, backward);
+                }
+                Key::Backspace if query_len > 0 => {
+                    query_len -= 1;
+                    while query_len > 0 && (0x80..=0xbf).contains(&query[query_len]) {
+                        query_len -= 1;
+                    }
+                    failed = query_len > 0 && !self.find(&query[..query_len], start, backward);
+                }
+                Key::Ctrl(b'g') | Key::Ctrl(b'c') => {
+                    self.line.clear();
+                    self.line.push(self.searched.as_slice());
+                    self.cursor = searched_cursor;
+                    self.history_number = searched_number;
+                    self.refresh(prompt);
+                    return Some(if matches!(key, Key::Ctrl(b'c')) { key } else { Key::Unknown });
+                }
+                Key::Text(..) | Key::Backspace => {}
+                _ => {
+                    self.refresh(prompt);
+                    return Some(key);
+                }
+            }
+        }
+    }
+}
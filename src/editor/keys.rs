@@ -0,0 +1,111 @@
+use crate::syscalls::{read, STDIN};
+
+/// A key press, decoded from the bytes the terminal sends for it.
+pub enum Key {
+    /// A printable character, `len` bytes of UTF-8
+    Text([u8; 4], usize),
+    /// A control character, by its letter: `Ctrl(b'a')` for Ctrl-A
+    Ctrl(u8),
+    /// A key pressed with Alt or after Escape
+    Alt(u8),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    WordLeft,
+    WordRight,
+    /// A sequence the editor has no use for
+    Unknown,
+}
+
+fn read_byte() -> Option<u8> {
+    let mut byte = [0u8; 1];
+    if read(STDIN, &mut byte) <= 0 {
+        return None;
+    }
+    Some(byte[0])
+}
+
+/// Decodes the rest of a CSI sequence, `ESC [ params final`.
+fn csi_sequence() -> Option<Key> {
+    let mut params = [0u8; 8];
+    let mut len = 0;
+    let last = loop {
+        let b = read_byte()?;
+        if !(0x30..=0x3f).contains(&b) {
+            break b;
+        }
+        if len < params.len() {
+            params[len] = b;
+            len += 1;
+        }
+    };
+    
+    let key = match (&params[..len], last) {
+        (_, b'A') => Key::Up,
+        (_, b'B') => Key::Down,
+        // Ctrl and Alt with the arrows move by words
+        (b"1;5" | b"1;3", b'C') => Key::WordRight,
+        (b"1;5" | b"1;3", b'D') => Key::WordLeft,
+        (_, b'C') => Key::Right,
+        (_, b'D') => Key::Left,
+        (_, b'H') | (b"1" | b"7", b'~') => Key::Home,
+        (_, b'F') | (b"4" | b"8", b'~') => Key::End,
+        (b"3", b'~') => Key::Delete,
+        _ => Key::Unknown,
+    };
+    Some(key)
+}
+
+/// Reads the continuation bytes of a UTF-8 character starting with
+/// `lead`. Malformed characters are dropped.
+fn utf8_char(lead: u8) -> Option<Key> {
+    let len = match lead {
+        0xc2..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf4 => 4,
+        _ => return Some(Key::Unknown),
+    };
+    let mut bytes = [lead, 0, 0, 0];
+    for byte in &mut bytes[1..len] {
+        *byte = read_byte()?;
+        if !(0x80..=0xbf).contains(byte) {
+            return Some(Key::Unknown);
+        }
+    }
+    Some(Key::Text(bytes, len))
+}
+
+/// Waits for the next key. `None` at the end of the input or when the
+/// read is interrupted by a signal.
+pub fn read_key() -> Option<Key> {
+    let key = match read_byte()? {
+        b'\r' | b'\n' => Key::Enter,
+        b'\t' => Key::Tab,
+        0x7f | 0x08 => Key::Backspace,
+        0x1b => match read_byte()? {
+            b'[' => return csi_sequence(),
+            b'O' => match read_byte()? {
+                b'A' => Key::Up,
+                b'B' => Key::Down,
+                b'C' => Key::Right,
+                b'D' => Key::Left,
+                b'H' => Key::Home,
+                b'F' => Key::End,
+                _ => Key::Unknown,
+            },
+            b => Key::Alt(b.to_ascii_lowercase()),
+        },
+        b @ 0x01..=0x1a => Key::Ctrl(b - 1 + b'a'),
+        b @ 0x20..=0x7e => Key::Text([b, 0, 0, 0], 1),
+        b @ 0x80..=0xff => return utf8_char(b),
+        _ => Key::Unknown,
+    };
+    Some(key)
+}
@@ -0,0 +1,465 @@
+mod completion;
+mod history;
+mod keys;
+mod width;
+
+use core::ops::Range;
+use keys::{read_key, Key};
+use width::{advance, char_width, decode, text_width};
+use crate::io::LineBuffer;
+use crate::syscalls::{ioctl, read, write, terminal_columns, Termios, STDIN, STDOUT, TCGETS, TCSETS, ISIG, ICANON, ECHO, IXON};
+use crate::shell::storage::HISTORY;
+use crate::shell::prompt::{HIDDEN_START, HIDDEN_END};
+use crate::io::format_number;
+
+/// Columns assumed when the terminal doesn't report its size.
+const DEFAULT_COLUMNS: usize = 80;
+/// Bytes read at once when standard input is not a terminal.
+const PLAIN_READ_SIZE: usize = 4096;
+
+fn columns() -> usize {
+    match terminal_columns(STDOUT) {
+        cols if cols > 0 => cols as usize,
+        _ => DEFAULT_COLUMNS,
+    }
+}
+
+/// The part of `prompt` on the row where the line starts.
+fn last_line(prompt: &[u8]) -> &[u8] {
+    match prompt.iter().rposition(|&b| b == b'\n') {
+        Some(newline) => &prompt[newline + 1..],
+        None => prompt,
+    }
+}
+
+/// Words, for the word motions and kills, are runs of letters and digits.
+/// Bytes of non-ASCII characters count as letters.
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b >= 0x80
+}
+
+/// Start of the character before offset `at`. Combining marks go with
+/// the character they are drawn on.
+fn prev_char(line: &[u8], at: usize) -> usize {
+    let mut i = at;
+    while i > 0 {
+        i -= 1;
+        while i > 0 && (0x80..=0xbf).contains(&line[i]) {
+            i -= 1;
+        }
+        if char_width(decode(line, i).0) > 0 {
+            break;
+        }
+    }
+    i
+}
+
+/// End of the character at offset `at`, with its combining marks.
+fn next_char(line: &[u8], at: usize) -> usize {
+    let mut i = at + decode(line, at).1;
+    while i < line.len() {
+        let (c, len) = decode(line, i);
+        if char_width(c) > 0 {
+            break;
+        }
+        i += len;
+    }
+    i
+}
+
+/// Terminal output collected so a redraw is written at once instead of
+/// flickering.
+struct Screen {
+    buf: [u8; 512],
+    len: usize,
+}
+
+impl Screen {
+    fn new() -> Self {
+        Self { buf: [0u8; 512], len: 0 }
+    }
+    
+    fn push(&mut self, s: &[u8]) {
+        if self.len + s.len() > self.buf.len() {
+            self.flush();
+        }
+        if s.len() > self.buf.len() {
+            write(STDOUT, s);
+            return;
+        }
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s);
+        self.len += s.len();
+    }
+    
+    /// Pushes the text of the line. Control characters, such as the
+    /// newlines of a command recalled from the history, are shown as `^J`.
+    fn push_text(&mut self, text: &[u8]) {
+        for part in text.split_inclusive(|&b| b < 0x20 || b == 0x7f) {
+            match part.split_last() {
+                Some((&last, rest)) if last < 0x20 || last == 0x7f => {
+                    self.push(rest);
+                    self.push(&[b'^', last ^ 0x40]);
+                }
+                _ => self.push(part),
+            }
+        }
+    }
+    
+    /// Pushes a prompt, without the marks around its hidden parts.
+    fn push_prompt(&mut self, prompt: &[u8]) {
+        for part in prompt.split(|&b| b == HIDDEN_START || b == HIDDEN_END) {
+            self.push(part);
+        }
+    }
+    
+    /// Writes the control sequence `ESC [ count cmd`.
+    fn csi(&mut self, count: usize, cmd: u8) {
+        let mut digits = [0u8; 20];
+        let len = format_number(count as i64, &mut digits);
+        self.push(b"\x1b[");
+        self.push(&digits[..len]);
+        self.push(&[cmd]);
+    }
+    
+    fn flush(&mut self) {
+        write(STDOUT, &self.buf[..self.len]);
+        self.len = 0;
+    }
+}
+
+/// Reads lines typed at the local console, with the cursor movements and
+/// kill and yank commands of Emacs-style line editing, and the commands
+/// that recall and search the history. The line may grow past the width
+/// of the terminal, in which case it wraps over several rows.
+pub struct LineEditor {
+    line: LineBuffer,
+    /// Byte offset of the cursor in `line`
+    cursor: usize,
+    /// Text removed by the last kill commands, which Ctrl-Y inserts
+    killed: LineBuffer,
+    /// Whether the previous key killed text, so the next kill adds to it
+    killing: bool,
+    /// Rows taken by the prompt and line when last drawn
+    rows: usize,
+    /// Row of the cursor, counted from the row where the line starts
+    cursor_row: usize,
+    /// Number of the history entry shown, or the number the next entry
+    /// will get while the line being typed is
+    history_number: usize,
+    /// The line being typed, kept while history entries are shown
+    typed: LineBuffer,
+    /// The line as it was when the search started, put back by Ctrl-G
+    searched: LineBuffer,
+    /// After Alt-., the entry whose last word it inserted and where
+    last_word: Option<(usize, usize)>,
+}
+
+impl LineEditor {
+    pub const fn new() -> Self {
+        Self {
+            line: LineBuffer::new(),
+            cursor: 0,
+            killed: LineBuffer::new(),
+            killing: false,
+            rows: 1,
+            cursor_row: 0,
+            history_number: 0,
+            typed: LineBuffer::new(),
+            searched: LineBuffer::new(),
+            last_word: None,
+        }
+    }
+    
+    /// Shows `prompt` and reads a line, which is returned with its newline.
+    /// `None` if Ctrl-C cancels the line, at the end of the input, or when
+    /// a signal such as SIGTERM interrupts the read. Without a terminal,
+    /// whatever one read returns is the line.
+    pub fn read_line(&mut self, prompt: &[u8]) -> Option<&[u8]> {
+        let mut screen = Screen::new();
+        screen.push_prompt(prompt);
+        screen.flush();
+        self.line.clear();
+        self.cursor = 0;
+        self.killing = false;
+        self.rows = 1;
+        self.cursor_row = 0;
+        self.history_number = HISTORY.next_number();
+        self.last_word = None;
+        
+        let mut old_term = Termios {
+            c_iflag: 0,
+            c_oflag: 0,
+            c_cflag: 0,
+            c_lflag: 0,
+            c_line: 0,
+            c_cc: [0u8; 32],
+            _padding: [0u8; 3],
+            c_ispeed: 0,
+            c_ospeed: 0,
+        };
+        
+        let old_ptr = &mut old_term as *mut Termios as u64;
+        if ioctl(STDIN, TCGETS, old_ptr) < 0 {
+            return self.read_plain();
+        }
+        
+        // Without ISIG, Ctrl-C arrives as a byte and cancels the line;
+        // without IXON, Ctrl-S searches instead of stopping the output
+        let mut raw_term = old_term;
+        raw_term.c_lflag &= !(ICANON | ECHO | ISIG);
+        raw_term.c_iflag &= !IXON;
+        
+        let raw_ptr = &mut raw_term as *mut Termios as u64;
+        ioctl(STDIN, TCSETS, raw_ptr);
+        let entered = self.edit(prompt);
+        ioctl(STDIN, TCSETS, old_ptr);
+        
+        if entered { Some(self.line.as_slice()) } else { None }
+    }
+    
+    fn read_plain(&mut self) -> Option<&[u8]> {
+        if !self.line.reserve(PLAIN_READ_SIZE) {
+            return None;
+        }
+        let n = read(STDIN, self.line.spare());
+        if n <= 0 {
+            return None;
+        }
+        self.line.extend_by(n as usize);
+        Some(self.line.as_slice())
+    }
+    
+    /// Handles keys until Enter, which returns `true`, or until the line
+    /// is abandoned.
+    fn edit(&mut self, prompt: &[u8]) -> bool {
+        // The key that ended a search
+        let mut next_key = None;
+        
+        loop {
+            // EOF or a signal; never run a half-typed line
+            let key = match next_key.take().or_else(read_key) {
+                Some(key) => key,
+                None => return false,
+            };
+            let killing = core::mem::replace(&mut self.killing, false);
+            let last_word = self.last_word.take();
+            let line = self.line.as_slice();
+            let len = line.len();
+            
+            match key {
+                Key::Enter => {
+                    if !self.move_to_end(prompt) {
+                        write(STDOUT, b"\n");
+                    }
+                    return self.line.push(b"\n");
+                }
+                Key::Ctrl(b'c') => {
+                    self.move_to_end(prompt);
+                    write(STDOUT, b"^C\n");
+                    return false;
+                }
+                Key::Text(bytes, count) => self.insert(prompt, &bytes[..count]),
+                Key::Tab => self.complete(prompt),
+                
+                Key::Left | Key::Ctrl(b'b') => self.move_to(prompt, prev_char(line, self.cursor)),
+                Key::Right | Key::Ctrl(b'f') if self.cursor < len => {
+                    self.move_to(prompt, next_char(line, self.cursor));
+                }
+                Key::Home | Key::Ctrl(b'a') => self.move_to(prompt, 0),
+                Key::End | Key::Ctrl(b'e') => self.move_to(prompt, len),
+                Key::WordLeft | Key::Alt(b'b') => self.move_to(prompt, self.word_start()),
+                Key::WordRight | Key::Alt(b'f') => self.move_to(prompt, self.word_end()),
+                
+                Key::Backspace if self.cursor > 0 => {
+                    self.delete(prompt, prev_char(line, self.cursor)..self.cursor);
+                }
+                Key::Delete | Key::Ctrl(b'd') if self.cursor < len => {
+                    self.delete(prompt, self.cursor..next_char(line, self.cursor));
+                }
+                
+                Key::Ctrl(b'k') => self.kill(prompt, self.cursor..len, killing),
+                Key::Ctrl(b'u') => self.kill(prompt, 0..self.cursor, killing),
+                Key::Ctrl(b'w') => self.kill(prompt, self.blank_word_start()..self.cursor, killing),
+                Key::Alt(0x7f) => self.kill(prompt, self.word_start()..self.cursor, killing),
+                Key::Alt(b'd') => self.kill(prompt, self.cursor..self.word_end(), killing),
+                Key::Ctrl(b'y') if self.line.insert(self.cursor, self.killed.as_slice()) => {
+                    self.cursor += self.killed.len();
+                    self.refresh(prompt);
+                }
+                
+                Key::Up | Key::Ctrl(b'p') if self.history_number > 0 => {
+                    self.recall(prompt, self.history_number - 1);
+                }
+                Key::Down | Key::Ctrl(b'n') if self.history_number < HISTORY.next_number() => {
+                    self.recall(prompt, self.history_number + 1);
+                }
+                Key::Ctrl(b'r') | Key::Ctrl(b's') => {
+                    match self.search(prompt, matches!(key, Key::Ctrl(b'r'))) {
+                        Some(key) => next_key = Some(key),
+                        None => return false,
+                    }
+                }
+                Key::Alt(b'.') => self.insert_last_word(prompt, last_word),
+                
+                Key::Ctrl(b'l') => {
+                    write(STDOUT, b"\x1b[H\x1b[2J");
+                    self.rows = 1;
+                    self.cursor_row = 0;
+                    self.refresh(prompt);
+                }
+                _ => {}
+            }
+        }
+    }
+    
+    fn move_to(&mut self, prompt: &[u8], cursor: usize) {
+        if cursor != self.cursor {
+            self.cursor = cursor;
+            self.refresh(prompt);
+        }
+    }
+    
+    /// Puts the cursor after the line. Returns `true` if that leaves it at
+    /// the start of an empty row, because the line filled the last one.
+    fn move_to_end(&mut self, prompt: &[u8]) -> bool {
+        let cols = columns();
+        let end = advance(text_width(last_line(prompt)), self.line.as_slice(), cols);
+        if self.cursor != self.line.len() || end >= cols {
+            self.cursor = self.line.len();
+            self.refresh(prompt);
+        }
+        end > 0 && end.is_multiple_of(cols)
+    }
+    
+    fn insert(&mut self, prompt: &[u8], text: &[u8]) {
+        if !self.line.insert(self.cursor, text) {
+            write(STDOUT, b"\x07");
+            return;
+        }
+        self.cursor += text.len();
+        
+        // Typing at the end of the line only needs the character drawn,
+        // unless it makes the line wrap
+        if self.cursor == self.line.len() {
+            let cols = columns();
+            let end = advance(text_width(last_line(prompt)), self.line.as_slice(), cols);
+            if !end.is_multiple_of(cols) && end / cols == self.rows - 1 {
+                write(STDOUT, text);
+                return;
+            }
+        }
+        self.refresh(prompt);
+    }
+    
+    fn delete(&mut self, prompt: &[u8], range: Range<usize>) {
+        self.cursor = range.start;
+        self.line.remove(range);
+        self.refresh(prompt);
+    }
+    
+    /// Removes `range` from the line into the kill buffer. Consecutive
+    /// kills add to what the previous ones removed.
+    fn kill(&mut self, prompt: &[u8], range: Range<usize>, appending: bool) {
+        self.killing = true;
+        if range.is_empty() {
+            return;
+        }
+        if !appending {
+            self.killed.clear();
+        }
+        
+        let text = &self.line.as_slice()[range.clone()];
+        let added = if range.start < self.cursor {
+            self.killed.insert(0, text)
+        } else {
+            self.killed.push(text)
+        };
+        if !added {
+            write(STDOUT, b"\x07");
+            return;
+        }
+        self.delete(prompt, range);
+    }
+    
+    /// Start of the word before the cursor.
+    fn word_start(&self) -> usize {
+        let line = self.line.as_slice();
+        let mut i = self.cursor;
+        while i > 0 && !is_word_byte(line[i - 1]) {
+            i -= 1;
+        }
+        while i > 0 && is_word_byte(line[i - 1]) {
+            i -= 1;
+        }
+        i
+    }
+    
+    /// End of the word after the cursor.
+    fn word_end(&self) -> usize {
+        let line = self.line.as_slice();
+        let mut i = self.cursor;
+        while i < line.len() && !is_word_byte(line[i]) {
+            i += 1;
+        }
+        while i < line.len() && is_word_byte(line[i]) {
+            i += 1;
+        }
+        i
+    }
+    
+    /// Start of the blank-separated word before the cursor, which Ctrl-W
+    /// removes.
+    fn blank_word_start(&self) -> usize {
+        let line = self.line.as_slice();
+        let mut i = self.cursor;
+        while i > 0 && matches!(line[i - 1], b' ' | b'\t') {
+            i -= 1;
+        }
+        while i > 0 && !matches!(line[i - 1], b' ' | b'\t') {
+            i -= 1;
+        }
+        i
+    }
+    
+    /// Redraws the prompt and the line and puts the cursor back. The rows
+    /// drawn last time are cleared first, since the line may have taken
+    /// more of them.
+    fn refresh(&mut self, prompt: &[u8]) {
+        let prompt = last_line(prompt);
+        let line = self.line.as_slice();
+        let cols = columns();
+        let mut screen = Screen::new();
+        
+        if self.rows > self.cursor_row + 1 {
+            screen.csi(self.rows - self.cursor_row - 1, b'B');
+        }
+        for _ in 1..self.rows {
+            screen.push(b"\r\x1b[K\x1b[A");
+        }
+        screen.push(b"\r\x1b[K");
+        screen.push_prompt(prompt);
+        screen.push_text(line);
+        
+        // At the end of a row the terminal only wraps once the next
+        // character comes; wrap now so the cursor moves count from there
+        let prompt_width = text_width(prompt);
+        let end = advance(prompt_width, line, cols);
+        if end > 0 && end.is_multiple_of(cols) {
+            screen.push(b"\r\n");
+        }
+        
+        let pos = advance(prompt_width, &line[..self.cursor], cols);
+        let (end_row, row) = (end / cols, pos / cols);
+        if end_row > row {
+            screen.csi(end_row - row, b'A');
+        }
+        screen.push(b"\r");
+        if !pos.is_multiple_of(cols) {
+            screen.csi(pos % cols, b'C');
+        }
+        screen.flush();
+        
+        self.rows = end_row + 1;
+        self.cursor_row = row;
+    }
+}
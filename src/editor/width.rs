@@ -0,0 +1,103 @@
+use crate::shell::prompt::{HIDDEN_START, HIDDEN_END};
+
+/// Code points that take no column, combining with the character before
+/// them.
+const ZERO_WIDTH: &[(u32, u32)] = &[
+    (0x0300, 0x036f), (0x0483, 0x0489), (0x0591, 0x05bd), (0x0610, 0x061a),
+    (0x064b, 0x065f), (0x0e31, 0x0e31), (0x0e34, 0x0e3a), (0x1ab0, 0x1aff),
+    (0x1dc0, 0x1dff), (0x200b, 0x200f), (0x20d0, 0x20ff), (0xfe00, 0xfe0f),
+    (0xfe20, 0xfe2f),
+];
+
+/// Code points that take two columns: CJK, Hangul, full-width forms and
+/// emoji.
+const DOUBLE_WIDTH: &[(u32, u32)] = &[
+    (0x1100, 0x115f), (0x2e80, 0x303e), (0x3041, 0x33ff), (0x3400, 0x4dbf),
+    (0x4e00, 0x9fff), (0xa000, 0xa4cf), (0xac00, 0xd7a3), (0xf900, 0xfaff),
+    (0xfe30, 0xfe4f), (0xff00, 0xff60), (0xffe0, 0xffe6), (0x1f300, 0x1f64f),
+    (0x1f900, 0x1f9ff), (0x20000, 0x2fffd), (0x30000, 0x3fffd),
+];
+
+fn in_table(table: &[(u32, u32)], c: u32) -> bool {
+    table.iter().any(|&(first, last)| (first..=last).contains(&c))
+}
+
+/// The code point starting at `text[at]` and its length in bytes. A byte
+/// that doesn't start a valid character stands for itself.
+pub fn decode(text: &[u8], at: usize) -> (u32, usize) {
+    let lead = text[at];
+    let (len, bits) = match lead {
+        0x00..=0x7f => return (lead as u32, 1),
+        0xc2..=0xdf => (2, lead & 0x1f),
+        0xe0..=0xef => (3, lead & 0x0f),
+        0xf0..=0xf4 => (4, lead & 0x07),
+        _ => return (lead as u32, 1),
+    };
+    if at + len > text.len() || !text[at + 1..at + len].iter().all(|b| (0x80..=0xbf).contains(b)) {
+        return (lead as u32, 1);
+    }
+    let c = text[at + 1..at + len].iter().fold(bits as u32, |c, &b| c << 6 | (b & 0x3f) as u32);
+    (c, len)
+}
+
+/// Columns the terminal uses to show code point `c`.
+pub fn char_width(c: u32) -> usize {
+    // Control characters are shown as `^X`
+    if c < 0x20 || c == 0x7f {
+        2
+    } else if in_table(ZERO_WIDTH, c) {
+        0
+    } else if in_table(DOUBLE_WIDTH, c) {
+        2
+    } else {
+        1
+    }
+}
+
+/// Columns the terminal uses to show `text`. Escape sequences such as
+/// colors take none, nor does what a prompt marks as hidden.
+pub fn text_width(text: &[u8]) -> usize {
+    let mut width = 0;
+    let mut i = 0;
+    while i < text.len() {
+        if text[i] == HIDDEN_START {
+            i += text[i..].iter().position(|&b| b == HIDDEN_END).map_or(text.len() - i, |end| end + 1);
+            continue;
+        }
+        if text[i] == HIDDEN_END {
+            i += 1;
+            continue;
+        }
+        if text[i] == 0x1b && text.get(i + 1) == Some(&b'[') {
+            i += 2;
+            while i < text.len() && !(0x40..=0x7e).contains(&text[i]) {
+                i += 1;
+            }
+            i += 1;
+            continue;
+        }
+        let (c, len) = decode(text, i);
+        width += char_width(c);
+        i += len;
+    }
+    width
+}
+
+/// Position of the cursor after drawing `text` from position `start`, on
+/// a terminal `cols` wide. Positions count cells from the start of the
+/// first row, so the row is `position / cols`. A double-width character
+/// that doesn't fit at the end of a row goes to the next one.
+pub fn advance(start: usize, text: &[u8], cols: usize) -> usize {
+    let mut pos = start;
+    let mut i = 0;
+    while i < text.len() {
+        let (c, len) = decode(text, i);
+        let width = char_width(c);
+        if width == 2 && pos % cols == cols - 1 {
+            pos += 1;
+        }
+        pos += width;
+        i += len;
+    }
+    pos
+}
@@ -1,6 +1,27 @@
-use core::cell::UnsafeCell;
+mod buffer;
+
+pub use buffer::LineBuffer;
+
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use crate::syscalls::{read, write, STDIN, STDOUT};
+use crate::syscalls::{write, STDIN, STDOUT, STDERR};
+
+/// File descriptors a command reads from and writes to.
+#[derive(Clone, Copy)]
+pub struct Stdio {
+    pub stdin: i32,
+    pub stdout: i32,
+    pub stderr: i32,
+}
+
+impl Stdio {
+    pub const fn standard() -> Self {
+        Self {
+            stdin: STDIN,
+            stdout: STDOUT,
+            stderr: STDERR,
+        }
+    }
+}
 
 // Output capture for broadcasting to WebSocket
 static OUTPUT_CAPTURE_ENABLED: AtomicBool = AtomicBool::new(false);
@@ -52,12 +73,17 @@ pub fn print(s: &[u8]) {
 }
 
 pub fn print_number(n: i64) {
+    write_number(STDOUT, n);
+}
+
+pub fn format_number(n: i64, out: &mut [u8; 20]) -> usize {
     if n == 0 {
-        print(b"0");
-        return;
+        out[0] = b'0';
+        return 1;
     }
     
-    let mut num = n;
+    let negative = n < 0;
+    let mut num = n.unsigned_abs();
     let mut digits = [0u8; 20];
     let mut count = 0;
     
@@ -67,9 +93,24 @@ pub fn print_number(n: i64) {
         count += 1;
     }
     
+    let mut len = 0;
+    if negative {
+        out[0] = b'-';
+        len = 1;
+    }
     for i in (0..count).rev() {
-        write(STDOUT, &[digits[i]]);
+        if len < out.len() {
+            out[len] = digits[i];
+            len += 1;
+        }
     }
+    len
+}
+
+pub fn write_number(fd: i32, n: i64) {
+    let mut buf = [0u8; 20];
+    let len = format_number(n, &mut buf);
+    write(fd, &buf[..len]);
 }
 
 pub struct CStr {
@@ -100,137 +141,3 @@ impl CStr {
         unsafe { core::slice::from_raw_parts(self.ptr, len) }
     }
 }
-
-pub struct StaticBuffer {
-    data: UnsafeCell<[u8; 128]>,
-    locked: AtomicBool,
-}
-
-unsafe impl Sync for StaticBuffer {}
-
-impl StaticBuffer {
-    pub const fn new() -> Self {
-        Self {
-            data: UnsafeCell::new([0u8; 128]),
-            locked: AtomicBool::new(false),
-        }
-    }
-    
-    pub fn with_mut<F, R>(&self, f: F) -> R 
-    where
-        F: FnOnce(&mut [u8]) -> R,
-    {
-        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
-            core::hint::spin_loop();
-        }
-        
-        let result = unsafe {
-            let buf = &mut *self.data.get();
-            f(buf)
-        };
-        
-        self.locked.store(false, Ordering::Release);
-        result
-    }
-}
-
-pub fn read_line(buf: &mut [u8]) -> usize {
-    let n = read(STDIN, buf);
-    if n > 0 { n as usize } else { 0 }
-}
-
-pub fn read_line_with_tab(buf: &mut [u8]) -> usize {
-    use crate::syscalls::ioctl;
-    use crate::syscalls::{Termios, TCGETS, TCSETS, ICANON, ECHO};
-    
-    let mut old_term = Termios {
-        c_iflag: 0,
-        c_oflag: 0,
-        c_cflag: 0,
-        c_lflag: 0,
-        c_line: 0,
-        c_cc: [0u8; 32],
-        _padding: [0u8; 3],
-        c_ispeed: 0,
-        c_ospeed: 0,
-    };
-    
-    let old_ptr = &mut old_term as *mut Termios as u64;
-    if ioctl(STDIN, TCGETS, old_ptr) < 0 {
-        return read_line(buf);
-    }
-    
-    let mut raw_term = old_term;
-    raw_term.c_lflag &= !(ICANON | ECHO);
-    
-    let raw_ptr = &mut raw_term as *mut Termios as u64;
-    ioctl(STDIN, TCSETS, raw_ptr);
-    
-    let mut pos = 0;
-    let mut tmp = [0u8; 1];
-    
-    loop {
-        let n = read(STDIN, &mut tmp);
-        if n <= 0 {
-            break;
-        }
-        
-        let ch = tmp[0];
-        
-        if ch == b'\n' || ch == b'\r' {
-            write(STDOUT, b"\n");
-            buf[pos] = b'\n';
-            pos += 1;
-            break;
-        } else if ch == 9 {
-            if pos == 1 {
-                let ch = buf[0];
-                let completion: Option<&[u8]> = match ch {
-                    b'l' => Some(b"ls "),
-                    b'c' => Some(b"cd "),
-                    b'p' => Some(b"pwd"),
-                    b'e' => Some(b"echo "),
-                    b'a' => Some(b"alias "),
-                    b'h' => Some(b"history"),
-                    b's' => Some(b"serve "),
-                    _ => None,
-                };
-                
-                if let Some(comp) = completion {
-                    write(STDOUT, b"\x08 \x08");
-                    write(STDOUT, comp);
-                    pos = 0;
-                    for &b in comp {
-                        if pos < buf.len() - 1 {
-                            buf[pos] = b;
-                            pos += 1;
-                        }
-                    }
-                } else {
-                    write(STDOUT, b"\x07");
-                }
-            } else {
-                write(STDOUT, b"\x07");
-            }
-        } else if ch == 127 || ch == 8 {
-            if pos > 0 {
-                pos -= 1;
-                write(STDOUT, b"\x08 \x08");
-            }
-        } else if ch == 3 {
-            write(STDOUT, b"^C\n");
-            pos = 0;
-            break;
-        } else if ch >= 32 && ch < 127 {
-            if pos < buf.len() - 1 {
-                buf[pos] = ch;
-                write(STDOUT, &[ch]);
-                pos += 1;
-            }
-        }
-    }
-    
-    ioctl(STDIN, TCSETS, old_ptr);
-    
-    pos
-}
@@ -0,0 +1,131 @@
+use core::ops::Range;
+use crate::syscalls::{sys_mmap, sys_mremap, sys_munmap, PROT_READ, PROT_WRITE, MAP_PRIVATE, MAP_ANONYMOUS, MREMAP_MAYMOVE};
+
+const PAGE_SIZE: usize = 4096;
+
+/// Bytes that grow as needed, such as a line being edited, in memory
+/// mapped from the kernel since there is no allocator.
+pub struct LineBuffer {
+    ptr: *mut u8,
+    len: usize,
+    capacity: usize,
+}
+
+impl LineBuffer {
+    pub const fn new() -> Self {
+        Self {
+            ptr: core::ptr::null_mut(),
+            len: 0,
+            capacity: 0,
+        }
+    }
+    
+    pub fn as_slice(&self) -> &[u8] {
+        if self.capacity == 0 {
+            return &[];
+        }
+        unsafe { core::slice::from_raw_parts(self.ptr, self.len) }
+    }
+    
+    pub fn as_mut_slice(&mut self) -> &mut [u8] {
+        let len = self.len;
+        &mut self.allocation()[..len]
+    }
+    
+    /// All the memory mapped, used or not.
+    fn allocation(&mut self) -> &mut [u8] {
+        if self.capacity == 0 {
+            return &mut [];
+        }
+        unsafe { core::slice::from_raw_parts_mut(self.ptr, self.capacity) }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+    
+    /// Drops the bytes from offset `len` on.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+    
+    /// Makes room for `additional` more bytes. Returns `false` if the
+    /// memory can't be had.
+    pub fn reserve(&mut self, additional: usize) -> bool {
+        let needed = match self.len.checked_add(additional) {
+            Some(needed) => needed,
+            None => return false,
+        };
+        if needed <= self.capacity {
+            return true;
+        }
+        
+        let capacity = needed.max(self.capacity * 2).next_multiple_of(PAGE_SIZE);
+        let ptr = unsafe {
+            if self.capacity == 0 {
+                sys_mmap(core::ptr::null_mut(), capacity, PROT_READ | PROT_WRITE, MAP_PRIVATE | MAP_ANONYMOUS, -1, 0)
+            } else {
+                sys_mremap(self.ptr, self.capacity, capacity, MREMAP_MAYMOVE)
+            }
+        };
+        // Failures come back as a negative errno
+        if (ptr as isize) < 0 && (ptr as isize) > -(PAGE_SIZE as isize) {
+            return false;
+        }
+        
+        self.ptr = ptr;
+        self.capacity = capacity;
+        true
+    }
+    
+    /// Inserts `bytes` at offset `at`. Returns `false` if there is no room.
+    pub fn insert(&mut self, at: usize, bytes: &[u8]) -> bool {
+        if !self.reserve(bytes.len()) {
+            return false;
+        }
+        let len = self.len;
+        let buf = self.allocation();
+        buf.copy_within(at..len, at + bytes.len());
+        buf[at..at + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        true
+    }
+    
+    pub fn push(&mut self, bytes: &[u8]) -> bool {
+        self.insert(self.len, bytes)
+    }
+    
+    pub fn remove(&mut self, range: Range<usize>) {
+        let len = self.len;
+        let removed = range.end - range.start;
+        self.allocation().copy_within(range.end..len, range.start);
+        self.len -= removed;
+    }
+    
+    /// The room after the bytes, to be filled and then added with
+    /// `extend_by`.
+    pub fn spare(&mut self) -> &mut [u8] {
+        let len = self.len;
+        &mut self.allocation()[len..]
+    }
+    
+    pub fn extend_by(&mut self, count: usize) {
+        self.len = (self.len + count).min(self.capacity);
+    }
+}
+
+impl Drop for LineBuffer {
+    fn drop(&mut self) {
+        if self.capacity > 0 {
+            unsafe { sys_munmap(self.ptr, self.capacity) };
+        }
+    }
+}
@@ -8,70 +8,86 @@ mod assets;
 mod shell;
 mod network;
 mod system;
+mod editor;
 
 use core::panic::PanicInfo;
 use syscalls::*;
-use io::{print, print_number, CStr, StaticBuffer, read_line_with_tab};
+use io::{print, print_number, CStr, LineBuffer};
+use editor::LineEditor;
 use shell::{ENV_STORAGE, execute_command};
+use shell::executor::{run_line, ExecState, Flow};
+use shell::history::{expand_history, load_history, record_command};
+use shell::jobs::{init_job_control, terminal_available, CONSOLE_JOBS};
+use shell::parser::{is_name, needs_more_input};
+use shell::passwd::find_uid;
+use shell::prompt::{render_prompt, PROMPT_SIZE, CONSOLE_PS1};
+use shell::script::{open_script, run_script, run_startup_file};
+use shell::storage::{SHELL_NAME, CONSOLE_PARAMS, HISTORY_LINE_SIZE, EXPORTED, Params, set_shell_pid};
 
-fn initialize_path_from_envp(envp: *const *const u8) -> bool {
+/// Where commands are looked for when the environment has no `PATH`.
+const DEFAULT_PATH: &[u8] = b"/bin:/usr/bin:/usr/local/bin";
+
+/// Imports every `NAME=VALUE` entry of the environment the shell was
+/// started with.
+fn import_environment(envp: *const *const u8) {
     if envp.is_null() {
-        return false;
+        return;
     }
     
-    unsafe {
-        let mut env_ptr = envp;
-        let mut count = 0;
-        
-        while count < 1000 {
-            let env_str_ptr = *env_ptr;
-            if env_str_ptr.is_null() {
-                break;
-            }
-            
-            let mut len = 0;
-            while len < 4096 && *env_str_ptr.add(len) != 0 {
-                len += 1;
+    let mut i = 0;
+    while let Some(entry) = unsafe { CStr::from_ptr(*envp.add(i)) } {
+        let entry = entry.as_bytes();
+        if let Some(equals) = entry.iter().position(|&b| b == b'=') {
+            let name = &entry[..equals];
+            if is_name(name) {
+                let _ = ENV_STORAGE.declare(name, Some(&entry[equals + 1..]), EXPORTED);
             }
-            
-            if is_path_variable(env_str_ptr, len) {
-                return extract_and_set_path(env_str_ptr, len);
-            }
-            
-            env_ptr = env_ptr.add(1);
-            count += 1;
         }
-    }
-    
-    false
-}
-
-fn is_path_variable(env_str_ptr: *const u8, len: usize) -> bool {
-    len > 5 && unsafe {
-        *env_str_ptr == b'P' && 
-        *env_str_ptr.add(1) == b'A' && 
-        *env_str_ptr.add(2) == b'T' && 
-        *env_str_ptr.add(3) == b'H' && 
-        *env_str_ptr.add(4) == b'='
+        i += 1;
     }
 }
 
-fn extract_and_set_path(env_str_ptr: *const u8, len: usize) -> bool {
-    let mut path_value = [0u8; 2048];
-    let path_len = (len - 5).min(2048);
+/// Fills in `USER`, `HOME` and `SHELL` from the user's entry in
+/// `/etc/passwd` when the environment doesn't have them, and sets `PWD`
+/// and `SHLVL`, one more than in the shell that started this one. All of
+/// them are exported, like `OLDPWD` once `cd` sets it.
+fn init_environment() {
+    let is_unset = |name: &[u8]| ENV_STORAGE.get(name, &mut []).is_none();
     
-    if path_len == 0 {
-        return false;
+    if is_unset(b"PATH") {
+        let _ = ENV_STORAGE.declare(b"PATH", Some(DEFAULT_PATH), EXPORTED);
     }
     
-    unsafe {
-        for i in 0..path_len {
-            path_value[i] = *env_str_ptr.add(5 + i);
+    if is_unset(b"USER") || is_unset(b"HOME") || is_unset(b"SHELL") {
+        if let Some(entry) = find_uid(getuid()) {
+            let fields: [(&[u8], &[u8]); 3] = [(b"USER", entry.name()), (b"HOME", entry.home()), (b"SHELL", entry.shell())];
+            for (name, value) in fields {
+                if is_unset(name) && !value.is_empty() {
+                    let _ = ENV_STORAGE.declare(name, Some(value), EXPORTED);
+                }
+            }
         }
     }
     
-    ENV_STORAGE.set(b"PATH", &path_value[..path_len]);
-    true
+    let mut cwd = [0u8; 256];
+    let len = getcwd(&mut cwd);
+    if len > 0 {
+        let _ = ENV_STORAGE.declare(b"PWD", Some(&cwd[..len as usize]), EXPORTED);
+    }
+    let _ = ENV_STORAGE.declare(b"OLDPWD", None, EXPORTED);
+    
+    // A level that isn't a number counts as 0, like in other shells
+    let mut level = [0u8; 20];
+    let len = ENV_STORAGE.get(b"SHLVL", &mut level).unwrap_or(0);
+    let level = &level[..len];
+    let parent = if !level.is_empty() && level.iter().all(|b| b.is_ascii_digit()) {
+        level.iter().fold(0i64, |n, &b| (n * 10 + (b - b'0') as i64).min(999))
+    } else {
+        0
+    };
+    let mut digits = [0u8; 20];
+    let len = io::format_number(parent + 1, &mut digits);
+    let _ = ENV_STORAGE.declare(b"SHLVL", Some(&digits[..len]), EXPORTED);
 }
 
 #[panic_handler]
@@ -93,7 +109,7 @@ fn cleanup_and_exit(code: i32) -> ! {
     nanosleep(0, 500_000_000);
     
     print(b"[INFO] Goodbye!\n");
-    sys_exit(code);
+    exit_group(code);
 }
 
 fn should_shutdown() -> bool {
@@ -125,6 +141,129 @@ impl Args {
             None
         }
     }
+    
+    /// The argument at `index`, which lives as long as the process does.
+    fn bytes(&self, index: usize) -> Option<&'static [u8]> {
+        let arg = self.get(index)?;
+        let bytes = arg.as_bytes();
+        Some(unsafe { core::slice::from_raw_parts(bytes.as_ptr(), bytes.len()) })
+    }
+}
+
+const USAGE: &[u8] = b"Usage: reshell [-p PORT] [-c COMMAND [NAME [ARG...]] | FILE [ARG...] | PORT]\n";
+const MAX_SCRIPT_ARGS: usize = 64;
+
+enum Mode {
+    /// Commands typed at the prompt or in a WebSocket session
+    Interactive,
+    /// `-c COMMAND`
+    Command(&'static [u8]),
+    /// `FILE`, also how `#!` scripts are started
+    Script(&'static [u8]),
+}
+
+/// What the command line asks for. Only an interactive shell starts the
+/// web server unless `-p` is given.
+struct Invocation {
+    mode: Mode,
+    port: Option<u16>,
+    /// `$0` for a script or command
+    name: Option<&'static [u8]>,
+    /// Index of the first positional parameter in argv
+    first_arg: usize,
+}
+
+/// Errors name the option they are about.
+fn parse_invocation(args: &Args) -> Result<Invocation, (&'static [u8], &'static [u8])> {
+    let mut invocation = Invocation { mode: Mode::Interactive, port: None, name: None, first_arg: 0 };
+    let mut index = 1;
+    
+    while let Some(arg) = args.bytes(index) {
+        match arg {
+            b"-p" => {
+                let port = args.bytes(index + 1).ok_or((arg, &b"option requires an argument"[..]))?;
+                invocation.port = Some(parse_port(port).ok_or((arg, &b"invalid port number"[..]))?);
+                index += 2;
+            }
+            b"-c" => {
+                let command = args.bytes(index + 1).ok_or((arg, &b"option requires an argument"[..]))?;
+                invocation.mode = Mode::Command(command);
+                invocation.name = args.bytes(index + 2);
+                invocation.first_arg = index + 3;
+                return Ok(invocation);
+            }
+            _ if arg.len() > 1 && arg[0] == b'-' => return Err((arg, b"invalid option")),
+            // A lone number is the port of an interactive shell, as before
+            // scripts could be run
+            _ if invocation.port.is_none() && args.bytes(index + 1).is_none() && parse_port(arg).is_some() => {
+                invocation.port = parse_port(arg);
+                return Ok(invocation);
+            }
+            _ => {
+                invocation.mode = Mode::Script(arg);
+                invocation.name = Some(arg);
+                invocation.first_arg = index + 1;
+                return Ok(invocation);
+            }
+        }
+    }
+    
+    Ok(invocation)
+}
+
+/// Runs a `-c` command or a script file and returns the status of its last
+/// command. Unless there is a web server to serve, signals keep their
+/// default dispositions, so Ctrl-C stops the script.
+fn run_noninteractive(invocation: &Invocation) -> i32 {
+    init_job_control();
+    if let Some(port) = invocation.port {
+        setup_signal_handlers();
+        start_server(port);
+    }
+    
+    let io = io::Stdio::standard();
+    let mut state = ExecState::new();
+    state.params = CONSOLE_PARAMS.load();
+    
+    let status = match invocation.mode {
+        Mode::Command(command) => run_line(command, &io, &mut state),
+        Mode::Script(path) => match open_script(path) {
+            Ok(fd) => {
+                let status = run_script(fd, &io, &mut state);
+                close(fd);
+                status
+            }
+            Err(err) => {
+                write(STDERR, b"reshell: ");
+                write(STDERR, path);
+                write(STDERR, b": ");
+                write(STDERR, strerror(err));
+                write(STDERR, b"\n");
+                127
+            }
+        },
+        Mode::Interactive => 0,
+    };
+    
+    if invocation.port.is_some() {
+        cleanup_and_exit(status);
+    }
+    status
+}
+
+fn start_server(port: u16) {
+    use crate::system::thread::start_http_server_thread;
+    
+    // Server threads start with SIGCHLD and SIGTERM blocked so both land on
+    // this thread: the job reaper never interrupts a blocking poll in a
+    // client thread, and SIGTERM wakes up the prompt to shut down.
+    mask_signal(SIG_BLOCK, SIGCHLD);
+    mask_signal(SIG_BLOCK, SIGTERM);
+    start_http_server_thread(port);
+    mask_signal(SIG_UNBLOCK, SIGCHLD);
+    mask_signal(SIG_UNBLOCK, SIGTERM);
+    
+    nanosleep(0, 200_000_000);
 }
 
 fn parse_port(bytes: &[u8]) -> Option<u16> {
@@ -141,6 +280,93 @@ fn parse_port(bytes: &[u8]) -> Option<u16> {
     if port > 0 { Some(port) } else { None }
 }
 
+/// Runs `/etc/reshellrc` and then `$HOME/.reshellrc`, which may set up
+/// the environment, aliases and functions of the interactive shell. An
+/// `exit` in either ends the shell before it starts.
+fn run_startup_files() {
+    let io = io::Stdio::standard();
+    let mut state = ExecState::new();
+    state.job_control = terminal_available();
+    state.interactive = true;
+    state.params = CONSOLE_PARAMS.load();
+    
+    run_startup_file(b"/etc/reshellrc", &io, &mut state);
+    
+    let mut path = [0u8; 256];
+    if let Some(len) = ENV_STORAGE.get(b"HOME", &mut path) {
+        let name = b"/.reshellrc";
+        if state.flow == Flow::Normal && len > 0 && len + name.len() <= path.len() {
+            path[len..len + name.len()].copy_from_slice(name);
+            run_startup_file(&path[..len + name.len()], &io, &mut state);
+        }
+    }
+    CONSOLE_PARAMS.store(&state.params);
+    
+    if let Flow::Exit(code) = state.flow {
+        cleanup_and_exit(code);
+    }
+}
+
+/// Runs a command typed at the local prompt, echoing it and its output to
+/// the WebSocket clients if the server is running.
+fn run_local_command(cmd: &[u8], has_websocket: bool) {
+    if !has_websocket {
+        execute_command(cmd);
+        return;
+    }
+    
+    use crate::network::websocket::broadcast_message;
+    use crate::io::{enable_output_capture, disable_output_capture, get_captured_output};
+    
+    // Broadcast command
+    let mut cmd_msg = [0u8; 520];
+    let mut cmd_len = 0;
+    let prefix = b"[Local] > ";
+    for &b in prefix {
+        cmd_msg[cmd_len] = b;
+        cmd_len += 1;
+    }
+    for &b in &cmd[..cmd.len().min(510)] {
+        cmd_msg[cmd_len] = b;
+        cmd_len += 1;
+    }
+    broadcast_message(&cmd_msg[..cmd_len]);
+    
+    // Execute with output capture
+    enable_output_capture();
+    execute_command(cmd);
+    disable_output_capture();
+    
+    let mut output = [0u8; 4096];
+    let output_len = get_captured_output(&mut output);
+    if output_len > 0 {
+        broadcast_message(&output[..output_len]);
+    }
+}
+
+/// Adds a line typed at the prompt to the command in `pending`, with its
+/// history references expanded. Returns `false` if the line is dropped.
+fn add_line(pending: &mut LineBuffer, line: &[u8]) -> bool {
+    if !pending.reserve(line.len() + HISTORY_LINE_SIZE) {
+        print(b"reshell: command too long\n");
+        return false;
+    }
+    
+    match expand_history(line, pending.spare()) {
+        // The expanded line is shown before it runs
+        Ok(Some(len)) => {
+            write(STDOUT, &pending.spare()[..len]);
+            pending.extend_by(len);
+            true
+        }
+        Ok(None) => pending.push(line),
+        Err(err) => {
+            err.report(|s| { write(STDERR, s); });
+            false
+        }
+    }
+}
+
 core::arch::global_asm!(
     ".global _start",
     ".type _start, @function",
@@ -161,35 +387,63 @@ core::arch::global_asm!(
 extern "C" fn main(argc: i64, argv: *const *const u8, envp: *const *const u8) -> i32 {
     let args = Args::new(argc, argv);
     
-    let port = if argc > 1 {
-        if let Some(arg) = args.get(1) {
-            parse_port(arg.as_bytes()).unwrap_or(8000)
-        } else {
-            8000
+    let invocation = match parse_invocation(&args) {
+        Ok(invocation) => invocation,
+        Err((option, err)) => {
+            write(STDERR, b"reshell: ");
+            write(STDERR, option);
+            write(STDERR, b": ");
+            write(STDERR, err);
+            write(STDERR, b"\n");
+            write(STDERR, USAGE);
+            return 2;
         }
-    } else {
-        8000
     };
     
-    // Get PATH from parent environment
-    if !initialize_path_from_envp(envp) {
-        ENV_STORAGE.set(b"PATH", b"/bin:/usr/bin:/usr/local/bin");
+    set_shell_pid(getpid());
+    if let Some(name) = invocation.name.or_else(|| args.bytes(0)) {
+        SHELL_NAME.set(name);
+    }
+    
+    if invocation.first_arg > 0 {
+        let mut script_args: [&[u8]; MAX_SCRIPT_ARGS] = [&[]; MAX_SCRIPT_ARGS];
+        let mut count = 0;
+        while let Some(arg) = args.bytes(invocation.first_arg + count) {
+            if count == MAX_SCRIPT_ARGS {
+                break;
+            }
+            script_args[count] = arg;
+            count += 1;
+        }
+        let mut params = Params::new();
+        if !params.args.set(&script_args[..count]) {
+            write(STDERR, b"reshell: too many arguments\n");
+            return 2;
+        }
+        CONSOLE_PARAMS.store(&params);
+    }
+    
+    import_environment(envp);
+    init_environment();
+    
+    if !matches!(invocation.mode, Mode::Interactive) {
+        return run_noninteractive(&invocation);
     }
     
+    let port = invocation.port.unwrap_or(8000);
+    
     if !setup_signal_handlers() {
         print(b"[WARN] Failed to setup signal handlers\n");
     }
     
-    print(b"Minimal Shell v0.3\n");
-    print(b"Features: tab completion, env vars, WebSocket, multi-threaded\n");
-    print(b"Builtins: ls, cd, pwd, export, echo, env, threads, exit\n");
-    print(b"Signal handlers: SIGINT, SIGTERM, SIGPIPE\n\n");
-    
-    ENV_STORAGE.set(b"HOME", b"/home");
-    ENV_STORAGE.set(b"USER", b"user");
-    // PATH already set from parent env
+    if !init_job_control() {
+        print(b"[WARN] Failed to setup job control\n");
+    }
     
-    use crate::system::thread::start_http_server_thread;
+    print(b"Minimal Shell v0.3\n");
+    print(b"Features: line editing, tab completion, env vars, WebSocket, multi-threaded\n");
+    print(b"Builtins: ls, cd, pwd, export, readonly, unset, echo, let, env, threads, jobs, fg, bg, wait, disown, break, continue, local, return, type, source, alias, unalias, history, exit\n");
+    print(b"Signal handlers: SIGTERM, SIGCHLD (SIGINT, SIGQUIT, SIGTSTP go to the foreground job)\n\n");
     
     print(b"[INFO] Starting HTTP server on port ");
     print_number(port as i64);
@@ -198,14 +452,14 @@ extern "C" fn main(argc: i64, argv: *const *const u8, envp: *const *const u8) ->
     print(b"[INFO] Server running in multi-threaded mode\n");
     print(b"[INFO] Type 'exit' to quit, or use commands below\n\n");
     
-    start_http_server_thread(port);
-    
-    use syscalls::nanosleep;
-    nanosleep(0, 200_000_000);
+    run_startup_files();
+    load_history();
+    start_server(port);
     
     let has_websocket = port > 0;
     
-    static INPUT_BUF: StaticBuffer = StaticBuffer::new();
+    let mut editor = LineEditor::new();
+    let mut pending = LineBuffer::new();
     
     loop {
         if should_shutdown() {
@@ -243,50 +497,33 @@ extern "C" fn main(argc: i64, argv: *const *const u8, envp: *const *const u8) ->
             }
         }
         
-        // Local shell prompt
-        print(b"reshell> ");
+        // PS1, or PS2 while a command like an `if` without its `fi`
+        // waits for more lines
+        let continuing = !pending.is_empty();
+        if !continuing {
+            CONSOLE_JOBS.list(&io::Stdio::standard(), true);
+        }
+        let mut prompt = [0u8; PROMPT_SIZE];
+        let prompt_len = render_prompt(continuing, CONSOLE_PS1, CONSOLE_PARAMS.status(), &mut prompt);
         
-        INPUT_BUF.with_mut(|input| {
-            let n = read_line_with_tab(input);
-            
-            if n > 0 {
-                if has_websocket {
-                    use crate::network::websocket::broadcast_message;
-                    use crate::io::{enable_output_capture, disable_output_capture, get_captured_output};
-                    
-                    // Broadcast command
-                    let mut cmd_msg = [0u8; 520];
-                    let mut cmd_len = 0;
-                    let prefix = b"[Local] > ";
-                    for &b in prefix {
-                        cmd_msg[cmd_len] = b;
-                        cmd_len += 1;
-                    }
-                    for i in 0..n.min(510) {
-                        cmd_msg[cmd_len] = input[i];
-                        cmd_len += 1;
-                    }
-                    broadcast_message(&cmd_msg[..cmd_len]);
-                    
-                    // Execute with output capture
-                    enable_output_capture();
-                    execute_command(&input[..n]);
-                    disable_output_capture();
-                    
-                    let mut output = [0u8; 4096];
-                    let output_len = get_captured_output(&mut output);
-                    if output_len > 0 {
-                        broadcast_message(&output[..output_len]);
-                    }
-                } else {
-                    execute_command(&input[..n]);
-                }
-            }
-            
-            if should_shutdown() {
-                cleanup_and_exit(0);
-            }
-        });
+        let added = match editor.read_line(&prompt[..prompt_len]) {
+            Some(line) => add_line(&mut pending, line),
+            None => false,
+        };
+        
+        if !added {
+            // Ctrl-C and failed expansions also drop the lines of an
+            // unfinished command
+            pending.clear();
+        } else if !needs_more_input(pending.as_slice()) {
+            record_command(pending.as_slice());
+            run_local_command(pending.as_slice(), has_websocket);
+            pending.clear();
+        }
+        
+        if should_shutdown() {
+            cleanup_and_exit(0);
+        }
         
         if has_websocket {
             nanosleep(0, 10_000_000);
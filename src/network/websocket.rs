@@ -1,7 +1,9 @@
 use crate::syscalls::{read, write, close, STDOUT};
 use crate::io::print;
 use crate::system::crypto::{sha1, base64_encode};
-use crate::shell::{allocate_session, free_session, get_session, execute_command_in_session};
+use crate::shell::{allocate_session, free_session, get_session, execute_command_in_session, init_session};
+use crate::shell::session::ShellSession;
+use crate::shell::prompt::{render_plain_prompt, PROMPT_SIZE, SESSION_PS1};
 
 // Legacy broadcast/queue functions for backwards compatibility with main.rs
 // These are no longer used in per-session websocket implementation
@@ -185,9 +187,17 @@ fn websocket_frame_loop(client_fd: i32, session_id: usize) {
     let mut buf = [0u8; 1024];
     let mut output_buf = [0u8; 4096];
     
+    // The ENV file sets the session up, and what it prints comes first
+    init_session(session);
+    let len = session.read_output(&mut output_buf);
+    if len > 0 {
+        send_websocket_text(client_fd, &output_buf[..len]);
+    }
+    
     print(b"[WS] Sending welcome message...\n");
     // Send welcome message
-    send_websocket_text(client_fd, b"Welcome to ReShell!\n$ ");
+    send_websocket_text(client_fd, b"Welcome to ReShell!\n");
+    send_prompt(client_fd, session);
     print(b"[WS] Welcome message sent\n");
     
     loop {
@@ -203,7 +213,7 @@ fn websocket_frame_loop(client_fd: i32, session_id: usize) {
             if len > 0 {
                 send_websocket_text(client_fd, &output_buf[..len]);
                 // Send prompt after output
-                send_websocket_text(client_fd, b"$ ");
+                send_prompt(client_fd, session);
             }
         }
         
@@ -288,7 +298,8 @@ fn websocket_frame_loop(client_fd: i32, session_id: usize) {
                         let mut cmd = [0u8; 512];
                         let cmd_len = session.get_input(&mut cmd);
                         
-                        if cmd_len > 0 {
+                        // An empty line still belongs to an unfinished command
+                        if cmd_len > 0 || session.is_continuing() {
                             // Echo newline
                             send_websocket_text(client_fd, b"\n");
                             
@@ -297,9 +308,15 @@ fn websocket_frame_loop(client_fd: i32, session_id: usize) {
                             
                             // Clear input
                             session.clear_input();
+                            
+                            // Ask for the next line of an unfinished command
+                            if session.is_continuing() {
+                                send_prompt(client_fd, session);
+                            }
                         } else {
                             // Empty command, just send prompt
-                            send_websocket_text(client_fd, b"\n$ ");
+                            send_websocket_text(client_fd, b"\n");
+                            send_prompt(client_fd, session);
                         }
                     } else if ch == 0x7f || ch == 0x08 {
                         // Backspace/Delete
@@ -362,3 +379,10 @@ fn send_websocket_text(fd: i32, payload: &[u8]) {
     
     write(fd, &frame[..pos]);
 }
+
+// Send PS1, or PS2 while the session's command waits for more lines
+fn send_prompt(fd: i32, session: &ShellSession) {
+    let mut prompt = [0u8; PROMPT_SIZE];
+    let len = render_plain_prompt(session.is_continuing(), SESSION_PS1, session.last_status(), &mut prompt);
+    send_websocket_text(fd, &prompt[..len]);
+}
@@ -0,0 +1,91 @@
+use crate::syscalls::write;
+use crate::shell::parser::is_alias_name;
+use crate::shell::storage::{ALIASES, ALIAS_VALUE_SIZE};
+use crate::io::Stdio;
+
+/// Prints `alias NAME='VALUE'`, quoted so it can be read back.
+fn print_alias(io: &Stdio, name: &[u8], value: &[u8]) {
+    write(io.stdout, b"alias ");
+    write(io.stdout, name);
+    write(io.stdout, b"='");
+    for (i, part) in value.split(|&b| b == b'\'').enumerate() {
+        if i > 0 {
+            write(io.stdout, b"'\\''");
+        }
+        write(io.stdout, part);
+    }
+    write(io.stdout, b"'\n");
+}
+
+/// `alias` lists the aliases, `alias NAME=VALUE` defines one and
+/// `alias NAME` shows one.
+pub fn builtin_alias(args: &[&[u8]], io: &Stdio) -> i32 {
+    if args.is_empty() {
+        ALIASES.for_each(|name, value| print_alias(io, name, value));
+        return 0;
+    }
+    
+    let mut status = 0;
+    for &arg in args {
+        let (name, value) = match arg.iter().position(|&c| c == b'=') {
+            Some(eq_pos) => (&arg[..eq_pos], Some(&arg[eq_pos + 1..])),
+            None => (arg, None),
+        };
+        
+        match value {
+            Some(value) if is_alias_name(name) => {
+                if let Err(err) = ALIASES.define(name, value) {
+                    write(io.stderr, b"alias: ");
+                    write(io.stderr, name);
+                    write(io.stderr, b": ");
+                    write(io.stderr, err.as_bytes());
+                    write(io.stderr, b"\n");
+                    status = 1;
+                }
+            }
+            Some(_) => {
+                write(io.stderr, b"alias: `");
+                write(io.stderr, name);
+                write(io.stderr, b"': invalid alias name\n");
+                status = 1;
+            }
+            None => {
+                let mut value = [0u8; ALIAS_VALUE_SIZE];
+                match ALIASES.get(name, &mut value) {
+                    Some(len) => print_alias(io, name, &value[..len]),
+                    None => {
+                        write(io.stderr, b"alias: ");
+                        write(io.stderr, name);
+                        write(io.stderr, b": not found\n");
+                        status = 1;
+                    }
+                }
+            }
+        }
+    }
+    status
+}
+
+/// `unalias NAME...` removes aliases, `unalias -a` all of them.
+pub fn builtin_unalias(args: &[&[u8]], io: &Stdio) -> i32 {
+    if args.is_empty() {
+        write(io.stderr, b"unalias: usage: unalias [-a] name [name ...]\n");
+        return 2;
+    }
+    
+    if args[0] == b"-a" {
+        ALIASES.clear();
+        return 0;
+    }
+    
+    let mut status = 0;
+    for &name in args {
+        if !ALIASES.remove(name) {
+            write(io.stderr, b"unalias: ");
+            write(io.stderr, name);
+            write(io.stderr, b": not found\n");
+            status = 1;
+        }
+    }
+    status
+}
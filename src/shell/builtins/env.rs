@@ -1,33 +1,161 @@
-use crate::syscalls::{write, STDOUT};
-use crate::shell::storage::ENV_STORAGE;
-use crate::utils::trim_spaces;
-use crate::io::print;
+use crate::syscalls::write;
+use crate::shell::executor::ExecState;
+use crate::shell::parser::is_name;
+use crate::shell::storage::{ENV_STORAGE, EXPORTED, READONLY};
+use crate::io::Stdio;
 
-pub fn builtin_export(args: &[u8]) {
-    let args = trim_spaces(args);
-    
+/// Prints `COMMAND NAME='VALUE'`, quoted so it can be read back, or just
+/// `COMMAND NAME` for a variable without a value.
+fn print_variable(io: &Stdio, command: &[u8], name: &[u8], value: Option<&[u8]>) {
+    write(io.stdout, command);
+    write(io.stdout, b" ");
+    write(io.stdout, name);
+    if let Some(value) = value {
+        write(io.stdout, b"='");
+        for (i, part) in value.split(|&b| b == b'\'').enumerate() {
+            if i > 0 {
+                write(io.stdout, b"'\\''");
+            }
+            write(io.stdout, part);
+        }
+        write(io.stdout, b"'");
+    }
+    write(io.stdout, b"\n");
+}
+
+fn report_variable_error(io: &Stdio, command: &[u8], arg: &[u8], msg: &[u8]) {
+    write(io.stderr, command);
+    write(io.stderr, b": ");
+    write(io.stderr, arg);
+    write(io.stderr, b": ");
+    write(io.stderr, msg);
+    write(io.stderr, b"\n");
+}
+
+/// Gives each `NAME[=VALUE]` of `args` the `flags`, and the value if
+/// there is one. With no arguments or just `-p`, lists the variables that
+/// have the flags instead.
+fn declare_variables(command: &[u8], args: &[&[u8]], flags: u8, io: &Stdio) -> i32 {
+    let args = match args.first() {
+        Some(&b"-p") => &args[1..],
+        _ => args,
+    };
     if args.is_empty() {
-        ENV_STORAGE.iter(|var| {
-            write(STDOUT, var);
-            print(b"\n");
+        ENV_STORAGE.for_each(|name, value, var_flags| {
+            if var_flags & flags != 0 {
+                print_variable(io, command, name, value);
+            }
         });
-        return;
+        return 0;
     }
     
-    for i in 0..args.len() {
-        if args[i] == b'=' {
-            let name = &args[..i];
-            let value = if i + 1 < args.len() {
-                &args[i + 1..]
-            } else {
-                &[]
-            };
-            if !ENV_STORAGE.set(name, value) {
-                print(b"export: too many variables\n");
-            }
-            return;
+    let mut status = 0;
+    for &arg in args {
+        let (name, value) = match arg.iter().position(|&c| c == b'=') {
+            Some(eq_pos) => (&arg[..eq_pos], Some(&arg[eq_pos + 1..])),
+            None => (arg, None),
+        };
+        if !is_name(name) {
+            write(io.stderr, command);
+            write(io.stderr, b": `");
+            write(io.stderr, arg);
+            write(io.stderr, b"': not a valid identifier\n");
+            status = 1;
+            continue;
+        }
+        if let Err(err) = ENV_STORAGE.declare(name, value, flags) {
+            report_variable_error(io, command, name, err.as_bytes());
+            status = 1;
         }
     }
+    status
+}
+
+/// `export NAME[=VALUE]...` passes each variable on to the commands the
+/// shell runs from now on. `export` alone lists them.
+pub fn builtin_export(args: &[&[u8]], io: &Stdio) -> i32 {
+    declare_variables(b"export", args, EXPORTED, io)
+}
+
+/// `readonly NAME[=VALUE]...` keeps each variable from being changed or
+/// unset. `readonly` alone lists them.
+pub fn builtin_readonly(args: &[&[u8]], io: &Stdio) -> i32 {
+    declare_variables(b"readonly", args, READONLY, io)
+}
+
+/// Prints the environment commands get, as `NAME=VALUE` lines.
+pub fn builtin_env(io: &Stdio) -> i32 {
+    ENV_STORAGE.for_each(|name, value, flags| {
+        if let Some(value) = value.filter(|_| flags & EXPORTED != 0) {
+            write(io.stdout, name);
+            write(io.stdout, b"=");
+            write(io.stdout, value);
+            write(io.stdout, b"\n");
+        }
+    });
+    0
+}
+
+/// `unset [-v] NAME...` removes each variable, unless it is readonly.
+pub fn builtin_unset(args: &[&[u8]], io: &Stdio) -> i32 {
+    let args = match args.first() {
+        Some(&b"-v") => &args[1..],
+        _ => args,
+    };
     
-    print(b"export: invalid format (use NAME=VALUE)\n");
+    let mut status = 0;
+    for &name in args {
+        if !is_name(name) {
+            write(io.stderr, b"unset: `");
+            write(io.stderr, name);
+            write(io.stderr, b"': not a valid identifier\n");
+            status = 1;
+            continue;
+        }
+        if let Err(err) = ENV_STORAGE.remove(name) {
+            report_variable_error(io, b"unset", name, err.as_bytes());
+            status = 1;
+        }
+    }
+    status
+}
+
+/// `local NAME[=VALUE]...`: gives the running function its own copy of each
+/// variable, set to VALUE or unset, until the function returns.
+pub fn builtin_local(args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> i32 {
+    if state.call_depth == 0 {
+        write(io.stderr, b"local: can only be used in a function\n");
+        return 1;
+    }
+    
+    let mut status = 0;
+    for &arg in args {
+        let (name, value) = match arg.iter().position(|&c| c == b'=') {
+            Some(eq_pos) => (&arg[..eq_pos], Some(&arg[eq_pos + 1..])),
+            None => (arg, None),
+        };
+        
+        if !is_name(name) {
+            write(io.stderr, b"local: `");
+            write(io.stderr, arg);
+            write(io.stderr, b"': not a valid identifier\n");
+            status = 1;
+            continue;
+        }
+        if let Err(err) = state.locals.save(name, state.frame_start) {
+            report_variable_error(io, b"local", name, err.as_bytes());
+            status = 1;
+            continue;
+        }
+        
+        let result = match value {
+            Some(value) => ENV_STORAGE.set(name, value),
+            None => ENV_STORAGE.remove(name),
+        };
+        if let Err(err) = result {
+            report_variable_error(io, b"local", name, err.as_bytes());
+            status = 1;
+        }
+    }
+    status
 }
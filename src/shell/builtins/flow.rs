@@ -0,0 +1,70 @@
+use crate::syscalls::write;
+use crate::shell::executor::{status_argument, ExecState, Flow};
+use crate::io::Stdio;
+
+/// Number of loops `break N` or `continue N` applies to, capped at the
+/// loops around it. Errors are reported as coming from `name`.
+fn loop_count(name: &[u8], args: &[&[u8]], io: &Stdio, state: &ExecState) -> Option<usize> {
+    if state.loop_depth == 0 {
+        write(io.stderr, name);
+        write(io.stderr, b": only meaningful in a `for', `while', or `until' loop\n");
+        return None;
+    }
+    
+    let arg = match args.first() {
+        Some(arg) => *arg,
+        None => return Some(1),
+    };
+    
+    let valid = !arg.is_empty() && arg.len() <= 9 && arg.iter().all(u8::is_ascii_digit);
+    let count = if valid { arg.iter().fold(0usize, |acc, &b| acc * 10 + (b - b'0') as usize) } else { 0 };
+    if count == 0 {
+        write(io.stderr, name);
+        write(io.stderr, b": ");
+        write(io.stderr, arg);
+        write(io.stderr, b": loop count out of range\n");
+        return None;
+    }
+    
+    Some(count.min(state.loop_depth))
+}
+
+pub fn builtin_break(args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> i32 {
+    match loop_count(b"break", args, io, state) {
+        Some(count) => {
+            state.flow = Flow::Break(count);
+            0
+        }
+        None => 1,
+    }
+}
+
+pub fn builtin_continue(args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> i32 {
+    match loop_count(b"continue", args, io, state) {
+        Some(count) => {
+            state.flow = Flow::Continue(count);
+            0
+        }
+        None => 1,
+    }
+}
+
+/// `return [N]` leaves the running function or sourced file with status N,
+/// or the status of the last command.
+pub fn builtin_return(args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> i32 {
+    if state.call_depth == 0 && state.source_depth == 0 {
+        write(io.stderr, b"return: can only `return' from a function or sourced script\n");
+        return 1;
+    }
+    
+    match status_argument(b"return", args, io, state.params.status) {
+        Some(status) => {
+            state.flow = Flow::Return(status);
+            status
+        }
+        None => {
+            state.flow = Flow::Return(2);
+            2
+        }
+    }
+}
@@ -1,83 +1,123 @@
-use crate::syscalls::{write, chdir, open, close, getdents64, STDOUT, O_RDONLY, O_DIRECTORY};
-use crate::utils::{trim_spaces, sort_entries};
-use crate::shell::parser::{expand_env_vars, DirentParser};
-use crate::io::print;
+use crate::syscalls::{write, chdir, getcwd, open, close, getdents64, strerror, O_RDONLY, O_DIRECTORY};
+use crate::utils::sort_entries;
+use crate::shell::parser::DirentParser;
+use crate::shell::storage::ENV_STORAGE;
+use crate::io::{LineBuffer, Stdio};
 
-pub fn builtin_pwd() {
-    use crate::syscalls::getcwd;
-    
-    let mut buf = [0u8; 512];
+/// Longest path the kernel gives back for the working directory.
+const PATH_SIZE: usize = 4096;
+
+pub fn builtin_pwd(io: &Stdio) -> i32 {
+    let mut buf = [0u8; PATH_SIZE];
     let ret = getcwd(&mut buf);
     
-    if ret > 0 && ret < 512 {
-        write(STDOUT, &buf[..ret as usize]);
-        print(b"\n");
+    if ret > 0 {
+        write(io.stdout, &buf[..ret as usize]);
+        write(io.stdout, b"\n");
+        0
     } else {
-        print(b"pwd: error getting current directory\n");
+        write(io.stderr, b"pwd: error getting current directory\n");
+        1
     }
 }
 
-pub fn builtin_cd(path: &[u8]) {
-    let path = trim_spaces(path);
-    
+/// `cd [DIR]`: changes to DIR, to `$HOME` without one, or with `cd -` to
+/// `$OLDPWD`, which it shows. `PWD` and `OLDPWD` follow the change.
+pub fn builtin_cd(args: &[&[u8]], io: &Stdio) -> i32 {
+    let mut value = LineBuffer::new();
+    let (path, show) = match args.first() {
+        Some(&b"-") => {
+            if !ENV_STORAGE.get_all(b"OLDPWD", &mut value) {
+                write(io.stderr, b"cd: OLDPWD not set\n");
+                return 1;
+            }
+            (value.as_slice(), true)
+        }
+        Some(path) => (*path, false),
+        None => {
+            if !ENV_STORAGE.get_all(b"HOME", &mut value) {
+                write(io.stderr, b"cd: HOME not set\n");
+                return 1;
+            }
+            (value.as_slice(), false)
+        }
+    };
+    // Like `cd ""`, an empty `HOME` leaves the shell where it is
     if path.is_empty() {
-        print(b"cd: missing argument\n");
-        return;
+        return 0;
     }
     
-    let mut expanded = [0u8; 512];
-    let exp_len = expand_env_vars(path, &mut expanded);
+    let mut c_path = LineBuffer::new();
+    if !(c_path.push(path) && c_path.push(&[0])) {
+        write(io.stderr, b"cd: out of memory\n");
+        return 1;
+    }
     
-    let mut path_buf = [0u8; 256];
-    let mut idx = 0;
-    for i in 0..exp_len {
-        if idx >= path_buf.len() - 1 {
-            print(b"cd: path too long\n");
-            return;
+    // The directory left is the one `PWD` names, if it is set
+    let mut old = LineBuffer::new();
+    if !ENV_STORAGE.get_all(b"PWD", &mut old) {
+        let mut cwd = [0u8; PATH_SIZE];
+        let len = getcwd(&mut cwd);
+        if len > 0 {
+            old.push(&cwd[..len as usize]);
         }
-        path_buf[idx] = expanded[i];
-        idx += 1;
     }
-    path_buf[idx] = 0;
     
-    let ret = chdir(&path_buf[..idx + 1]);
+    let ret = chdir(c_path.as_slice());
     if ret < 0 {
-        print(b"cd: cannot change directory\n");
+        write(io.stderr, b"cd: ");
+        write(io.stderr, path);
+        write(io.stderr, b": ");
+        write(io.stderr, strerror(ret));
+        write(io.stderr, b"\n");
+        return 1;
+    }
+    
+    if !old.is_empty() {
+        let _ = ENV_STORAGE.set(b"OLDPWD", old.as_slice());
+    }
+    let mut cwd = [0u8; PATH_SIZE];
+    let len = getcwd(&mut cwd);
+    if len > 0 {
+        let _ = ENV_STORAGE.set(b"PWD", &cwd[..len as usize]);
+        if show {
+            write(io.stdout, &cwd[..len as usize]);
+            write(io.stdout, b"\n");
+        }
     }
+    0
 }
 
-pub fn builtin_ls(path: &[u8]) {
-    let path = trim_spaces(path);
-    let mut expanded = [0u8; 512];
-    let exp_len = if path.is_empty() {
-        0
-    } else {
-        expand_env_vars(path, &mut expanded)
-    };
+pub fn builtin_ls(args: &[&[u8]], io: &Stdio) -> i32 {
+    let path: &[u8] = args.first().copied().unwrap_or(b"");
     
     let mut path_buf = [0u8; 256];
     let mut idx = 0;
     
-    if exp_len == 0 {
+    if path.is_empty() {
         path_buf[0] = b'.';
         path_buf[1] = 0;
         idx = 1;
     } else {
-        for i in 0..exp_len {
+        for &b in path {
             if idx >= path_buf.len() - 1 {
-                print(b"ls: path too long\n");
-                return;
+                write(io.stderr, b"ls: path too long\n");
+                return 2;
             }
-            path_buf[idx] = expanded[i];
+            path_buf[idx] = b;
             idx += 1;
         }
         path_buf[idx] = 0;
     }
     
-    let fd = open(&path_buf[..idx + 1], O_RDONLY | O_DIRECTORY);
+    let fd = open(&path_buf[..idx + 1], O_RDONLY | O_DIRECTORY, 0);
     if fd < 0 {
-        print(b"ls: cannot open directory\n");
-        return;
+        write(io.stderr, b"ls: cannot open directory ");
+        write(io.stderr, &path_buf[..idx]);
+        write(io.stderr, b": ");
+        write(io.stderr, strerror(fd));
+        write(io.stderr, b"\n");
+        return 2;
     }
     
     let mut entries = [[0u8; 256]; 128];
@@ -121,8 +161,9 @@ pub fn builtin_ls(path: &[u8]) {
             len += 1;
         }
         if len > 0 {
-            write(STDOUT, &entries[i][..len]);
-            print(b"\n");
+            write(io.stdout, &entries[i][..len]);
+            write(io.stdout, b"\n");
         }
     }
+    0
 }
@@ -0,0 +1,75 @@
+use crate::syscalls::write;
+use crate::shell::history::save_history;
+use crate::shell::storage::HISTORY;
+use crate::io::{format_number, Stdio};
+
+const USAGE: &[u8] = b"history: usage: history [-c] [-d offset] [n]\n";
+
+fn parse_count(arg: &[u8]) -> Option<usize> {
+    if arg.is_empty() || arg.len() > 9 || !arg.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    Some(arg.iter().fold(0, |acc, &b| acc * 10 + (b - b'0') as usize))
+}
+
+/// Prints the commands from number `first` on, each after its number.
+fn list(io: &Stdio, first: usize) {
+    HISTORY.for_each(first, |number, entry| {
+        let mut digits = [0u8; 20];
+        let len = format_number(number as i64, &mut digits);
+        let mut line = [b' '; 27];
+        let pad = 5usize.saturating_sub(len);
+        line[pad..pad + len].copy_from_slice(&digits[..len]);
+        write(io.stdout, &line[..pad + len + 2]);
+        write(io.stdout, entry);
+        write(io.stdout, b"\n");
+    });
+}
+
+/// `history` lists the commands run so far, `history N` the last N of
+/// them. `history -c` forgets them all and `history -d N` forgets command
+/// N; both also rewrite the history file.
+pub fn builtin_history(args: &[&[u8]], io: &Stdio) -> i32 {
+    match args {
+        [] => list(io, 0),
+        [b"-c"] => {
+            HISTORY.clear();
+            save_history();
+        }
+        [b"-d"] => {
+            write(io.stderr, b"history: -d: option requires an argument\n");
+            write(io.stderr, USAGE);
+            return 2;
+        }
+        [b"-d", offset] => {
+            if !parse_count(offset).is_some_and(|number| HISTORY.remove(number)) {
+                write(io.stderr, b"history: ");
+                write(io.stderr, offset);
+                write(io.stderr, b": history position out of range\n");
+                return 1;
+            }
+            save_history();
+        }
+        [option, ..] if option.len() > 1 && option[0] == b'-' => {
+            write(io.stderr, b"history: ");
+            write(io.stderr, option);
+            write(io.stderr, b": invalid option\n");
+            write(io.stderr, USAGE);
+            return 2;
+        }
+        [count] => match parse_count(count) {
+            Some(count) => list(io, HISTORY.next_number().saturating_sub(count)),
+            None => {
+                write(io.stderr, b"history: ");
+                write(io.stderr, count);
+                write(io.stderr, b": numeric argument required\n");
+                return 1;
+            }
+        },
+        _ => {
+            write(io.stderr, b"history: too many arguments\n");
+            return 1;
+        }
+    }
+    0
+}
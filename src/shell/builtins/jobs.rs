@@ -0,0 +1,105 @@
+use crate::syscalls::write;
+use crate::shell::jobs::MAX_JOBS;
+use crate::shell::executor::ExecState;
+use crate::io::Stdio;
+
+fn no_such_job(io: &Stdio, name: &[u8], spec: Option<&[u8]>) {
+    write(io.stderr, name);
+    write(io.stderr, b": ");
+    write(io.stderr, spec.unwrap_or(b"current"));
+    write(io.stderr, b": no such job\n");
+}
+
+pub fn builtin_jobs(io: &Stdio, state: &ExecState) -> i32 {
+    state.jobs.list(io, false);
+    0
+}
+
+pub fn builtin_fg(args: &[&[u8]], io: &Stdio, state: &ExecState) -> i32 {
+    let spec = args.first().copied();
+    let number = match state.jobs.find(spec) {
+        Some(number) => number,
+        None => {
+            no_such_job(io, b"fg", spec);
+            return 1;
+        }
+    };
+    
+    state.jobs.print_command(io.stdout, number, false);
+    state.jobs.wait(number, io, state.job_control)
+}
+
+pub fn builtin_bg(args: &[&[u8]], io: &Stdio, state: &ExecState) -> i32 {
+    let spec = args.first().copied();
+    let number = match state.jobs.find(spec) {
+        Some(number) => number,
+        None => {
+            no_such_job(io, b"bg", spec);
+            return 1;
+        }
+    };
+    
+    if !state.jobs.is_stopped(number) {
+        write(io.stderr, b"bg: job already in background\n");
+        return 0;
+    }
+    
+    state.jobs.resume(number);
+    state.jobs.print_command(io.stdout, number, true);
+    0
+}
+
+/// `wait` with no arguments waits for every job and returns 0; otherwise
+/// it returns the status of the last job named.
+pub fn builtin_wait(args: &[&[u8]], io: &Stdio, state: &ExecState) -> i32 {
+    if args.is_empty() {
+        let mut numbers = [0usize; MAX_JOBS];
+        let count = state.jobs.visible(&mut numbers);
+        for &number in &numbers[..count] {
+            if !state.jobs.is_stopped(number) {
+                state.jobs.wait(number, io, false);
+            }
+        }
+        return 0;
+    }
+    
+    let mut status = 0;
+    for &spec in args {
+        status = match state.jobs.find(Some(spec)) {
+            Some(number) if state.jobs.is_stopped(number) => 0,
+            Some(number) => state.jobs.wait(number, io, false),
+            None => {
+                no_such_job(io, b"wait", Some(spec));
+                127
+            }
+        };
+    }
+    status
+}
+
+pub fn builtin_disown(args: &[&[u8]], io: &Stdio, state: &ExecState) -> i32 {
+    if args.is_empty() {
+        return match state.jobs.find(None) {
+            Some(number) => {
+                state.jobs.disown(number);
+                0
+            }
+            None => {
+                no_such_job(io, b"disown", None);
+                1
+            }
+        };
+    }
+    
+    let mut status = 0;
+    for &spec in args {
+        match state.jobs.find(Some(spec)) {
+            Some(number) => state.jobs.disown(number),
+            None => {
+                no_such_job(io, b"disown", Some(spec));
+                status = 1;
+            }
+        }
+    }
+    status
+}
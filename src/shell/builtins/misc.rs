@@ -1,10 +1,106 @@
-use crate::syscalls::{write, STDOUT};
-use crate::shell::parser::expand_env_vars;
-use crate::io::print;
+use crate::syscalls::{write, access, X_OK};
+use crate::shell::builtins::is_builtin;
+use crate::shell::parser::{evaluate_arithmetic, find_in_path, is_reserved_word};
+use crate::shell::storage::{ALIASES, FUNCTIONS, ALIAS_VALUE_SIZE};
+use crate::io::{LineBuffer, Stdio};
 
-pub fn builtin_echo(args: &[u8]) {
-    let mut expanded = [0u8; 512];
-    let len = expand_env_vars(args, &mut expanded);
-    write(STDOUT, &expanded[..len]);
-    print(b"\n");
+pub fn builtin_echo(args: &[&[u8]], io: &Stdio) -> i32 {
+    for (i, arg) in args.iter().enumerate() {
+        if i > 0 {
+            write(io.stdout, b" ");
+        }
+        write(io.stdout, arg);
+    }
+    write(io.stdout, b"\n");
+    0
+}
+
+/// Evaluates each argument as an arithmetic expression. Like in C, the
+/// status is 1 if the last value is 0 and 0 otherwise.
+pub fn builtin_let(args: &[&[u8]], io: &Stdio) -> i32 {
+    if args.is_empty() {
+        write(io.stderr, b"let: expression expected\n");
+        return 1;
+    }
+    
+    let mut value = 0;
+    for &arg in args {
+        match evaluate_arithmetic(arg) {
+            Ok(result) => value = result,
+            Err(err) => {
+                write(io.stderr, b"let: ");
+                write(io.stderr, arg);
+                write(io.stderr, b": ");
+                write(io.stderr, err.as_bytes());
+                write(io.stderr, b"\n");
+                return 1;
+            }
+        }
+    }
+    (value == 0) as i32
+}
+
+/// Tells how each name would be run: as an alias, function, keyword,
+/// builtin or program. The status is 1 if any of them is none of these.
+pub fn builtin_type(args: &[&[u8]], io: &Stdio) -> i32 {
+    let mut status = 0;
+    for &name in args {
+        let mut value = [0u8; ALIAS_VALUE_SIZE];
+        if let Some(len) = ALIASES.get(name, &mut value) {
+            write(io.stdout, name);
+            write(io.stdout, b" is aliased to `");
+            write(io.stdout, &value[..len]);
+            write(io.stdout, b"'\n");
+            continue;
+        }
+        
+        let mut body = LineBuffer::new();
+        match FUNCTIONS.get(name, &mut body) {
+            Ok(true) => {
+                write(io.stdout, name);
+                write(io.stdout, b" is a function\n");
+                write(io.stdout, name);
+                write(io.stdout, b" () ");
+                write(io.stdout, body.as_slice());
+                write(io.stdout, b"\n");
+                continue;
+            }
+            Ok(false) => {}
+            Err(err) => {
+                write(io.stderr, b"type: ");
+                write(io.stderr, err.as_bytes());
+                write(io.stderr, b"\n");
+                status = 1;
+                continue;
+            }
+        }
+        
+        if is_reserved_word(name) {
+            write(io.stdout, name);
+            write(io.stdout, b" is a shell keyword\n");
+            continue;
+        }
+        
+        if is_builtin(name) {
+            write(io.stdout, name);
+            write(io.stdout, b" is a shell builtin\n");
+            continue;
+        }
+        
+        let mut path = [0u8; 256];
+        if !name.is_empty() && find_in_path(name, &mut path) && access(&path, X_OK) == 0 {
+            let len = path.iter().position(|&b| b == 0).unwrap_or(path.len());
+            write(io.stdout, name);
+            write(io.stdout, b" is ");
+            write(io.stdout, &path[..len]);
+            write(io.stdout, b"\n");
+            continue;
+        }
+        
+        write(io.stderr, b"type: ");
+        write(io.stderr, name);
+        write(io.stderr, b": not found\n");
+        status = 1;
+    }
+    status
 }
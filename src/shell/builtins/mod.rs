@@ -1,9 +1,76 @@
+mod alias;
 mod env;
+mod flow;
 mod fs;
+mod history;
+mod jobs;
 mod misc;
+mod script;
 mod server;
 
-pub use env::builtin_export;
-pub use fs::{builtin_pwd, builtin_cd, builtin_ls};
-pub use misc::builtin_echo;
-pub use server::builtin_threads;
+use alias::{builtin_alias, builtin_unalias};
+use env::{builtin_export, builtin_readonly, builtin_env, builtin_unset, builtin_local};
+use flow::{builtin_break, builtin_continue, builtin_return};
+use fs::{builtin_pwd, builtin_cd, builtin_ls};
+use history::builtin_history;
+use jobs::{builtin_jobs, builtin_fg, builtin_bg, builtin_wait, builtin_disown};
+use misc::{builtin_echo, builtin_let, builtin_type};
+use script::builtin_source;
+use server::builtin_threads;
+use crate::shell::executor::ExecState;
+use crate::io::Stdio;
+
+/// Commands handled by the shell itself, including `exit`, which the
+/// executor handles before the others.
+pub const BUILTIN_NAMES: &[&[u8]] = &[
+    b"cd", b"ls", b"pwd", b"export", b"readonly", b"unset", b"echo", b"let", b"env", b"threads",
+    b"jobs", b"fg", b"bg", b"wait", b"disown", b"break", b"continue",
+    b"local", b"return", b"type", b"source", b".", b"alias", b"unalias", b"history", b"exit",
+];
+
+/// Builtins whose `NAME=value` prefixes stay set once they are done, as
+/// POSIX has it for its special builtins.
+const SPECIAL_BUILTINS: &[&[u8]] = &[
+    b"break", b"continue", b".", b"exit", b"export", b"readonly", b"return", b"unset",
+];
+
+pub fn is_builtin(name: &[u8]) -> bool {
+    BUILTIN_NAMES.contains(&name)
+}
+
+pub fn is_special_builtin(name: &[u8]) -> bool {
+    SPECIAL_BUILTINS.contains(&name)
+}
+
+/// Runs `program` if it is a builtin and returns its exit status, or
+/// `None` if there is no such builtin.
+pub fn run_builtin(program: &[u8], args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> Option<i32> {
+    let status = match program {
+        b"cd" => builtin_cd(args, io),
+        b"ls" => builtin_ls(args, io),
+        b"pwd" => builtin_pwd(io),
+        b"export" => builtin_export(args, io),
+        b"readonly" => builtin_readonly(args, io),
+        b"unset" => builtin_unset(args, io),
+        b"echo" => builtin_echo(args, io),
+        b"let" => builtin_let(args, io),
+        b"env" => builtin_env(io),
+        b"threads" => builtin_threads(io),
+        b"jobs" => builtin_jobs(io, state),
+        b"fg" => builtin_fg(args, io, state),
+        b"bg" => builtin_bg(args, io, state),
+        b"wait" => builtin_wait(args, io, state),
+        b"disown" => builtin_disown(args, io, state),
+        b"break" => builtin_break(args, io, state),
+        b"continue" => builtin_continue(args, io, state),
+        b"local" => builtin_local(args, io, state),
+        b"return" => builtin_return(args, io, state),
+        b"type" => builtin_type(args, io),
+        b"source" | b"." => builtin_source(program, args, io, state),
+        b"alias" => builtin_alias(args, io),
+        b"unalias" => builtin_unalias(args, io),
+        b"history" => builtin_history(args, io),
+        _ => return None,
+    };
+    Some(status)
+}
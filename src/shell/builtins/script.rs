@@ -0,0 +1,64 @@
+use crate::syscalls::{write, close, strerror};
+use crate::shell::executor::{ExecState, Flow};
+use crate::shell::script::{open_script, run_script};
+use crate::io::Stdio;
+
+/// Files `source` may run inside each other, so a file sourcing itself
+/// ends with an error instead of overflowing the stack.
+const MAX_SOURCE_DEPTH: usize = 32;
+
+/// `source FILE [ARGS...]` and `. FILE`: runs the commands in FILE in the
+/// current shell, with ARGS as the positional parameters if given.
+pub fn builtin_source(name: &[u8], args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> i32 {
+    let path = match args.first() {
+        Some(path) => *path,
+        None => {
+            write(io.stderr, name);
+            write(io.stderr, b": filename argument required\n");
+            return 2;
+        }
+    };
+    
+    if state.source_depth >= MAX_SOURCE_DEPTH {
+        write(io.stderr, name);
+        write(io.stderr, b": ");
+        write(io.stderr, path);
+        write(io.stderr, b": maximum source nesting level exceeded\n");
+        return 1;
+    }
+    
+    let fd = match open_script(path) {
+        Ok(fd) => fd,
+        Err(err) => {
+            write(io.stderr, name);
+            write(io.stderr, b": ");
+            write(io.stderr, path);
+            write(io.stderr, b": ");
+            write(io.stderr, strerror(err));
+            write(io.stderr, b"\n");
+            return 1;
+        }
+    };
+    
+    let saved_args = state.params.args;
+    if args.len() > 1 && !state.params.args.set(&args[1..]) {
+        write(io.stderr, name);
+        write(io.stderr, b": too many arguments\n");
+        close(fd);
+        return 1;
+    }
+    
+    state.source_depth += 1;
+    let mut status = run_script(fd, io, state);
+    state.source_depth -= 1;
+    close(fd);
+    
+    if let Flow::Return(code) = state.flow {
+        state.flow = Flow::Normal;
+        status = code;
+    }
+    if args.len() > 1 {
+        state.params.args = saved_args;
+    }
+    status
+}
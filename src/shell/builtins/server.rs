@@ -1,17 +1,19 @@
-use crate::io::{print, print_number};
+use crate::syscalls::write;
+use crate::io::{write_number, Stdio};
 
-pub fn builtin_threads() {
+pub fn builtin_threads(io: &Stdio) -> i32 {
     use crate::system::thread::get_thread_stats;
     
     let (active, max) = get_thread_stats();
     
-    print(b"Thread Statistics:\n");
-    print(b"  Active threads: ");
-    print_number(active as i64);
-    print(b"\n  Maximum threads: ");
-    print_number(max as i64);
-    print(b"  Memory per thread: 128 KB (mmap-allocated)\n");
-    print(b"  Total memory used: ");
-    print_number((active * 128) as i64);
-    print(b" KB\n");
+    write(io.stdout, b"Thread Statistics:\n");
+    write(io.stdout, b"  Active threads: ");
+    write_number(io.stdout, active as i64);
+    write(io.stdout, b"\n  Maximum threads: ");
+    write_number(io.stdout, max as i64);
+    write(io.stdout, b"  Memory per thread: 128 KB (mmap-allocated)\n");
+    write(io.stdout, b"  Total memory used: ");
+    write_number(io.stdout, (active * 128) as i64);
+    write(io.stdout, b" KB\n");
+    0
 }
@@ -0,0 +1,86 @@
+use crate::syscalls::{sys_exit, restore_default_signals, SIGINT};
+use crate::shell::parser::{Ast, NodeId, NodeKind, Operator, Token};
+use crate::shell::pipeline::run_pipeline;
+use crate::shell::executor::{redirect_stdio, report_error, fork_shell, ExecState, Flow};
+use crate::shell::jobs::join_process_group;
+use crate::io::Stdio;
+
+/// Runs the command list starting at item `first`: `a; b && c || d & e`.
+/// Each item ended by `&` runs as a background job; a skipped pipeline
+/// leaves `$?` unchanged.
+pub fn run_list(ast: &Ast, first: Option<NodeId>, io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut next = first;
+    
+    while let Some(id) = next {
+        let node = ast.node(id);
+        if let NodeKind::Item { first, background, text } = node.kind {
+            if background {
+                run_background(ast, first, text, io, state);
+                state.params.status = 0;
+            } else {
+                run_and_or(ast, first, io, state);
+            }
+        }
+        
+        if state.flow != Flow::Normal {
+            break;
+        }
+        next = node.next;
+    }
+    
+    state.params.status
+}
+
+/// Runs the and-or list starting at pipeline `first`: `a && b || c`. `&&`
+/// and `||` have equal precedence and bind left to right.
+fn run_and_or(ast: &Ast, first: NodeId, io: &Stdio, state: &mut ExecState) {
+    let mut next = Some(first);
+    
+    while let Some(id) = next {
+        let node = ast.node(id);
+        let run = match node.kind {
+            NodeKind::Pipeline { connector: Operator::And, .. } => state.params.status == 0,
+            NodeKind::Pipeline { connector: Operator::Or, .. } => state.params.status != 0,
+            _ => true,
+        };
+        
+        if run {
+            let status = run_pipeline(ast, id, io, state, false);
+            state.params.status = status;
+            if state.job_control && status == 128 + SIGINT {
+                state.flow = Flow::Interrupt;
+            }
+            if state.flow != Flow::Normal {
+                return;
+            }
+        }
+        next = node.next;
+    }
+}
+
+/// Starts an and-or list as a background job. A lone pipeline is forked
+/// stage by stage; a longer list runs in a forked copy of the shell.
+fn run_background(ast: &Ast, first: NodeId, text: &[Token], io: &Stdio, state: &mut ExecState) {
+    if ast.node(first).next.is_none() {
+        run_pipeline(ast, first, io, state, true);
+        return;
+    }
+    
+    let pid = fork_shell();
+    
+    if pid == 0 {
+        join_process_group(0, 0, false);
+        restore_default_signals();
+        redirect_stdio(io);
+        let mut subshell = state.subshell();
+        run_and_or(ast, first, &Stdio::standard(), &mut subshell);
+        sys_exit(subshell.params.status);
+    } else if pid > 0 {
+        let pid = pid as i32;
+        join_process_group(pid, 0, false);
+        state.params.background_pid = pid;
+        state.jobs.start_background(pid, &[pid], text, io);
+    } else {
+        report_error(io, b"fork failed");
+    }
+}
@@ -0,0 +1,209 @@
+use crate::syscalls::{should_shutdown, write};
+use crate::shell::parser::{expand_words, expand_flat, expand_pattern, pattern_matches, Ast, Compound, NodeId, NodeKind, Token, TokenList};
+use crate::shell::command_list::run_list;
+use crate::shell::executor::{report_error, ExecState, Flow};
+use crate::shell::redirect::{SimpleCommand, OpenFiles, RedirectError};
+use crate::shell::storage::ENV_STORAGE;
+use crate::io::{LineBuffer, Stdio};
+
+/// Runs the compound command `id` (`if`, `while`, `until`, `for`, `case`
+/// or a `{ ...; }` group) in the shell process, with the redirections
+/// written after it.
+pub fn run_compound(ast: &Ast, id: NodeId, io: &Stdio, state: &mut ExecState) -> i32 {
+    let (command, redirects) = match ast.node(id).kind {
+        NodeKind::Compound { command, redirects } => (command, redirects),
+        _ => return 0,
+    };
+    
+    if redirects.is_empty() {
+        return run_command(ast, command, io, state);
+    }
+    
+    let cmd = match SimpleCommand::parse(redirects) {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            report_error(io, err.as_bytes());
+            return 2;
+        }
+    };
+    
+    let mut files = OpenFiles::new();
+    let status = match cmd.apply_redirects(io, &mut state.params, &mut files) {
+        Ok(io) => run_command(ast, command, &io, state),
+        Err(RedirectError::Expansion) => state.expansion_failed(),
+        Err(RedirectError::Failed) => 1,
+    };
+    files.close_all();
+    status
+}
+
+fn run_command(ast: &Ast, command: Compound, io: &Stdio, state: &mut ExecState) -> i32 {
+    match command {
+        Compound::If { first } => run_if(ast, first, io, state),
+        Compound::Loop { condition, body, until } => run_loop(ast, condition, body, until, io, state),
+        Compound::For { name, words, body } => run_for(ast, name, words, body, io, state),
+        Compound::Case { word, first } => run_case(ast, word, first, io, state),
+        Compound::Group { body } => run_list(ast, Some(body), io, state),
+    }
+}
+
+/// Runs the body of the first branch whose condition succeeds. The status
+/// is that of the body, or 0 if no branch ran.
+fn run_if(ast: &Ast, first: NodeId, io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut next = Some(first);
+    
+    while let Some(id) = next {
+        let node = ast.node(id);
+        if let NodeKind::Branch { condition, body } = node.kind {
+            if let Some(condition) = condition {
+                let status = run_list(ast, Some(condition), io, state);
+                if state.flow != Flow::Normal {
+                    return status;
+                }
+                if status != 0 {
+                    next = node.next;
+                    continue;
+                }
+            }
+            return run_list(ast, Some(body), io, state);
+        }
+        next = node.next;
+    }
+    
+    0
+}
+
+/// Settles the flow after a loop body ran and tells whether the loop ends:
+/// a `break` or `continue` aimed at an outer loop, `return`, `exit` or
+/// Ctrl-C leave it with the flow still set.
+fn loop_ends(state: &mut ExecState) -> bool {
+    if should_shutdown() {
+        state.flow = Flow::Interrupt;
+    }
+    
+    match state.flow {
+        Flow::Normal => false,
+        Flow::Break(1) => {
+            state.flow = Flow::Normal;
+            true
+        }
+        Flow::Break(n) => {
+            state.flow = Flow::Break(n - 1);
+            true
+        }
+        Flow::Continue(1) => {
+            state.flow = Flow::Normal;
+            false
+        }
+        Flow::Continue(n) => {
+            state.flow = Flow::Continue(n - 1);
+            true
+        }
+        Flow::Exit(_) | Flow::Return(_) | Flow::Interrupt => true,
+    }
+}
+
+/// `while` runs the body as long as the condition succeeds, `until` as
+/// long as it fails. The status is that of the last body run, or 0.
+fn run_loop(ast: &Ast, condition: NodeId, body: NodeId, until: bool, io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut status = 0;
+    state.loop_depth += 1;
+    
+    loop {
+        run_list(ast, Some(condition), io, state);
+        if state.flow != Flow::Normal {
+            if loop_ends(state) {
+                break;
+            }
+            continue;
+        }
+        if (state.params.status == 0) == until {
+            break;
+        }
+        
+        status = run_list(ast, Some(body), io, state);
+        if loop_ends(state) {
+            break;
+        }
+    }
+    
+    state.loop_depth -= 1;
+    status
+}
+
+/// Runs the body once for every word, or every positional parameter if
+/// there is no `in`, with the word assigned to `name`.
+fn run_for(ast: &Ast, name: &[u8], words: Option<&[Token]>, body: NodeId, io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut values = TokenList::new();
+    match words {
+        Some(words) => {
+            if !expand_words(words, &mut values, io, &mut state.params) {
+                return state.expansion_failed();
+            }
+        }
+        None => {
+            for arg in state.params.args.iter() {
+                values.begin();
+                values.push_slice(arg);
+                values.finish();
+            }
+        }
+    }
+    
+    let mut status = 0;
+    state.loop_depth += 1;
+    
+    for i in 0..values.len() {
+        if let Err(err) = ENV_STORAGE.set(name, values.get(i)) {
+            write(io.stderr, b"reshell: ");
+            write(io.stderr, name);
+            write(io.stderr, b": ");
+            write(io.stderr, err.as_bytes());
+            write(io.stderr, b"\n");
+            status = 1;
+            break;
+        }
+        status = run_list(ast, Some(body), io, state);
+        if loop_ends(state) {
+            break;
+        }
+    }
+    
+    state.loop_depth -= 1;
+    status
+}
+
+/// Runs the body of the first item with a pattern matching `word`. The
+/// status is that of the body, or 0 if no pattern matched.
+fn run_case(ast: &Ast, word: &[u8], first: Option<NodeId>, io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut subject = LineBuffer::new();
+    if expand_flat(word, io, &mut state.params, &mut subject).is_none() {
+        return state.expansion_failed();
+    }
+    
+    let mut next = first;
+    while let Some(id) = next {
+        let node = ast.node(id);
+        if let NodeKind::CaseItem { patterns, body } = node.kind {
+            for token in patterns {
+                let pattern = match token {
+                    Token::Word(pattern) => pattern,
+                    _ => continue,
+                };
+                let mut expanded = LineBuffer::new();
+                if expand_pattern(pattern, io, &mut state.params, &mut expanded).is_none() {
+                    return state.expansion_failed();
+                }
+                if pattern_matches(expanded.as_slice(), subject.as_slice()) {
+                    return match body {
+                        Some(body) => run_list(ast, Some(body), io, state),
+                        None => 0,
+                    };
+                }
+            }
+        }
+        next = node.next;
+    }
+    
+    0
+}
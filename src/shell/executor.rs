@@ -1,10 +1,94 @@
-use crate::syscalls::{fork, execve, waitpid, sys_exit, nanosleep};
-use crate::utils::{trim_newline, bytes_equal, split_first_word};
-use crate::shell::builtins::*;
-use crate::shell::parser::find_in_path;
-use crate::io::{print, print_number, StaticBuffer};
+use crate::syscalls::{execve, sys_exit, nanosleep, write, dup, dup2, restore_default_signals, STDIN, STDOUT, STDERR};
+use crate::utils::{trim_newline, bytes_equal};
+use crate::shell::builtins::{run_builtin, is_special_builtin};
+use crate::shell::parser::{find_in_path, expand_flat, lex, parse, expand_words, expand_aliases, Ast, NodeId, NodeKind, ParseError, Token, TokenList, MAX_TOKENS, MAX_INPUT_TOKENS, ALIAS_LINE_SIZE};
+use crate::shell::command_list::run_list;
+use crate::shell::compound::run_compound;
+use crate::shell::functions::{call_function, define_function, LocalVars};
+use crate::shell::redirect::{SimpleCommand, OpenFiles, RedirectError};
+use crate::shell::jobs::{join_process_group, terminal_available, JobTable, CONSOLE_JOBS};
+use crate::shell::storage::{ENV_STORAGE, ALIASES, FUNCTIONS, HISTORY, SHELL_NAME, EXPORTED, Environment, Params, CONSOLE_PARAMS};
+use crate::shell::session::fork_holding_sessions;
+use crate::io::{print, write_number, LineBuffer, Stdio};
 
-static CMD_BUF: StaticBuffer = StaticBuffer::new();
+/// Room for the path of a program to run.
+const PATH_SIZE: usize = 4096;
+
+/// Control flow requested by a builtin, checked after every pipeline.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Normal,
+    Exit(i32),
+    /// `break N`: leave the N innermost loops.
+    Break(usize),
+    /// `continue N`: go on with the next iteration of the Nth loop.
+    Continue(usize),
+    /// `return N`: leave the running function or sourced file with status N.
+    Return(i32),
+    /// A foreground job was killed by Ctrl-C, which stops the rest of the
+    /// command line as well.
+    Interrupt,
+}
+
+/// State carried through the execution of one command line.
+pub struct ExecState {
+    pub flow: Flow,
+    /// Foreground jobs get the terminal, so Ctrl-C and Ctrl-Z reach them.
+    pub job_control: bool,
+    /// Loops around the running command, which `break` and `continue` may leave.
+    pub loop_depth: usize,
+    /// Function calls around the running command.
+    pub call_depth: usize,
+    /// Variables shadowed by `local` in the running functions.
+    pub locals: LocalVars,
+    /// First entry of `locals` that belongs to the innermost function.
+    pub frame_start: usize,
+    /// Files being run by `source` around the running command.
+    pub source_depth: usize,
+    /// Whether the commands come from the user. A word that can't be
+    /// expanded, as with `${NAME:?}`, ends any other shell.
+    pub interactive: bool,
+    /// `$?` and the positional parameters of the session.
+    pub params: Params,
+    /// Jobs of the session.
+    pub jobs: &'static JobTable,
+}
+
+impl ExecState {
+    pub const fn new() -> Self {
+        Self {
+            flow: Flow::Normal,
+            job_control: false,
+            loop_depth: 0,
+            call_depth: 0,
+            locals: LocalVars::new(),
+            frame_start: 0,
+            source_depth: 0,
+            interactive: false,
+            params: Params::new(),
+            jobs: &CONSOLE_JOBS,
+        }
+    }
+    
+    /// State for a forked copy of the shell, which starts out with the
+    /// same parameters and jobs.
+    pub fn subshell(&self) -> Self {
+        let mut state = Self::new();
+        state.params = self.params;
+        state.jobs = self.jobs;
+        state
+    }
+    
+    /// Called when words of a command could not be expanded; the error has
+    /// been reported. A shell that isn't interactive exits, as POSIX asks.
+    /// Returns the status of the failed command.
+    pub fn expansion_failed(&mut self) -> i32 {
+        if !self.interactive {
+            self.flow = Flow::Exit(1);
+        }
+        1
+    }
+}
 
 fn cleanup_and_exit(code: i32) -> ! {
     print(b"\n[INFO] Shutting down...\n");
@@ -28,71 +112,400 @@ fn cleanup_and_exit(code: i32) -> ! {
 }
 
 pub fn execute_command(cmd: &[u8]) {
-    let cmd = trim_newline(cmd);
+    let mut state = ExecState::new();
+    state.job_control = terminal_available();
+    state.interactive = true;
+    state.params = CONSOLE_PARAMS.load();
+    run_line(cmd, &Stdio::standard(), &mut state);
+    CONSOLE_PARAMS.store(&state.params);
     
-    if cmd.is_empty() {
-        return;
+    if let Flow::Exit(code) = state.flow {
+        cleanup_and_exit(code);
+    }
+}
+
+/// Parses and runs one command line with the given standard streams.
+/// Returns the exit status of the last command, which is also stored as `$?`.
+pub fn run_line(cmd: &[u8], io: &Stdio, state: &mut ExecState) -> i32 {
+    if ALIASES.is_empty() {
+        return run_source(cmd, io, state);
     }
+    run_with_aliases(cmd, io, state)
+}
 
-    if bytes_equal(cmd, b"exit") {
-        cleanup_and_exit(0);
+/// Kept out of line so the expanded copy of the line only takes stack
+/// space while aliases are defined.
+#[inline(never)]
+fn run_with_aliases(cmd: &[u8], io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut expanded = [0u8; ALIAS_LINE_SIZE];
+    match expand_aliases(cmd, &mut expanded) {
+        Ok(len) => run_source(&expanded[..len], io, state),
+        Err(err) => {
+            report_error(io, err.as_bytes());
+            state.params.status = 2;
+            2
+        }
     }
+}
 
-    let (program, args) = split_first_word(cmd);
+/// Like `run_line`, but without alias expansion, for text that already
+/// went through it such as a function body.
+pub fn run_source(cmd: &[u8], io: &Stdio, state: &mut ExecState) -> i32 {
+    let cmd = trim_newline(cmd);
+    
+    if cmd.is_empty() {
+        return state.params.status;
+    }
+    
+    let mut tokens = [Token::Word(&[]); MAX_INPUT_TOKENS];
+    let count = match lex(cmd, &mut tokens) {
+        Ok(count) => count,
+        Err(err) => {
+            report_error(io, err.as_bytes());
+            state.params.status = 2;
+            return 2;
+        }
+    };
     
-    if bytes_equal(program, b"cd") {
-        builtin_cd(args);
-        return;
+    let mut ast = Ast::new();
+    if let Err(err) = parse(cmd, &tokens[..count], &mut ast) {
+        report_parse_error(io, err);
+        state.params.status = 2;
+        return 2;
     }
     
-    if bytes_equal(program, b"ls") {
-        builtin_ls(args);
-        return;
+    run_list(&ast, ast.root, io, state)
+}
+
+fn report_parse_error(io: &Stdio, err: ParseError) {
+    match err {
+        ParseError::Incomplete => report_error(io, b"syntax error: unexpected end of file"),
+        ParseError::Unexpected(token) => {
+            write(io.stderr, b"reshell: syntax error near unexpected token `");
+            write(io.stderr, token);
+            write(io.stderr, b"'\n");
+        }
+        ParseError::TooComplex => report_error(io, b"command too complex"),
+    }
+}
+
+/// Turns a raw `waitpid` status into a shell exit status: the exit code for
+/// a normal exit, or 128 plus the signal number for a killed process.
+pub fn decode_wait_status(status: i32) -> i32 {
+    let signal = status & 0x7f;
+    if signal == 0 {
+        (status >> 8) & 0xff
+    } else {
+        128 + signal
     }
+}
+
+/// Forks the shell. Only the calling thread goes on in the child, so a
+/// lock another thread held at that moment would stay taken there for
+/// good; every shared store is locked across the fork instead, always in
+/// the same order.
+pub fn fork_shell() -> isize {
+    ENV_STORAGE.hold(|| {
+        FUNCTIONS.hold(|| {
+            ALIASES.hold(|| {
+                HISTORY.hold(|| SHELL_NAME.hold(|| CONSOLE_PARAMS.hold(fork_holding_sessions)))
+            })
+        })
+    })
+}
+
+pub fn report_error(io: &Stdio, msg: &[u8]) {
+    write(io.stderr, b"reshell: ");
+    write(io.stderr, msg);
+    write(io.stderr, b"\n");
+}
+
+/// Runs command `id` of a pipeline in the shell process.
+pub fn run_command(ast: &Ast, id: NodeId, io: &Stdio, state: &mut ExecState) -> i32 {
+    match ast.node(id).kind {
+        NodeKind::Simple(words) => run_simple_command(words, io, state),
+        NodeKind::Function { name, body } => define_function(name, body, io),
+        _ => run_compound(ast, id, io, state),
+    }
+}
+
+/// Runs command `id` inside an already forked child, e.g. a pipeline
+/// stage, that got `state` from the shell. Never returns.
+pub fn exec_command(ast: &Ast, id: NodeId, state: &ExecState) -> ! {
+    match ast.node(id).kind {
+        NodeKind::Simple(words) => exec_simple_command(words, state),
+        _ => sys_exit(run_command(ast, id, &Stdio::standard(), &mut state.subshell())),
+    }
+}
+
+/// Runs a simple command in the shell process. Builtins run in place,
+/// external programs are forked and waited for.
+fn run_simple_command(words: &[Token], io: &Stdio, state: &mut ExecState) -> i32 {
+    let cmd = match SimpleCommand::parse(words) {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            report_error(io, err.as_bytes());
+            return 2;
+        }
+    };
     
-    if bytes_equal(program, b"pwd") {
-        builtin_pwd();
-        return;
+    let mut files = OpenFiles::new();
+    let status = match cmd.apply_redirects(io, &mut state.params, &mut files) {
+        Ok(cmd_io) => run_command_words(cmd.words(), cmd.assignments(), &cmd_io, state),
+        Err(RedirectError::Expansion) => state.expansion_failed(),
+        Err(RedirectError::Failed) => 1,
+    };
+    files.close_all();
+    status
+}
+
+/// Expands the value of each `NAME=value` word and sets the variable.
+/// With `temporary`, the variables are exported and their old values are
+/// saved in `state.locals` from entry `first` on, to be put back once the
+/// command they were given for is done. Returns the status to fail with.
+fn assign_variables(assignments: &[&[u8]], temporary: bool, io: &Stdio, state: &mut ExecState) -> Result<(), i32> {
+    let first = state.locals.len();
+    for &word in assignments {
+        let equals = word.iter().position(|&b| b == b'=').unwrap_or(word.len());
+        let name = &word[..equals];
+        let mut value = LineBuffer::new();
+        if expand_flat(&word[equals + 1..], io, &mut state.params, &mut value).is_none() {
+            return Err(state.expansion_failed());
+        }
+        
+        let result = if temporary {
+            state.locals.save(name, first).and_then(|()| ENV_STORAGE.declare(name, Some(value.as_slice()), EXPORTED))
+        } else {
+            ENV_STORAGE.set(name, value.as_slice())
+        };
+        if let Err(err) = result {
+            write(io.stderr, b"reshell: ");
+            write(io.stderr, name);
+            write(io.stderr, b": ");
+            write(io.stderr, err.as_bytes());
+            write(io.stderr, b"\n");
+            return Err(1);
+        }
+    }
+    Ok(())
+}
+
+fn run_command_words(words: &[Token], assignments: &[&[u8]], io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut tokens = TokenList::new();
+    if !expand_words(words, &mut tokens, io, &mut state.params) {
+        return state.expansion_failed();
     }
     
-    if bytes_equal(program, b"export") {
-        builtin_export(args);
-        return;
+    // Without a command the assignments are all there is to do
+    if tokens.is_empty() {
+        return match assign_variables(assignments, false, io, state) {
+            Ok(()) => 0,
+            Err(status) => status,
+        };
     }
     
-    if bytes_equal(program, b"echo") {
-        builtin_echo(args);
-        return;
+    let mut argv: [&[u8]; MAX_TOKENS] = [&[]; MAX_TOKENS];
+    let argc = tokens.words(&mut argv);
+    let program = argv[0];
+    
+    // A special builtin keeps its assignments; anything else gets them
+    // only while it runs
+    let temporary = !is_special_builtin(program);
+    let first = state.locals.len();
+    if let Err(status) = assign_variables(assignments, temporary, io, state) {
+        state.locals.restore(first);
+        return status;
+    }
+    let end = state.locals.len();
+    let status = run_program(program, &argv[1..argc], &tokens, words, first, io, state);
+    // Only the assignments' entries are put back: a `local` run as the
+    // command saves its variables after them, until the function returns
+    state.locals.restore_range(first, end);
+    status
+}
+
+/// Runs the function, builtin or external program `program`. Variables
+/// saved in `state.locals` from entry `first` on are put back before an
+/// external program is started, since it has its own copy of them.
+fn run_program(program: &[u8], args: &[&[u8]], tokens: &TokenList, words: &[Token], first: usize, io: &Stdio, state: &mut ExecState) -> i32 {
+    if let Some(status) = call_function(program, args, io, state) {
+        return status;
+    }
+
+    if bytes_equal(program, b"exit") {
+        return match status_argument(b"exit", args, io, state.params.status) {
+            Some(code) => {
+                state.flow = Flow::Exit(code);
+                code
+            }
+            None => 1,
+        };
     }
     
-    if bytes_equal(program, b"env") {
-        builtin_export(b"");
-        return;
+    if let Some(status) = run_builtin(program, args, io, state) {
+        return status;
     }
     
-    if bytes_equal(program, b"threads") {
-        builtin_threads();
-        return;
+    // Everything the child needs from the variables is copied now, since
+    // other threads may change them while it starts
+    let mut path = [0u8; PATH_SIZE];
+    let mut env = Environment::new();
+    let prepared = prepare_external(program, &mut path, &mut env, io);
+    state.locals.restore(first);
+    if let Err(status) = prepared {
+        return status;
     }
     
-    let pid = fork();
+    let pid = fork_shell();
     
     if pid == 0 {
-        CMD_BUF.with_mut(|cmd_buf| {
-            find_in_path(program, cmd_buf);
-            
-            let argv: [*const u8; 2] = [cmd_buf.as_ptr(), core::ptr::null()];
-            let ret = execve(cmd_buf, &argv);
-            
-            print(b"Command not found (errno: ");
-            print_number(-ret as i64);
-            print(b")\n");
-            sys_exit(1);
-        })
+        join_process_group(0, 0, state.job_control);
+        restore_default_signals();
+        redirect_stdio(io);
+        exec_external(tokens, &path, &env);
     } else if pid > 0 {
-        let mut status: i32 = 0;
-        waitpid(pid as i32, &mut status);
+        let pid = pid as i32;
+        join_process_group(pid, 0, false);
+        state.jobs.run_foreground(pid, &[pid], words, io, state.job_control)
     } else {
-        print(b"Fork failed\n");
+        write(io.stderr, b"Fork failed\n");
+        1
+    }
+}
+
+/// Runs a simple command inside an already forked child. Never returns.
+fn exec_simple_command(words: &[Token], parent: &ExecState) -> ! {
+    let standard = Stdio::standard();
+    let mut state = parent.subshell();
+    let cmd = match SimpleCommand::parse(words) {
+        Ok(cmd) => cmd,
+        Err(err) => {
+            report_error(&standard, err.as_bytes());
+            sys_exit(2);
+        }
+    };
+    
+    let mut files = OpenFiles::new();
+    let io = match cmd.apply_redirects(&standard, &mut state.params, &mut files) {
+        Ok(io) => io,
+        Err(_) => sys_exit(1),
+    };
+    redirect_stdio(&io);
+    
+    let mut tokens = TokenList::new();
+    if !expand_words(cmd.words(), &mut tokens, &standard, &mut state.params) {
+        sys_exit(1);
+    }
+    
+    // The process is gone once the command is done, so its assignments
+    // never need to be undone
+    if let Err(status) = assign_variables(cmd.assignments(), !tokens.is_empty(), &standard, &mut state) {
+        sys_exit(status);
+    }
+    if tokens.is_empty() {
+        sys_exit(0);
+    }
+    
+    let mut argv: [&[u8]; MAX_TOKENS] = [&[]; MAX_TOKENS];
+    let argc = tokens.words(&mut argv);
+    let program = argv[0];
+    
+    if let Some(status) = call_function(program, &argv[1..argc], &standard, &mut state) {
+        sys_exit(status);
+    }
+    
+    if bytes_equal(program, b"exit") {
+        sys_exit(status_argument(b"exit", &argv[1..argc], &standard, state.params.status).unwrap_or(1));
+    }
+    
+    if let Some(status) = run_builtin(program, &argv[1..argc], &standard, &mut state) {
+        sys_exit(status);
     }
+    
+    let mut path = [0u8; PATH_SIZE];
+    let mut env = Environment::new();
+    if let Err(status) = prepare_external(program, &mut path, &mut env, &standard) {
+        sys_exit(status);
+    }
+    exec_external(&tokens, &path, &env);
+}
+
+/// Parses the status argument of `exit` or `return`, defaulting to
+/// `last`, the last exit status. Errors are reported as coming from `name`.
+pub fn status_argument(name: &[u8], args: &[&[u8]], io: &Stdio, last: i32) -> Option<i32> {
+    let arg = match args.first() {
+        Some(arg) => *arg,
+        None => return Some(last),
+    };
+    
+    let (negative, digits) = match arg.first() {
+        Some(b'-') => (true, &arg[1..]),
+        _ => (false, arg),
+    };
+    
+    if digits.is_empty() || digits.len() > 10 || !digits.iter().all(|b| b.is_ascii_digit()) {
+        write(io.stderr, name);
+        write(io.stderr, b": ");
+        write(io.stderr, arg);
+        write(io.stderr, b": numeric argument required\n");
+        return None;
+    }
+    
+    let value = digits.iter().fold(0i64, |acc, &b| acc * 10 + (b - b'0') as i64);
+    let value = if negative { -value } else { value };
+    Some((value & 0xff) as i32)
+}
+
+/// Moves the streams in `io` onto fds 0, 1 and 2 of a forked child.
+pub fn redirect_stdio(io: &Stdio) {
+    let mut fds = [io.stdin, io.stdout, io.stderr];
+    
+    // A stream may refer to one of the standard fds that is about to be
+    // replaced (e.g. `2>&1 >file`), so move such sources out of the way first.
+    for (target, fd) in fds.iter_mut().enumerate() {
+        if *fd != target as i32 && (0..=STDERR).contains(fd) {
+            let copy = dup(*fd);
+            if copy >= 0 {
+                *fd = copy as i32;
+            }
+        }
+    }
+    
+    let targets = [STDIN, STDOUT, STDERR];
+    for i in 0..3 {
+        if fds[i] != targets[i] {
+            dup2(fds[i], targets[i]);
+        }
+    }
+}
+
+/// Finds the file to run for `program` and copies the environment it
+/// gets. Returns the exit status to fail with if that doesn't work out.
+fn prepare_external(program: &[u8], path: &mut [u8], env: &mut Environment, io: &Stdio) -> Result<(), i32> {
+    if !find_in_path(program, path) {
+        write(io.stderr, program);
+        write(io.stderr, b": command not found\n");
+        return Err(127);
+    }
+    if !ENV_STORAGE.environment(env) {
+        report_error(io, b"out of memory");
+        return Err(1);
+    }
+    Ok(())
+}
+
+/// Runs the program at `path` with the words of `tokens` as its
+/// arguments and `env` as its environment.
+fn exec_external(tokens: &TokenList, path: &[u8], env: &Environment) -> ! {
+    let mut argv: [*const u8; MAX_TOKENS + 1] = [core::ptr::null(); MAX_TOKENS + 1];
+    for (i, arg) in argv.iter_mut().enumerate().take(tokens.len()) {
+        *arg = tokens.as_ptr(i);
+    }
+    
+    let ret = execve(path, &argv, env.envp());
+    
+    write(STDERR, b"Command not found (errno: ");
+    write_number(STDERR, -ret as i64);
+    write(STDERR, b")\n");
+    sys_exit(127);
 }
@@ -0,0 +1,173 @@
+use crate::syscalls::write;
+use crate::shell::executor::{run_source, ExecState, Flow};
+use crate::shell::storage::{ENV_STORAGE, FUNCTIONS};
+use crate::io::{LineBuffer, Stdio};
+
+/// Nested function calls allowed, so runaway recursion ends with an error
+/// instead of overflowing the stack.
+const MAX_CALL_DEPTH: usize = 32;
+const MAX_LOCALS: usize = 32;
+
+fn report(io: &Stdio, name: &[u8], msg: &[u8]) {
+    write(io.stderr, b"reshell: ");
+    write(io.stderr, name);
+    write(io.stderr, b": ");
+    write(io.stderr, msg);
+    write(io.stderr, b"\n");
+}
+
+/// Runs a function definition: stores `body` as function `name`.
+pub fn define_function(name: &[u8], body: &[u8], io: &Stdio) -> i32 {
+    match FUNCTIONS.define(name, body) {
+        Ok(()) => 0,
+        Err(err) => {
+            report(io, name, err.as_bytes());
+            1
+        }
+    }
+}
+
+/// Runs function `name` with `args` as its positional parameters and
+/// returns its status, or `None` if there is no such function.
+pub fn call_function(name: &[u8], args: &[&[u8]], io: &Stdio, state: &mut ExecState) -> Option<i32> {
+    // Copied out so the table isn't locked while the body runs, and only
+    // once the name is found: every simple command looks here first
+    let mut body = LineBuffer::new();
+    match FUNCTIONS.get(name, &mut body) {
+        Ok(true) => {}
+        Ok(false) => return None,
+        Err(err) => {
+            report(io, name, err.as_bytes());
+            return Some(1);
+        }
+    }
+    
+    if state.call_depth >= MAX_CALL_DEPTH {
+        report(io, name, b"maximum function nesting level exceeded");
+        return Some(1);
+    }
+    
+    let saved_args = state.params.args;
+    if !state.params.args.set(args) {
+        report(io, name, b"too many arguments");
+        return Some(1);
+    }
+    
+    // Loops of the caller can't be left with `break` from inside the function
+    let loop_depth = core::mem::replace(&mut state.loop_depth, 0);
+    let frame_start = core::mem::replace(&mut state.frame_start, state.locals.len());
+    state.call_depth += 1;
+    
+    // Aliases in the body were expanded when the function was defined
+    let mut status = run_source(body.as_slice(), io, state);
+    if let Flow::Return(code) = state.flow {
+        state.flow = Flow::Normal;
+        status = code;
+    }
+    
+    state.call_depth -= 1;
+    state.locals.restore(state.frame_start);
+    state.frame_start = frame_start;
+    state.loop_depth = loop_depth;
+    state.params.args = saved_args;
+    Some(status)
+}
+
+#[derive(Clone, Copy)]
+struct SavedVar {
+    start: usize,
+    name_len: usize,
+    /// `None` if the variable didn't exist.
+    flags: Option<u8>,
+    /// `None` if the variable was unset.
+    value_len: Option<usize>,
+}
+
+/// Variables shadowed by `local`, with the values and flags they get back
+/// when their function returns.
+pub struct LocalVars {
+    /// The name of each saved variable followed by its value
+    buf: LineBuffer,
+    vars: [SavedVar; MAX_LOCALS],
+    count: usize,
+}
+
+impl LocalVars {
+    pub const fn new() -> Self {
+        Self {
+            buf: LineBuffer::new(),
+            vars: [SavedVar { start: 0, name_len: 0, flags: None, value_len: None }; MAX_LOCALS],
+            count: 0,
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.count
+    }
+    
+    fn name(&self, index: usize) -> &[u8] {
+        let var = &self.vars[index];
+        &self.buf.as_slice()[var.start..var.start + var.name_len]
+    }
+    
+    /// Saves the current value and flags of `name`, unless the function
+    /// whose variables start at entry `first` already made it local.
+    pub fn save(&mut self, name: &[u8], first: usize) -> Result<(), &'static str> {
+        if (first..self.count).any(|i| self.name(i) == name) {
+            return Ok(());
+        }
+        if self.count >= MAX_LOCALS {
+            return Err("too many local variables");
+        }
+        
+        let start = self.buf.len();
+        if !self.buf.push(name) {
+            return Err("out of memory");
+        }
+        let saved = ENV_STORAGE.copy(name, &mut self.buf);
+        let (flags, value_len) = match saved {
+            Ok(Some((flags, has_value))) => (Some(flags), has_value.then(|| self.buf.len() - start - name.len())),
+            Ok(None) => (None, None),
+            Err(err) => {
+                self.buf.remove(start..self.buf.len());
+                return Err(err);
+            }
+        };
+        self.vars[self.count] = SavedVar { start, name_len: name.len(), flags, value_len };
+        self.count += 1;
+        Ok(())
+    }
+    
+    /// Puts back the variables saved since entry `first`, newest first.
+    pub fn restore(&mut self, first: usize) {
+        self.restore_range(first, self.count);
+    }
+    
+    /// Puts back the variables saved in entries `first..end`, newest first.
+    /// The entries after them move down to take their place.
+    pub fn restore_range(&mut self, first: usize, end: usize) {
+        let end = end.min(self.count);
+        if first >= end {
+            return;
+        }
+        
+        for var in self.vars[first..end].iter().rev() {
+            let buf = self.buf.as_slice();
+            let name = &buf[var.start..var.start + var.name_len];
+            let value_start = var.start + var.name_len;
+            let value = var.value_len.map(|len| &buf[value_start..value_start + len]);
+            // Nothing to do about a failure; the saved value is lost either way
+            let _ = ENV_STORAGE.restore(name, var.flags.map(|flags| (flags, value)));
+        }
+        
+        let start = self.vars[first].start;
+        let next = if end < self.count { self.vars[end].start } else { self.buf.len() };
+        let removed = next - start;
+        self.buf.remove(start..next);
+        self.vars.copy_within(end..self.count, first);
+        self.count -= end - first;
+        for var in &mut self.vars[first..self.count] {
+            var.start -= removed;
+        }
+    }
+}
@@ -0,0 +1,287 @@
+use crate::syscalls::{open, close, read, write, EINTR, O_RDONLY, O_WRONLY, O_CREAT, O_TRUNC, O_APPEND, O_CLOEXEC};
+use crate::utils::trim_newline;
+use crate::shell::storage::{ENV_STORAGE, HISTORY, HISTORY_LINE_SIZE};
+
+const HISTORY_FILE: &[u8] = b"/.reshell_history";
+const PATH_SIZE: usize = 256;
+
+/// `$HOME/.reshell_history`, NUL-terminated.
+fn history_path(path: &mut [u8; PATH_SIZE]) -> Option<&[u8]> {
+    let len = ENV_STORAGE.get(b"HOME", path)?;
+    if len == 0 || len + HISTORY_FILE.len() >= PATH_SIZE {
+        return None;
+    }
+    path[len..len + HISTORY_FILE.len()].copy_from_slice(HISTORY_FILE);
+    path[len + HISTORY_FILE.len()] = 0;
+    Some(&path[..len + HISTORY_FILE.len() + 1])
+}
+
+fn open_history(flags: i32) -> Option<i32> {
+    let mut path = [0u8; PATH_SIZE];
+    let fd = open(history_path(&mut path)?, flags | O_CLOEXEC, 0o600);
+    if fd < 0 { None } else { Some(fd as i32) }
+}
+
+/// Writes `entry` as one line of the history file: backslashes and the
+/// newlines of multi-line commands are escaped.
+fn write_entry(fd: i32, entry: &[u8]) {
+    let mut buf = [0u8; 512];
+    let mut len = 0;
+    for &b in entry {
+        if len + 2 > buf.len() {
+            write(fd, &buf[..len]);
+            len = 0;
+        }
+        let escaped: &[u8] = match b {
+            b'\\' => b"\\\\",
+            b'\n' => b"\\n",
+            _ => core::slice::from_ref(&b),
+        };
+        buf[len..len + escaped.len()].copy_from_slice(escaped);
+        len += escaped.len();
+    }
+    buf[len] = b'\n';
+    write(fd, &buf[..len + 1]);
+}
+
+/// Reads the history file into the history, dropping the oldest commands
+/// past the size limit. The file is rewritten if it held more than is
+/// kept, so it doesn't grow from one run to the next.
+pub fn load_history() {
+    let fd = match open_history(O_RDONLY) {
+        Some(fd) => fd,
+        None => return,
+    };
+    
+    let mut entry = [0u8; HISTORY_LINE_SIZE];
+    let mut len = 0;
+    let mut escaped = false;
+    let mut too_long = false;
+    let mut lines = 0;
+    let mut buf = [0u8; 512];
+    
+    loop {
+        let n = read(fd, &mut buf);
+        if n == -EINTR {
+            continue;
+        }
+        if n <= 0 {
+            break;
+        }
+        
+        for &b in &buf[..n as usize] {
+            let b = match (escaped, b) {
+                (false, b'\n') => {
+                    if !too_long {
+                        HISTORY.add(&entry[..len]);
+                    }
+                    lines += 1;
+                    len = 0;
+                    too_long = false;
+                    continue;
+                }
+                (false, b'\\') => {
+                    escaped = true;
+                    continue;
+                }
+                (true, b'n') => b'\n',
+                _ => b,
+            };
+            escaped = false;
+            
+            if len == entry.len() {
+                too_long = true;
+            } else {
+                entry[len] = b;
+                len += 1;
+            }
+        }
+    }
+    close(fd);
+    
+    if lines != HISTORY.len() {
+        save_history();
+    }
+}
+
+/// Replaces the history file with the commands in the history.
+pub fn save_history() {
+    if let Some(fd) = open_history(O_WRONLY | O_CREAT | O_TRUNC) {
+        HISTORY.for_each(0, |_, entry| write_entry(fd, entry));
+        close(fd);
+    }
+}
+
+/// Adds a command about to run to the history and appends it to the
+/// history file, unless it repeats the previous one.
+pub fn record_command(command: &[u8]) {
+    let command = trim_newline(command);
+    if !HISTORY.add(command) {
+        return;
+    }
+    if let Some(fd) = open_history(O_WRONLY | O_CREAT | O_APPEND) {
+        write_entry(fd, command);
+        close(fd);
+    }
+}
+
+/// Why a line could not be expanded. Refers to the part of the line at
+/// fault.
+pub enum HistoryError<'a> {
+    EventNotFound(&'a [u8]),
+    SubstitutionFailed(&'a [u8]),
+    TooLong,
+}
+
+impl HistoryError<'_> {
+    /// Writes the error message, in pieces, with `write`.
+    pub fn report<F>(&self, mut write: F) where F: FnMut(&[u8]) {
+        write(b"reshell: ");
+        match *self {
+            HistoryError::EventNotFound(event) => {
+                write(event);
+                write(b": event not found\n");
+            }
+            HistoryError::SubstitutionFailed(substitution) => {
+                write(b":s");
+                write(substitution);
+                write(b": substitution failed\n");
+            }
+            HistoryError::TooLong => write(b"command too long after history expansion\n"),
+        }
+    }
+}
+
+struct Output<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Output<'_> {
+    fn push<'e>(&mut self, s: &[u8]) -> Result<(), HistoryError<'e>> {
+        if self.len + s.len() > self.buf.len() {
+            return Err(HistoryError::TooLong);
+        }
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s);
+        self.len += s.len();
+        Ok(())
+    }
+    
+    /// Appends the command `designator` refers to: `!`, `N`, `-N` or a
+    /// prefix. Returns `false` if there is no such command.
+    fn push_event<'e>(&mut self, designator: &[u8]) -> Result<bool, HistoryError<'e>> {
+        let previous = HISTORY.next_number() - 1;
+        let mut entry = [0u8; HISTORY_LINE_SIZE];
+        let len = match designator {
+            b"!" => HISTORY.get(previous, &mut entry),
+            [b'-', digits @ ..] => match parse_number(digits) {
+                Some(n) if n > 0 && n <= previous => HISTORY.get(previous + 1 - n, &mut entry),
+                _ => None,
+            },
+            [b'0'..=b'9', ..] => parse_number(designator).and_then(|n| HISTORY.get(n, &mut entry)),
+            _ => HISTORY.find_prefix(designator, &mut entry),
+        };
+        match len {
+            Some(len) => self.push(&entry[..len]).map(|_| true),
+            None => Ok(false),
+        }
+    }
+}
+
+fn parse_number(digits: &[u8]) -> Option<usize> {
+    if digits.is_empty() || digits.len() > 9 {
+        return None;
+    }
+    Some(digits.iter().fold(0, |acc, &b| acc * 10 + (b - b'0') as usize))
+}
+
+/// `^old^new^rest`: the previous command with the first `old` replaced.
+fn quick_substitution<'a>(line: &'a [u8], out: &mut Output) -> Result<(), HistoryError<'a>> {
+    let text = trim_newline(line);
+    let mut parts = text[1..].splitn(3, |&b| b == b'^');
+    let old = parts.next().unwrap_or(&[]);
+    let new = parts.next().unwrap_or(&[]);
+    let rest = parts.next().unwrap_or(&[]);
+    let substitution = &text[..text.len() - rest.len()];
+    
+    let mut previous = [0u8; HISTORY_LINE_SIZE];
+    let number = HISTORY.next_number() - 1;
+    let len = HISTORY.get(number, &mut previous).ok_or(HistoryError::EventNotFound(substitution))?;
+    let previous = &previous[..len];
+    
+    let at = match previous.windows(old.len().max(1)).position(|w| w == old) {
+        Some(at) if !old.is_empty() => at,
+        _ => return Err(HistoryError::SubstitutionFailed(substitution)),
+    };
+    
+    out.push(&previous[..at])?;
+    out.push(new)?;
+    out.push(&previous[at + old.len()..])?;
+    out.push(rest)?;
+    out.push(&line[text.len()..])
+}
+
+/// Where an event designator such as `!!` or `!prefix` ends; words end at
+/// blanks and at the characters that separate commands.
+fn event_end(line: &[u8], start: usize) -> usize {
+    let rest = &line[start..];
+    let len = match rest.first() {
+        Some(b'!') => 1,
+        Some(b'-') | Some(b'0'..=b'9') => 1 + rest[1..].iter().take_while(|b| b.is_ascii_digit()).count(),
+        _ => rest.iter().take_while(|&&b| !matches!(b, b' ' | b'\t' | b'\n' | b';' | b'&' | b'|'
+            | b'<' | b'>' | b'(' | b')' | b'"' | b'\'' | b'`')).count(),
+    };
+    start + len
+}
+
+/// Writes `line` to `out` with its history references replaced: `!!` is
+/// the previous command, `!N` command number N, `!-N` the Nth previous
+/// one and `!prefix` the newest starting with `prefix`. A line starting
+/// with `^old^new` repeats the previous command with `old` replaced by
+/// `new`. Nothing is replaced inside single quotes or after a backslash,
+/// and a `!` followed by a blank, `=` or `(` is left alone.
+///
+/// Returns the length of the result, or `None` if there was nothing to
+/// replace and `line` can be used as it is.
+pub fn expand_history<'a>(line: &'a [u8], out: &mut [u8]) -> Result<Option<usize>, HistoryError<'a>> {
+    let mut output = Output { buf: out, len: 0 };
+    
+    if line.first() == Some(&b'^') {
+        quick_substitution(line, &mut output)?;
+        return Ok(Some(output.len));
+    }
+    
+    let mut copied = 0;
+    let mut in_single = false;
+    let mut in_double = false;
+    let mut i = 0;
+    
+    while i < line.len() {
+        match line[i] {
+            b'\\' if !in_single => i += 1,
+            b'\'' if !in_double => in_single = !in_single,
+            b'"' if !in_single => in_double = !in_double,
+            // `$!` and `${!name}` are not history references
+            b'!' if !in_single && (i == 0 || !matches!(line[i - 1], b'$' | b'{')) => {
+                let end = event_end(line, i + 1);
+                if end > i + 1 && line[i + 1] != b'=' {
+                    output.push(&line[copied..i])?;
+                    if !output.push_event(&line[i + 1..end])? {
+                        return Err(HistoryError::EventNotFound(&line[i..end]));
+                    }
+                    copied = end;
+                    i = end;
+                    continue;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    
+    if copied == 0 {
+        return Ok(None);
+    }
+    output.push(&line[copied..])?;
+    Ok(Some(output.len))
+}
@@ -0,0 +1,598 @@
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, AtomicUsize, Ordering};
+use crate::syscalls::{
+    waitpid, setpgid, getpgrp, getpid, kill, tcsetpgrp, tcgetpgrp, write, set_signal_action,
+    EINTR, WNOHANG, WUNTRACED, WCONTINUED, SIGINT, SIGCHLD, SIGCONT, SIGTTIN, SIGTTOU, SIG_IGN, SA_RESTART, STDIN,
+};
+use crate::shell::parser::{Token, Operator, RedirectKind};
+use crate::shell::executor::{decode_wait_status, report_error};
+use crate::shell::session::for_each_session;
+use crate::io::{format_number, write_number, Stdio};
+
+pub const MAX_JOBS: usize = 16;
+/// Processes per job, one for every pipeline stage.
+pub const MAX_JOB_PROCS: usize = 16;
+const MAX_JOB_TEXT: usize = 128;
+
+const FREE: u8 = 0;
+/// Slot taken by `add` but not filled in yet.
+const CLAIMED: u8 = 1;
+const RUNNING: u8 = 2;
+const STOPPED: u8 = 3;
+const DONE: u8 = 4;
+
+/// Status of a stage that turned out not to be a child of the shell.
+const NOT_A_CHILD: i32 = 127;
+
+/// One pipeline started by the shell. Every field is atomic because the
+/// SIGCHLD handler updates jobs while the shell may be reading them.
+struct Job {
+    state: AtomicU8,
+    pgid: AtomicI32,
+    /// Pids of the pipeline stages; an entry is cleared once it is reaped.
+    pids: [AtomicI32; MAX_JOB_PROCS],
+    /// Exit status of every stage that has been reaped.
+    statuses: [AtomicI32; MAX_JOB_PROCS],
+    count: AtomicUsize,
+    /// Set while a shell waits for the job, so the reaper keeps away.
+    foreground: AtomicBool,
+    /// Held by the reaper while it looks at the job, and by a shell while
+    /// it takes the job into the foreground, so a process is never reaped
+    /// by one while the other waits for it.
+    reaping: AtomicBool,
+    /// State change the user has not been told about yet.
+    changed: AtomicBool,
+    disowned: AtomicBool,
+    text: [AtomicU8; MAX_JOB_TEXT],
+    text_len: AtomicUsize,
+}
+
+impl Job {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU8::new(FREE),
+            pgid: AtomicI32::new(0),
+            pids: [const { AtomicI32::new(0) }; MAX_JOB_PROCS],
+            statuses: [const { AtomicI32::new(0) }; MAX_JOB_PROCS],
+            count: AtomicUsize::new(0),
+            foreground: AtomicBool::new(false),
+            reaping: AtomicBool::new(false),
+            changed: AtomicBool::new(false),
+            disowned: AtomicBool::new(false),
+            text: [const { AtomicU8::new(0) }; MAX_JOB_TEXT],
+            text_len: AtomicUsize::new(0),
+        }
+    }
+    
+    /// A job the user can refer to with `%N`.
+    fn is_visible(&self) -> bool {
+        let state = self.state.load(Ordering::Acquire);
+        (state == RUNNING || state == STOPPED || state == DONE)
+            && !self.foreground.load(Ordering::Acquire)
+            && !self.disowned.load(Ordering::Acquire)
+    }
+    
+    fn text(&self, out: &mut [u8; MAX_JOB_TEXT]) -> usize {
+        let len = self.text_len.load(Ordering::Acquire);
+        for (b, stored) in out.iter_mut().zip(&self.text[..len]) {
+            *b = stored.load(Ordering::Relaxed);
+        }
+        len
+    }
+    
+    /// Exit status of the job, that of its last stage.
+    fn status(&self) -> i32 {
+        let count = self.count.load(Ordering::Acquire);
+        self.statuses[count.max(1) - 1].load(Ordering::Acquire)
+    }
+    
+    /// Takes `reaping`, or returns `false` if it is held.
+    fn try_lock(&self) -> bool {
+        self.reaping.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_ok()
+    }
+    
+    fn unlock(&self) {
+        self.reaping.store(false, Ordering::Release);
+    }
+    
+    /// Applies one `waitpid` result for process `index`.
+    fn record(&self, index: usize, status: i32) {
+        let (old, new) = if is_stopped(status) {
+            (self.state.swap(STOPPED, Ordering::AcqRel), STOPPED)
+        } else if is_continued(status) {
+            (self.state.swap(RUNNING, Ordering::AcqRel), RUNNING)
+        } else {
+            self.finish_stage(index, decode_wait_status(status));
+            let count = self.count.load(Ordering::Acquire);
+            if self.pids[..count].iter().any(|pid| pid.load(Ordering::Acquire) != 0) {
+                return;
+            }
+            (self.state.swap(DONE, Ordering::AcqRel), DONE)
+        };
+        
+        if old != new && !self.foreground.load(Ordering::Acquire) {
+            self.changed.store(true, Ordering::Release);
+        }
+    }
+    
+    fn finish_stage(&self, index: usize, status: i32) {
+        self.statuses[index].store(status, Ordering::Release);
+        self.pids[index].store(0, Ordering::Release);
+    }
+    
+    /// Collects status changes of the job without blocking, unless a
+    /// shell waits for it in the foreground.
+    fn reap(&self) {
+        // In the SIGCHLD handler the lock may be held by the very code
+        // the signal interrupted, so it is never waited for
+        if !self.try_lock() {
+            return;
+        }
+        
+        let state = self.state.load(Ordering::Acquire);
+        if (state == RUNNING || state == STOPPED) && !self.foreground.load(Ordering::Acquire) {
+            for i in 0..self.count.load(Ordering::Acquire) {
+                let pid = self.pids[i].load(Ordering::Acquire);
+                if pid == 0 {
+                    continue;
+                }
+                
+                let mut status: i32 = 0;
+                if waitpid(pid, &mut status, WNOHANG | WUNTRACED | WCONTINUED) == pid as isize {
+                    self.record(i, status);
+                }
+            }
+        }
+        self.unlock();
+    }
+    
+    /// Blocks until every process has exited or one of them stopped.
+    /// Returns the stop signal in the latter case.
+    fn wait_processes(&self) -> Option<i32> {
+        for i in 0..self.count.load(Ordering::Acquire) {
+            loop {
+                let pid = self.pids[i].load(Ordering::Acquire);
+                if pid == 0 {
+                    break;
+                }
+                
+                let mut status: i32 = 0;
+                let ret = waitpid(pid, &mut status, WUNTRACED);
+                
+                if ret == pid as isize {
+                    self.record(i, status);
+                    if is_stopped(status) {
+                        return Some((status >> 8) & 0xff);
+                    }
+                } else if ret != -EINTR {
+                    // The reaper keeps away from a foreground job, so the
+                    // process was never a child of the shell
+                    self.finish_stage(i, NOT_A_CHILD);
+                }
+            }
+        }
+        None
+    }
+}
+
+/// The jobs of one shell session, numbered from 1. The terminal has one
+/// table and every web session another, so `fg`, `bg` and `wait` only see
+/// the jobs started in the same session.
+pub struct JobTable {
+    jobs: [Job; MAX_JOBS],
+    /// Number of the job `fg` and `bg` act on by default, 0 if none.
+    current: AtomicUsize,
+}
+
+/// Jobs started from the terminal, or by the script the shell runs.
+pub static CONSOLE_JOBS: JobTable = JobTable::new();
+
+fn is_stopped(status: i32) -> bool {
+    status & 0xff == 0x7f
+}
+
+fn is_continued(status: i32) -> bool {
+    status == 0xffff
+}
+
+extern "C" fn child_handler(_signum: i32) {
+    reap_jobs();
+}
+
+/// Installs the SIGCHLD reaper and keeps the shell itself from being
+/// stopped when it hands the terminal to a job and takes it back.
+pub fn init_job_control() -> bool {
+    set_signal_action(SIGCHLD, child_handler as extern "C" fn(i32) as usize, SA_RESTART)
+        && set_signal_action(SIGTTOU, SIG_IGN, 0)
+        && set_signal_action(SIGTTIN, SIG_IGN, 0)
+}
+
+/// Whether the shell owns the terminal on stdin and can give it to jobs.
+pub fn terminal_available() -> bool {
+    tcgetpgrp(STDIN) == getpgrp() as isize
+}
+
+/// Moves a freshly forked process into process group `pgid`, or into a new
+/// group of its own if `pgid` is 0. Both parent and child call this so the
+/// group exists whichever of them runs first.
+pub fn join_process_group(pid: i32, pgid: i32, take_terminal: bool) {
+    setpgid(pid, pgid);
+    
+    if take_terminal {
+        let group = if pgid != 0 {
+            pgid
+        } else if pid != 0 {
+            pid
+        } else {
+            getpid()
+        };
+        tcsetpgrp(STDIN, group);
+    }
+}
+
+/// Collects status changes of the background jobs of every session
+/// without blocking. Runs from the SIGCHLD handler, so it only touches
+/// atomics.
+pub fn reap_jobs() {
+    CONSOLE_JOBS.reap();
+    for_each_session(|session| session.jobs().reap());
+}
+
+/// Rebuilds the command text of a job from its tokens.
+fn describe(tokens: &[Token], out: &mut [u8; MAX_JOB_TEXT]) -> usize {
+    let mut len = 0;
+    let mut here_doc = false;
+    
+    for token in tokens {
+        let mut fd_buf = [0u8; 20];
+        let (prefix, text): (&[u8], &[u8]) = match *token {
+            // A here-document is shown by its delimiter, the last line
+            Token::Word(word) if here_doc => {
+                let start = word.iter().rposition(|&b| b == b'\n').map_or(0, |n| n + 1);
+                (&[], &word[start..])
+            }
+            Token::Word(word) => (&[], word),
+            // A job typed over several lines is shown on one
+            Token::Op(Operator::Newline) => (&[], b";"),
+            Token::Op(op) => (&[], op.as_bytes()),
+            Token::Redirect(fd, kind) => {
+                let n = if fd == kind.default_fd() { 0 } else { format_number(fd as i64, &mut fd_buf) };
+                (&fd_buf[..n], kind.as_bytes())
+            }
+        };
+        here_doc = matches!(token, Token::Redirect(_, RedirectKind::HereDoc { .. }));
+        
+        if len > 0 {
+            append(out, &mut len, b" ");
+        }
+        append(out, &mut len, prefix);
+        append(out, &mut len, text);
+    }
+    
+    len
+}
+
+fn append(out: &mut [u8], len: &mut usize, bytes: &[u8]) {
+    for &b in bytes {
+        if *len >= out.len() {
+            return;
+        }
+        out[*len] = b;
+        *len += 1;
+    }
+}
+
+impl JobTable {
+    pub const fn new() -> Self {
+        Self {
+            jobs: [const { Job::new() }; MAX_JOBS],
+            current: AtomicUsize::new(0),
+        }
+    }
+    
+    fn job(&self, number: usize) -> &Job {
+        &self.jobs[number - 1]
+    }
+    
+    fn reap(&self) {
+        for job in &self.jobs {
+            job.reap();
+        }
+    }
+    
+    fn add(&self, pgid: i32, pids: &[i32], tokens: &[Token], foreground: bool) -> Option<usize> {
+        for (i, job) in self.jobs.iter().enumerate() {
+            if job.state.compare_exchange(FREE, CLAIMED, Ordering::AcqRel, Ordering::Acquire).is_err() {
+                continue;
+            }
+            
+            let count = pids.len().min(MAX_JOB_PROCS);
+            for ((stored, status), &pid) in job.pids.iter().zip(&job.statuses).zip(&pids[..count]) {
+                stored.store(pid, Ordering::Relaxed);
+                status.store(0, Ordering::Relaxed);
+            }
+            job.count.store(count, Ordering::Relaxed);
+            job.pgid.store(pgid, Ordering::Relaxed);
+            job.foreground.store(foreground, Ordering::Relaxed);
+            job.changed.store(false, Ordering::Relaxed);
+            job.disowned.store(false, Ordering::Relaxed);
+            
+            let mut text = [0u8; MAX_JOB_TEXT];
+            let len = describe(tokens, &mut text);
+            for (stored, &b) in job.text.iter().zip(&text[..len]) {
+                stored.store(b, Ordering::Relaxed);
+            }
+            job.text_len.store(len, Ordering::Relaxed);
+            
+            job.state.store(RUNNING, Ordering::Release);
+            return Some(i + 1);
+        }
+        None
+    }
+    
+    fn free(&self, number: usize) {
+        self.job(number).state.store(FREE, Ordering::Release);
+        self.forget_current(number);
+    }
+    
+    /// Makes the most recent remaining job current if `number` was.
+    fn forget_current(&self, number: usize) {
+        if self.current.load(Ordering::Acquire) == number {
+            let next = (1..=MAX_JOBS).rev().find(|&n| self.job(n).is_visible()).unwrap_or(0);
+            self.current.store(next, Ordering::Release);
+        }
+    }
+    
+    /// Waits for job `number` in the foreground, continuing it first if it
+    /// is stopped. With `job_control` the job gets the terminal while it
+    /// runs. Returns its exit status, or 128 plus the signal if it stopped
+    /// again.
+    pub fn wait(&self, number: usize, io: &Stdio, job_control: bool) -> i32 {
+        let job = self.job(number);
+        while !job.try_lock() {
+            core::hint::spin_loop();
+        }
+        job.foreground.store(true, Ordering::Release);
+        job.unlock();
+        
+        let pgid = job.pgid.load(Ordering::Acquire);
+        if job_control {
+            tcsetpgrp(STDIN, pgid);
+        }
+        if job.state.compare_exchange(STOPPED, RUNNING, Ordering::AcqRel, Ordering::Acquire).is_ok() {
+            kill(-pgid, SIGCONT);
+        }
+        
+        let stop_signal = job.wait_processes();
+        
+        if job_control {
+            tcsetpgrp(STDIN, getpgrp());
+        }
+        job.foreground.store(false, Ordering::Release);
+        
+        match stop_signal {
+            Some(signal) => {
+                job.changed.store(false, Ordering::Release);
+                self.current.store(number, Ordering::Release);
+                write(io.stderr, b"\n");
+                self.print(io.stderr, number);
+                128 + signal
+            }
+            None => {
+                let status = job.status();
+                self.free(number);
+                // The terminal shows `^C` where the job was killed; the next
+                // prompt goes on a line of its own
+                if job_control && status == 128 + SIGINT {
+                    write(io.stderr, b"\n");
+                }
+                status
+            }
+        }
+    }
+    
+    /// Waits for freshly started processes in the foreground.
+    pub fn run_foreground(&self, pgid: i32, pids: &[i32], tokens: &[Token], io: &Stdio, job_control: bool) -> i32 {
+        if let Some(number) = self.add(pgid, pids, tokens, true) {
+            return self.wait(number, io, job_control);
+        }
+        
+        // No free slot: the job cannot be stopped and resumed, just wait
+        // for every stage
+        let mut statuses = [0i32; MAX_JOB_PROCS];
+        for (status, &pid) in statuses.iter_mut().zip(pids) {
+            let mut raw: i32 = 0;
+            let ret = loop {
+                let ret = waitpid(pid, &mut raw, 0);
+                if ret != -EINTR {
+                    break ret;
+                }
+            };
+            *status = if ret == pid as isize { decode_wait_status(raw) } else { NOT_A_CHILD };
+        }
+        statuses[pids.len().clamp(1, MAX_JOB_PROCS) - 1]
+    }
+    
+    /// Registers processes started with `&` and prints `[N] PID`.
+    pub fn start_background(&self, pgid: i32, pids: &[i32], tokens: &[Token], io: &Stdio) {
+        match self.add(pgid, pids, tokens, false) {
+            Some(number) => {
+                self.current.store(number, Ordering::Release);
+                write(io.stderr, b"[");
+                write_number(io.stderr, number as i64);
+                write(io.stderr, b"] ");
+                write_number(io.stderr, pids[pids.len() - 1] as i64);
+                write(io.stderr, b"\n");
+            }
+            None => report_error(io, b"too many jobs"),
+        }
+    }
+    
+    /// Prints a line like `[1]+  Running                 sleep 10 &`.
+    fn print(&self, fd: i32, number: usize) {
+        let job = self.job(number);
+        let state = job.state.load(Ordering::Acquire);
+        let status = job.status();
+        
+        let mut label = [0u8; 24];
+        let mut label_len = 0;
+        match state {
+            RUNNING => append(&mut label, &mut label_len, b"Running"),
+            STOPPED => append(&mut label, &mut label_len, b"Stopped"),
+            _ if status == 0 => append(&mut label, &mut label_len, b"Done"),
+            _ => {
+                let mut digits = [0u8; 20];
+                let n = format_number(status as i64, &mut digits);
+                append(&mut label, &mut label_len, b"Exit ");
+                append(&mut label, &mut label_len, &digits[..n]);
+            }
+        }
+        while label_len < label.len() {
+            label[label_len] = b' ';
+            label_len += 1;
+        }
+        
+        let mut text = [0u8; MAX_JOB_TEXT];
+        let text_len = job.text(&mut text);
+        
+        write(fd, b"[");
+        write_number(fd, number as i64);
+        write(fd, if self.current.load(Ordering::Acquire) == number { b"]+  " } else { b"]   " });
+        write(fd, &label);
+        write(fd, &text[..text_len]);
+        write(fd, if state == RUNNING { b" &\n" } else { b"\n" });
+    }
+    
+    /// Lists jobs for the `jobs` builtin. With `changed_only`, only jobs
+    /// that finished or stopped since the last report are shown, as before
+    /// a prompt. Finished jobs are forgotten once reported.
+    pub fn list(&self, io: &Stdio, changed_only: bool) {
+        self.reap();
+        
+        for number in 1..=MAX_JOBS {
+            let job = self.job(number);
+            let state = job.state.load(Ordering::Acquire);
+            if state == FREE || state == CLAIMED || job.foreground.load(Ordering::Acquire) {
+                continue;
+            }
+            
+            if job.disowned.load(Ordering::Acquire) {
+                if state == DONE {
+                    self.free(number);
+                }
+                continue;
+            }
+            
+            if !job.changed.swap(false, Ordering::AcqRel) && changed_only {
+                continue;
+            }
+            self.print(if changed_only { io.stderr } else { io.stdout }, number);
+            
+            if state == DONE {
+                self.free(number);
+            }
+        }
+    }
+    
+    /// Resolves a job spec: `%N`, `%%`, `%+`, `%prefix` or a process id.
+    /// `None` as spec means the current job.
+    pub fn find(&self, spec: Option<&[u8]>) -> Option<usize> {
+        let spec = match spec {
+            None | Some(b"%") | Some(b"%%") | Some(b"%+") => {
+                let current = self.current.load(Ordering::Acquire);
+                return if current != 0 && self.job(current).is_visible() { Some(current) } else { None };
+            }
+            Some(spec) => spec,
+        };
+        
+        let (by_pid, digits) = match spec.first() {
+            Some(b'%') => (false, &spec[1..]),
+            _ => (true, spec),
+        };
+        
+        if !digits.is_empty() && digits.len() <= 10 && digits.iter().all(|b| b.is_ascii_digit()) {
+            let value = digits.iter().fold(0i64, |acc, &b| acc * 10 + (b - b'0') as i64);
+            if !by_pid {
+                let number = value as usize;
+                return if (1..=MAX_JOBS).contains(&number) && self.job(number).is_visible() { Some(number) } else { None };
+            }
+            return (1..=MAX_JOBS).find(|&n| {
+                let job = self.job(n);
+                job.is_visible() && (job.pgid.load(Ordering::Acquire) as i64 == value
+                    || job.pids.iter().any(|pid| pid.load(Ordering::Acquire) as i64 == value))
+            });
+        }
+        
+        if by_pid {
+            return None;
+        }
+        
+        // `%prefix` picks the most recent job whose command starts with it
+        (1..=MAX_JOBS).rev().find(|&n| {
+            let mut text = [0u8; MAX_JOB_TEXT];
+            let len = self.job(n).text(&mut text);
+            self.job(n).is_visible() && text[..len].starts_with(digits)
+        })
+    }
+    
+    pub fn is_stopped(&self, number: usize) -> bool {
+        self.job(number).state.load(Ordering::Acquire) == STOPPED
+    }
+    
+    /// Prints the command of job `number`, as `fg` does before resuming it.
+    pub fn print_command(&self, fd: i32, number: usize, background: bool) {
+        let mut text = [0u8; MAX_JOB_TEXT];
+        let len = self.job(number).text(&mut text);
+        
+        if background {
+            write(fd, b"[");
+            write_number(fd, number as i64);
+            write(fd, b"]+ ");
+        }
+        write(fd, &text[..len]);
+        write(fd, if background { b" &\n" } else { b"\n" });
+    }
+    
+    /// Continues a stopped job in the background.
+    pub fn resume(&self, number: usize) {
+        let job = self.job(number);
+        job.state.store(RUNNING, Ordering::Release);
+        job.changed.store(false, Ordering::Release);
+        self.current.store(number, Ordering::Release);
+        kill(-job.pgid.load(Ordering::Acquire), SIGCONT);
+    }
+    
+    /// Removes a job from the table. Its processes keep running and are
+    /// reaped silently.
+    pub fn disown(&self, number: usize) {
+        let job = self.job(number);
+        job.disowned.store(true, Ordering::Release);
+        
+        if job.state.load(Ordering::Acquire) == DONE {
+            self.free(number);
+        } else {
+            self.forget_current(number);
+        }
+    }
+    
+    /// Disowns every job, when the session they belong to is closed.
+    pub fn disown_all(&self) {
+        for number in 1..=MAX_JOBS {
+            let state = self.job(number).state.load(Ordering::Acquire);
+            if state != FREE && state != CLAIMED {
+                self.disown(number);
+            }
+        }
+    }
+    
+    /// Job numbers of all jobs the user can see, for `wait` without arguments.
+    pub fn visible(&self, out: &mut [usize; MAX_JOBS]) -> usize {
+        let mut count = 0;
+        for number in 1..=MAX_JOBS {
+            if self.job(number).is_visible() {
+                out[count] = number;
+                count += 1;
+            }
+        }
+        count
+    }
+}
@@ -1,6 +1,16 @@
 pub mod parser;
 pub mod builtins;
 pub mod executor;
+pub mod pipeline;
+pub mod jobs;
+pub mod command_list;
+pub mod compound;
+pub mod functions;
+pub mod script;
+pub mod history;
+pub mod passwd;
+pub mod prompt;
+pub mod redirect;
 pub mod storage;
 pub mod session;
 pub mod session_executor;
@@ -8,4 +18,4 @@ pub mod session_executor;
 pub use executor::execute_command;
 pub use storage::ENV_STORAGE;
 pub use session::{get_session, allocate_session, free_session};
-pub use session_executor::execute_command_in_session;
+pub use session_executor::{execute_command_in_session, init_session};
@@ -0,0 +1,117 @@
+use super::lexer::{lex, Token, Operator, MAX_INPUT_TOKENS};
+use crate::shell::storage::{ALIASES, ALIAS_VALUE_SIZE};
+
+/// Size of a command line after alias expansion.
+pub const ALIAS_LINE_SIZE: usize = 4096;
+
+/// Reserved words after which a command begins.
+const COMMAND_KEYWORDS: &[&[u8]] = &[b"if", b"then", b"else", b"elif", b"while", b"until", b"do", b"{", b"!"];
+
+/// Whether `name` can be an alias: a word that contains nothing the lexer
+/// would treat specially.
+pub fn is_alias_name(name: &[u8]) -> bool {
+    !name.is_empty() && name.iter().all(|&b| {
+        b.is_ascii_graphic() && !matches!(b, b'=' | b'/' | b'$' | b'`' | b'\\' | b'\'' | b'"'
+            | b'|' | b'&' | b';' | b'<' | b'>' | b'(' | b')')
+    })
+}
+
+/// Aliases being expanded, innermost first. An alias is not expanded
+/// inside its own value, which ends the recursion.
+struct Active<'a> {
+    name: &'a [u8],
+    outer: Option<&'a Active<'a>>,
+}
+
+impl Active<'_> {
+    fn contains(active: Option<&Active>, name: &[u8]) -> bool {
+        let mut next = active;
+        while let Some(alias) = next {
+            if alias.name == name {
+                return true;
+            }
+            next = alias.outer;
+        }
+        false
+    }
+}
+
+struct Output<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Output<'_> {
+    fn push(&mut self, s: &[u8]) -> Result<(), &'static str> {
+        if self.len + s.len() > self.buf.len() {
+            return Err("command too long after alias expansion");
+        }
+        self.buf[self.len..self.len + s.len()].copy_from_slice(s);
+        self.len += s.len();
+        Ok(())
+    }
+}
+
+/// Copies `text` to `out`, replacing the aliases among its command words.
+/// Returns whether the word following `text` is a command word too: after
+/// an operator, or when `text` ends with a blank.
+fn expand_text(text: &[u8], out: &mut Output, active: Option<&Active>) -> Result<bool, &'static str> {
+    let mut tokens = [Token::Word(&[]); MAX_INPUT_TOKENS];
+    let count = match lex(text, &mut tokens) {
+        Ok(count) => count,
+        // Left for the parser to report
+        Err(_) => {
+            out.push(text)?;
+            return Ok(false);
+        }
+    };
+    
+    let mut copied = 0;
+    let mut command_word = true;
+    
+    for (i, token) in tokens[..count].iter().enumerate() {
+        let word = match *token {
+            Token::Word(word) => word,
+            Token::Op(op) => {
+                command_word = op != Operator::DoubleSemicolon;
+                continue;
+            }
+            Token::Redirect(..) => continue,
+        };
+        // Redirection targets and here-documents are never commands
+        if i > 0 && matches!(tokens[i - 1], Token::Redirect(..)) {
+            continue;
+        }
+        
+        let mut value = [0u8; ALIAS_VALUE_SIZE];
+        let alias = if command_word && !Active::contains(active, word) {
+            ALIASES.get(word, &mut value)
+        } else {
+            None
+        };
+        let len = match alias {
+            Some(len) => len,
+            None => {
+                command_word = command_word && COMMAND_KEYWORDS.contains(&word);
+                continue;
+            }
+        };
+        
+        let start = word.as_ptr() as usize - text.as_ptr() as usize;
+        out.push(&text[copied..start])?;
+        command_word = expand_text(&value[..len], out, Some(&Active { name: word, outer: active }))?;
+        copied = start + word.len();
+    }
+    
+    out.push(&text[copied..])?;
+    Ok(command_word || text.last().is_some_and(|&b| b == b' ' || b == b'\t'))
+}
+
+/// Replaces aliases in the command words of `line` with their values,
+/// writing the result to `out`. A value ending with a blank makes the next
+/// word a command word as well. Returns the length of the result.
+pub fn expand_aliases(line: &[u8], out: &mut [u8]) -> Result<usize, &'static str> {
+    let mut output = Output { buf: out, len: 0 };
+    expand_text(line, &mut output, None)?;
+    Ok(output.len)
+}
@@ -0,0 +1,357 @@
+use super::env_expansion::{is_name_start, is_name_byte};
+use crate::shell::storage::ENV_STORAGE;
+use crate::io::format_number;
+
+/// Limit on nested parentheses, unary operators and variables whose values
+/// are expressions themselves, so a hostile expression can't exhaust the stack.
+const MAX_DEPTH: usize = 32;
+const VALUE_BUF_SIZE: usize = 256;
+
+const OVERFLOW: &str = "integer overflow";
+
+/// Longest operators first so `<<=` is not read as `<` or `<<`.
+const OPERATORS: [&[u8]; 40] = [
+    b"<<=", b">>=", b"**=",
+    b"**", b"<<", b">>", b"<=", b">=", b"==", b"!=", b"&&", b"||",
+    b"+=", b"-=", b"*=", b"/=", b"%=", b"&=", b"^=", b"|=", b"++", b"--",
+    b"+", b"-", b"*", b"/", b"%", b"<", b">", b"&", b"^", b"|",
+    b"!", b"~", b"=", b"?", b":", b"(", b")", b",",
+];
+
+/// Evaluates an integer expression with the C operators, as used by
+/// `$((...))` and `let`. Variables are read from and assigned to
+/// `ENV_STORAGE`; an unset or empty variable is 0.
+pub fn evaluate_arithmetic(expr: &[u8]) -> Result<i64, &'static str> {
+    Evaluator { expr, pos: 0, depth: 0 }.run()
+}
+
+struct Evaluator<'a> {
+    expr: &'a [u8],
+    pos: usize,
+    depth: usize,
+}
+
+fn binary_precedence(op: &[u8]) -> Option<u8> {
+    let precedence = match op {
+        b"||" => 1,
+        b"&&" => 2,
+        b"|" => 3,
+        b"^" => 4,
+        b"&" => 5,
+        b"==" | b"!=" => 6,
+        b"<" | b"<=" | b">" | b">=" => 7,
+        b"<<" | b">>" => 8,
+        b"+" | b"-" => 9,
+        b"*" | b"/" | b"%" => 10,
+        b"**" => 11,
+        _ => return None,
+    };
+    Some(precedence)
+}
+
+fn is_assignment(op: &[u8]) -> bool {
+    matches!(
+        op,
+        b"=" | b"+=" | b"-=" | b"*=" | b"/=" | b"%=" | b"**=" | b"<<=" | b">>=" | b"&=" | b"^=" | b"|="
+    )
+}
+
+/// Applies a binary operator other than `&&` and `||`.
+fn apply(op: &[u8], a: i64, b: i64) -> Result<i64, &'static str> {
+    let result = match op {
+        b"|" => Some(a | b),
+        b"^" => Some(a ^ b),
+        b"&" => Some(a & b),
+        b"==" => Some((a == b) as i64),
+        b"!=" => Some((a != b) as i64),
+        b"<" => Some((a < b) as i64),
+        b"<=" => Some((a <= b) as i64),
+        b">" => Some((a > b) as i64),
+        b">=" => Some((a >= b) as i64),
+        b"<<" | b">>" => {
+            if !(0..64).contains(&b) {
+                return Err("shift count out of range");
+            }
+            if op == b"<<" { Some(a << b) } else { Some(a >> b) }
+        }
+        b"+" => a.checked_add(b),
+        b"-" => a.checked_sub(b),
+        b"*" => a.checked_mul(b),
+        b"/" | b"%" => {
+            if b == 0 {
+                return Err("division by zero");
+            }
+            if op == b"/" { a.checked_div(b) } else { a.checked_rem(b) }
+        }
+        b"**" => {
+            if b < 0 {
+                return Err("exponent less than 0");
+            }
+            u32::try_from(b).ok().and_then(|exp| a.checked_pow(exp))
+        }
+        _ => return Err("syntax error in expression"),
+    };
+    result.ok_or(OVERFLOW)
+}
+
+/// Parses a decimal, `0x` hexadecimal, `0` octal or `BASE#DIGITS` constant.
+fn parse_number(token: &[u8]) -> Result<i64, &'static str> {
+    let (base, digits) = if let Some(hash) = token.iter().position(|&b| b == b'#') {
+        let base = match parse_number(&token[..hash]) {
+            Ok(base) if (2..=36).contains(&base) && token[..hash].iter().all(u8::is_ascii_digit) => base,
+            _ => return Err("invalid arithmetic base"),
+        };
+        (base, &token[hash + 1..])
+    } else if token.len() > 2 && (token.starts_with(b"0x") || token.starts_with(b"0X")) {
+        (16, &token[2..])
+    } else if token.len() > 1 && token[0] == b'0' {
+        (8, &token[1..])
+    } else {
+        (10, token)
+    };
+    
+    if digits.is_empty() {
+        return Err("invalid number");
+    }
+    
+    let mut value: i64 = 0;
+    for &b in digits {
+        let digit = match b {
+            b'0'..=b'9' => b - b'0',
+            b'a'..=b'z' => b - b'a' + 10,
+            b'A'..=b'Z' => b - b'A' + 10,
+            _ => return Err("invalid number"),
+        } as i64;
+        if digit >= base {
+            return Err("value too great for base");
+        }
+        value = value.checked_mul(base).and_then(|v| v.checked_add(digit)).ok_or(OVERFLOW)?;
+    }
+    Ok(value)
+}
+
+// Every parsing method takes `skip`, set for the operands that `&&`, `||`
+// and `?:` don't evaluate: they are parsed but have no side effects and
+// can't fail on division by zero.
+impl<'a> Evaluator<'a> {
+    fn run(mut self) -> Result<i64, &'static str> {
+        self.skip_spaces();
+        if self.pos == self.expr.len() {
+            return Ok(0);
+        }
+        
+        let value = self.comma(false)?;
+        
+        self.skip_spaces();
+        if self.pos < self.expr.len() {
+            return Err("syntax error in expression");
+        }
+        Ok(value)
+    }
+    
+    fn skip_spaces(&mut self) {
+        while self.pos < self.expr.len() && self.expr[self.pos].is_ascii_whitespace() {
+            self.pos += 1;
+        }
+    }
+    
+    /// The operator at the current position, or an empty slice.
+    fn peek_op(&mut self) -> &'static [u8] {
+        self.skip_spaces();
+        let rest = &self.expr[self.pos..];
+        OPERATORS.iter().find(|op| rest.starts_with(op)).copied().unwrap_or(b"")
+    }
+    
+    fn expect(&mut self, op: &[u8], err: &'static str) -> Result<(), &'static str> {
+        if self.peek_op() != op {
+            return Err(err);
+        }
+        self.pos += op.len();
+        Ok(())
+    }
+    
+    fn enter(&mut self) -> Result<(), &'static str> {
+        self.depth += 1;
+        if self.depth > MAX_DEPTH {
+            return Err("expression nested too deeply");
+        }
+        Ok(())
+    }
+    
+    fn name(&mut self) -> Option<&'a [u8]> {
+        self.skip_spaces();
+        let start = self.pos;
+        if !self.expr.get(start).is_some_and(|&b| is_name_start(b)) {
+            return None;
+        }
+        while self.pos < self.expr.len() && is_name_byte(self.expr[self.pos]) {
+            self.pos += 1;
+        }
+        Some(&self.expr[start..self.pos])
+    }
+    
+    /// The value of a variable, which may itself be an expression.
+    fn variable(&self, name: &[u8], skip: bool) -> Result<i64, &'static str> {
+        if skip {
+            return Ok(0);
+        }
+        let mut value = [0u8; VALUE_BUF_SIZE];
+        let len = ENV_STORAGE.get(name, &mut value).unwrap_or(0);
+        if self.depth >= MAX_DEPTH {
+            return Err("expression nested too deeply");
+        }
+        Evaluator { expr: &value[..len], pos: 0, depth: self.depth + 1 }.run()
+    }
+    
+    fn store(&self, name: &[u8], value: i64, skip: bool) -> Result<(), &'static str> {
+        if skip {
+            return Ok(());
+        }
+        let mut digits = [0u8; 20];
+        let len = format_number(value, &mut digits);
+        ENV_STORAGE.set(name, &digits[..len])
+    }
+    
+    /// `++NAME`, `--NAME`, `NAME++` and `NAME--`. Returns the old and the
+    /// new value.
+    fn increment(&self, name: &[u8], op: &[u8], skip: bool) -> Result<(i64, i64), &'static str> {
+        let old = self.variable(name, skip)?;
+        let new = if op == b"++" { old.checked_add(1) } else { old.checked_sub(1) }.ok_or(OVERFLOW)?;
+        self.store(name, new, skip)?;
+        Ok((old, new))
+    }
+    
+    fn comma(&mut self, skip: bool) -> Result<i64, &'static str> {
+        let mut value = self.assignment(skip)?;
+        while self.peek_op() == b"," {
+            self.pos += 1;
+            value = self.assignment(skip)?;
+        }
+        Ok(value)
+    }
+    
+    fn assignment(&mut self, skip: bool) -> Result<i64, &'static str> {
+        let start = self.pos;
+        if let Some(name) = self.name() {
+            let op = self.peek_op();
+            if is_assignment(op) {
+                self.pos += op.len();
+                self.enter()?;
+                let right = self.assignment(skip)?;
+                self.depth -= 1;
+                let value = if op == b"=" || skip {
+                    right
+                } else {
+                    apply(&op[..op.len() - 1], self.variable(name, skip)?, right)?
+                };
+                self.store(name, value, skip)?;
+                return Ok(value);
+            }
+        }
+        self.pos = start;
+        self.conditional(skip)
+    }
+    
+    fn conditional(&mut self, skip: bool) -> Result<i64, &'static str> {
+        let condition = self.binary(1, skip)?;
+        if self.peek_op() != b"?" {
+            return Ok(condition);
+        }
+        self.pos += 1;
+        
+        self.enter()?;
+        let if_true = self.comma(skip || condition == 0)?;
+        self.expect(b":", "`:' expected for conditional expression")?;
+        let if_false = self.assignment(skip || condition != 0)?;
+        self.depth -= 1;
+        
+        Ok(if condition != 0 { if_true } else { if_false })
+    }
+    
+    /// Binary operators of at least `min_precedence`, by precedence climbing.
+    fn binary(&mut self, min_precedence: u8, skip: bool) -> Result<i64, &'static str> {
+        let mut left = self.unary(skip)?;
+        
+        loop {
+            let op = self.peek_op();
+            let precedence = match binary_precedence(op) {
+                Some(precedence) if precedence >= min_precedence => precedence,
+                _ => return Ok(left),
+            };
+            self.pos += op.len();
+            
+            left = match op {
+                b"&&" => {
+                    let right = self.binary(precedence + 1, skip || left == 0)?;
+                    (left != 0 && right != 0) as i64
+                }
+                b"||" => {
+                    let right = self.binary(precedence + 1, skip || left != 0)?;
+                    (left != 0 || right != 0) as i64
+                }
+                _ => {
+                    // `**` is right-associative
+                    let next = if op == b"**" { precedence } else { precedence + 1 };
+                    let right = self.binary(next, skip)?;
+                    if skip { 0 } else { apply(op, left, right)? }
+                }
+            };
+        }
+    }
+    
+    fn unary(&mut self, skip: bool) -> Result<i64, &'static str> {
+        let op = self.peek_op();
+        match op {
+            b"++" | b"--" => {
+                self.pos += 2;
+                let name = self.name().ok_or("`++' or `--' needs a variable")?;
+                Ok(self.increment(name, op, skip)?.1)
+            }
+            b"+" | b"-" | b"!" | b"~" => {
+                self.pos += 1;
+                self.enter()?;
+                let value = self.unary(skip)?;
+                self.depth -= 1;
+                match op {
+                    b"-" => value.checked_neg().ok_or(OVERFLOW),
+                    b"!" => Ok((value == 0) as i64),
+                    b"~" => Ok(!value),
+                    _ => Ok(value),
+                }
+            }
+            _ => self.primary(skip),
+        }
+    }
+    
+    fn primary(&mut self, skip: bool) -> Result<i64, &'static str> {
+        self.skip_spaces();
+        match self.expr.get(self.pos) {
+            Some(b'(') => {
+                self.pos += 1;
+                self.enter()?;
+                let value = self.comma(skip)?;
+                self.depth -= 1;
+                self.expect(b")", "missing `)'")?;
+                Ok(value)
+            }
+            Some(b) if b.is_ascii_digit() => {
+                let start = self.pos;
+                while self.pos < self.expr.len() && (is_name_byte(self.expr[self.pos]) || self.expr[self.pos] == b'#') {
+                    self.pos += 1;
+                }
+                parse_number(&self.expr[start..self.pos])
+            }
+            Some(&b) if is_name_start(b) => {
+                let name = self.name().unwrap_or(b"");
+                let op = self.peek_op();
+                if op == b"++" || op == b"--" {
+                    self.pos += 2;
+                    return Ok(self.increment(name, op, skip)?.0);
+                }
+                self.variable(name, skip)
+            }
+            None => Err("operand expected"),
+            Some(_) => Err("syntax error: operand expected"),
+        }
+    }
+}
@@ -0,0 +1,519 @@
+use super::lexer::{lex, is_unterminated, Token, Operator, MAX_INPUT_TOKENS};
+use super::env_expansion::is_name;
+
+/// Nodes in the tree of one parsed chunk of input.
+pub const MAX_NODES: usize = 128;
+
+/// Index of a node in `Ast::nodes`.
+pub type NodeId = usize;
+
+/// Words that end a command list when they appear in command position.
+const LIST_TERMINATORS: [&[u8]; 8] = [b"then", b"elif", b"else", b"fi", b"do", b"done", b"esac", b"}"];
+
+const RESERVED_WORDS: [&[u8]; 16] = [
+    b"if", b"then", b"elif", b"else", b"fi", b"while", b"until", b"for",
+    b"in", b"do", b"done", b"case", b"esac", b"{", b"}", b"!",
+];
+
+/// Whether `word` has a meaning of its own in command position, like `if`.
+pub fn is_reserved_word(word: &[u8]) -> bool {
+    RESERVED_WORDS.contains(&word)
+}
+
+#[derive(Clone, Copy)]
+pub enum NodeKind<'a> {
+    /// An and-or list inside a command list, starting with pipeline
+    /// `first` and run as a background job if it ended with `&`.
+    Item { first: NodeId, background: bool, text: &'a [Token<'a>] },
+    /// A pipeline of an and-or list whose stages start at `first`. It runs
+    /// depending on the status before it and `connector`, the `&&` or `||`
+    /// in front of it (`;` for the first one).
+    Pipeline { first: NodeId, connector: Operator, negate: bool, text: &'a [Token<'a>] },
+    /// Words and redirections of a simple command.
+    Simple(&'a [Token<'a>]),
+    /// A compound command with the redirections written after it.
+    Compound { command: Compound<'a>, redirects: &'a [Token<'a>] },
+    /// An `if` or `elif` branch, or an `else` branch without a condition.
+    Branch { condition: Option<NodeId>, body: NodeId },
+    /// `pattern | pattern) body ;;` of a `case` command.
+    CaseItem { patterns: &'a [Token<'a>], body: Option<NodeId> },
+    /// `NAME() BODY`, keeping the source text of the compound command
+    /// `BODY` to parse again when the function is called.
+    Function { name: &'a [u8], body: &'a [u8] },
+}
+
+#[derive(Clone, Copy)]
+pub enum Compound<'a> {
+    /// `if`, with its branches starting at `first`.
+    If { first: NodeId },
+    /// `while` or `until`.
+    Loop { condition: NodeId, body: NodeId, until: bool },
+    /// `for NAME in WORDS`; without `in` the loop runs over `"$@"`.
+    For { name: &'a [u8], words: Option<&'a [Token<'a>]>, body: NodeId },
+    /// `case WORD in`, with its items starting at `first`.
+    Case { word: &'a [u8], first: Option<NodeId> },
+    /// `{ LIST; }`
+    Group { body: NodeId },
+}
+
+/// A node and its next sibling: the next item of a list, pipeline of an
+/// and-or list, stage of a pipeline, branch or case item.
+#[derive(Clone, Copy)]
+pub struct Node<'a> {
+    pub kind: NodeKind<'a>,
+    pub next: Option<NodeId>,
+}
+
+pub enum ParseError<'a> {
+    /// The input ends in the middle of a command, so more lines may
+    /// complete it.
+    Incomplete,
+    /// A token that can't appear where it does.
+    Unexpected(&'a [u8]),
+    TooComplex,
+}
+
+/// Syntax tree of a command list. Nodes refer to the tokens they were
+/// parsed from, so the tree lives no longer than the token array.
+pub struct Ast<'a> {
+    nodes: [Node<'a>; MAX_NODES],
+    count: usize,
+    /// The first item of the top-level list, `None` for blank input.
+    pub root: Option<NodeId>,
+}
+
+impl<'a> Ast<'a> {
+    // Kept out of line so the nodes are built in place; inlined, the
+    // optimizer leaves a second copy of them on the stack of every caller
+    #[inline(never)]
+    pub const fn new() -> Self {
+        Self {
+            nodes: [Node { kind: NodeKind::Simple(&[]), next: None }; MAX_NODES],
+            count: 0,
+            root: None,
+        }
+    }
+    
+    pub fn node(&self, id: NodeId) -> &Node<'a> {
+        &self.nodes[id]
+    }
+}
+
+/// Parses the command list `tokens`, lexed from `input`, into `ast`.
+pub fn parse<'a>(input: &'a [u8], tokens: &'a [Token<'a>], ast: &mut Ast<'a>) -> Result<(), ParseError<'a>> {
+    let mut parser = Parser { input, tokens, pos: 0, ast };
+    let root = parser.list()?;
+    if parser.pos < tokens.len() {
+        return Err(parser.unexpected());
+    }
+    ast.root = root;
+    Ok(())
+}
+
+/// Whether `input` stops in the middle of a command, like an `if` without
+/// its `fi`, an open quote or a trailing `&&`, so the caller should read
+/// more lines before running it.
+pub fn needs_more_input(input: &[u8]) -> bool {
+    // A backslash before the final newline continues the line
+    let line = input.strip_suffix(b"\n").unwrap_or(input);
+    let backslashes = line.iter().rev().take_while(|&&b| b == b'\\').count();
+    if line.len() < input.len() && backslashes % 2 == 1 {
+        return true;
+    }
+    
+    let mut tokens = [Token::Word(&[]); MAX_INPUT_TOKENS];
+    match lex(input, &mut tokens) {
+        Ok(count) => {
+            let mut ast = Ast::new();
+            matches!(parse(input, &tokens[..count], &mut ast), Err(ParseError::Incomplete))
+        }
+        Err(err) => is_unterminated(err),
+    }
+}
+
+struct Parser<'p, 'a> {
+    input: &'a [u8],
+    tokens: &'a [Token<'a>],
+    pos: usize,
+    ast: &'p mut Ast<'a>,
+}
+
+impl<'a> Parser<'_, 'a> {
+    fn add(&mut self, kind: NodeKind<'a>) -> Result<NodeId, ParseError<'a>> {
+        let ast = &mut *self.ast;
+        if ast.count >= MAX_NODES {
+            return Err(ParseError::TooComplex);
+        }
+        ast.nodes[ast.count] = Node { kind, next: None };
+        ast.count += 1;
+        Ok(ast.count - 1)
+    }
+    
+    /// Appends `id` to the sibling chain from `first` to `last`.
+    fn link(&mut self, first: &mut Option<NodeId>, last: &mut Option<NodeId>, id: NodeId) {
+        match *last {
+            Some(last) => self.ast.nodes[last].next = Some(id),
+            None => *first = Some(id),
+        }
+        *last = Some(id);
+    }
+    
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).copied()
+    }
+    
+    fn peek_word(&self) -> Option<&'a [u8]> {
+        match self.peek() {
+            Some(Token::Word(word)) => Some(word),
+            _ => None,
+        }
+    }
+    
+    fn peek_op(&self) -> Option<Operator> {
+        match self.peek() {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+    
+    /// The error for the current token; running out of tokens means the
+    /// command is not finished yet.
+    fn unexpected(&self) -> ParseError<'a> {
+        match self.peek() {
+            None => ParseError::Incomplete,
+            Some(Token::Word(word)) => ParseError::Unexpected(word),
+            Some(Token::Op(op)) => ParseError::Unexpected(op.as_bytes()),
+            Some(Token::Redirect(_, kind)) => ParseError::Unexpected(kind.as_bytes()),
+        }
+    }
+    
+    /// Consumes the reserved word `word`.
+    fn expect(&mut self, word: &[u8]) -> Result<(), ParseError<'a>> {
+        if self.peek_word() != Some(word) {
+            return Err(self.unexpected());
+        }
+        self.pos += 1;
+        Ok(())
+    }
+    
+    fn skip_newlines(&mut self) {
+        while self.peek_op() == Some(Operator::Newline) {
+            self.pos += 1;
+        }
+    }
+    
+    fn starts_command(&self) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) => !LIST_TERMINATORS.contains(&word),
+            Some(Token::Redirect(..)) => true,
+            _ => false,
+        }
+    }
+    
+    /// A list of and-or lists separated by `;`, `&` or newlines. Stops at
+    /// the first token that can't start a command, like `fi` or `;;`.
+    fn list(&mut self) -> Result<Option<NodeId>, ParseError<'a>> {
+        let mut first = None;
+        let mut last = None;
+        
+        loop {
+            self.skip_newlines();
+            if !self.starts_command() {
+                break;
+            }
+            
+            let start = self.pos;
+            let and_or = self.and_or()?;
+            let text = &self.tokens[start..self.pos];
+            let background = match self.peek_op() {
+                Some(Operator::Background) => true,
+                Some(Operator::Semicolon) | Some(Operator::Newline) => false,
+                _ => {
+                    let item = self.add(NodeKind::Item { first: and_or, background: false, text })?;
+                    self.link(&mut first, &mut last, item);
+                    break;
+                }
+            };
+            self.pos += 1;
+            
+            let item = self.add(NodeKind::Item { first: and_or, background, text })?;
+            self.link(&mut first, &mut last, item);
+        }
+        
+        Ok(first)
+    }
+    
+    /// A list that must contain at least one command, like the body of a loop.
+    fn required_list(&mut self) -> Result<NodeId, ParseError<'a>> {
+        match self.list()? {
+            Some(list) => Ok(list),
+            None => Err(self.unexpected()),
+        }
+    }
+    
+    fn and_or(&mut self) -> Result<NodeId, ParseError<'a>> {
+        let first = self.pipeline(Operator::Semicolon)?;
+        let mut last = first;
+        
+        while let Some(op @ (Operator::And | Operator::Or)) = self.peek_op() {
+            self.pos += 1;
+            self.skip_newlines();
+            let next = self.pipeline(op)?;
+            self.ast.nodes[last].next = Some(next);
+            last = next;
+        }
+        
+        Ok(first)
+    }
+    
+    fn pipeline(&mut self, connector: Operator) -> Result<NodeId, ParseError<'a>> {
+        let start = self.pos;
+        let negate = self.peek_word() == Some(b"!");
+        if negate {
+            self.pos += 1;
+        }
+        
+        let first = self.command()?;
+        let mut last = first;
+        
+        while self.peek_op() == Some(Operator::Pipe) {
+            self.pos += 1;
+            self.skip_newlines();
+            let next = self.command()?;
+            self.ast.nodes[last].next = Some(next);
+            last = next;
+        }
+        
+        let text = &self.tokens[start..self.pos];
+        self.add(NodeKind::Pipeline { first, connector, negate, text })
+    }
+    
+    fn command(&mut self) -> Result<NodeId, ParseError<'a>> {
+        let command = match self.peek() {
+            Some(Token::Word(name)) if self.is_function_definition(name) => return self.function_definition(name),
+            Some(Token::Word(b"{")) => self.group()?,
+            Some(Token::Word(b"if")) => self.if_command()?,
+            Some(Token::Word(b"while")) => self.loop_command(false)?,
+            Some(Token::Word(b"until")) => self.loop_command(true)?,
+            Some(Token::Word(b"for")) => self.for_command()?,
+            Some(Token::Word(b"case")) => self.case_command()?,
+            _ if self.starts_command() => return self.simple_command(),
+            _ => return Err(self.unexpected()),
+        };
+        
+        let start = self.pos;
+        while let Some(Token::Redirect(..)) = self.peek() {
+            self.pos += 1;
+            self.redirect_target()?;
+        }
+        let redirects = &self.tokens[start..self.pos];
+        
+        self.add(NodeKind::Compound { command, redirects })
+    }
+    
+    /// Consumes the word after a redirection operator.
+    fn redirect_target(&mut self) -> Result<(), ParseError<'a>> {
+        match self.peek() {
+            Some(Token::Word(_)) => {
+                self.pos += 1;
+                Ok(())
+            }
+            // Unlike a missing command, a missing file name is not
+            // something a following line could supply
+            None | Some(Token::Op(Operator::Newline)) => Err(ParseError::Unexpected(b"newline")),
+            _ => Err(self.unexpected()),
+        }
+    }
+    
+    fn simple_command(&mut self) -> Result<NodeId, ParseError<'a>> {
+        let start = self.pos;
+        loop {
+            match self.peek() {
+                Some(Token::Word(_)) => self.pos += 1,
+                Some(Token::Redirect(..)) => {
+                    self.pos += 1;
+                    self.redirect_target()?;
+                }
+                _ => break,
+            }
+        }
+        self.add(NodeKind::Simple(&self.tokens[start..self.pos]))
+    }
+    
+    /// Input text of the tokens from `start` to `end`, which begin and end
+    /// with a word. Here-documents inside, which may run past the last
+    /// word, are included.
+    fn source(&self, start: usize, end: usize) -> &'a [u8] {
+        let offset = |word: &[u8]| word.as_ptr() as usize - self.input.as_ptr() as usize;
+        let first = match self.tokens[start] {
+            Token::Word(first) => offset(first),
+            _ => return &[],
+        };
+        let last = self.tokens[start..end].iter().filter_map(|token| match token {
+            Token::Word(word) => Some(offset(word) + word.len()),
+            _ => None,
+        }).max().unwrap_or(first);
+        &self.input[first..last]
+    }
+    
+    fn is_function_definition(&self, name: &[u8]) -> bool {
+        is_name(name) && matches!(
+            self.tokens.get(self.pos + 1..self.pos + 3),
+            Some([Token::Op(Operator::LeftParen), Token::Op(Operator::RightParen)])
+        )
+    }
+    
+    /// `NAME() COMPOUND-COMMAND`, usually `NAME() { LIST; }`
+    fn function_definition(&mut self, name: &'a [u8]) -> Result<NodeId, ParseError<'a>> {
+        self.pos += 3;
+        self.skip_newlines();
+        
+        let start = self.pos;
+        match self.peek_word() {
+            Some(b"{" | b"if" | b"while" | b"until" | b"for" | b"case") => {}
+            _ => return Err(self.unexpected()),
+        }
+        self.command()?;
+        
+        let body = self.source(start, self.pos);
+        self.add(NodeKind::Function { name, body })
+    }
+    
+    /// `{ LIST; }`
+    fn group(&mut self) -> Result<Compound<'a>, ParseError<'a>> {
+        self.pos += 1;
+        let body = self.required_list()?;
+        self.expect(b"}")?;
+        Ok(Compound::Group { body })
+    }
+    
+    /// `if LIST; then LIST; [elif LIST; then LIST;]... [else LIST;] fi`
+    fn if_command(&mut self) -> Result<Compound<'a>, ParseError<'a>> {
+        self.pos += 1;
+        let mut first = None;
+        let mut last = None;
+        
+        loop {
+            let condition = self.required_list()?;
+            self.expect(b"then")?;
+            let body = self.required_list()?;
+            let branch = self.add(NodeKind::Branch { condition: Some(condition), body })?;
+            self.link(&mut first, &mut last, branch);
+            
+            match self.peek_word() {
+                Some(b"elif") => self.pos += 1,
+                Some(b"else") => {
+                    self.pos += 1;
+                    let body = self.required_list()?;
+                    let branch = self.add(NodeKind::Branch { condition: None, body })?;
+                    self.link(&mut first, &mut last, branch);
+                    break;
+                }
+                _ => break,
+            }
+        }
+        
+        self.expect(b"fi")?;
+        Ok(Compound::If { first: first.unwrap_or(0) })
+    }
+    
+    /// `while LIST; do LIST; done` and `until LIST; do LIST; done`
+    fn loop_command(&mut self, until: bool) -> Result<Compound<'a>, ParseError<'a>> {
+        self.pos += 1;
+        let condition = self.required_list()?;
+        self.expect(b"do")?;
+        let body = self.required_list()?;
+        self.expect(b"done")?;
+        Ok(Compound::Loop { condition, body, until })
+    }
+    
+    /// `for NAME [in WORDS...]; do LIST; done`
+    fn for_command(&mut self) -> Result<Compound<'a>, ParseError<'a>> {
+        self.pos += 1;
+        let name = match self.peek_word() {
+            Some(word) if is_name(word) => word,
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        
+        let words = if self.peek_word() == Some(b"in") {
+            self.pos += 1;
+            let start = self.pos;
+            while let Some(Token::Word(_)) = self.peek() {
+                self.pos += 1;
+            }
+            let words = &self.tokens[start..self.pos];
+            match self.peek_op() {
+                Some(Operator::Semicolon) | Some(Operator::Newline) => self.pos += 1,
+                _ => return Err(self.unexpected()),
+            }
+            Some(words)
+        } else {
+            if self.peek_op() == Some(Operator::Semicolon) {
+                self.pos += 1;
+            }
+            None
+        };
+        
+        self.skip_newlines();
+        self.expect(b"do")?;
+        let body = self.required_list()?;
+        self.expect(b"done")?;
+        Ok(Compound::For { name, words, body })
+    }
+    
+    /// `case WORD in [(]PATTERN[|PATTERN]...) LIST;; ... esac`. The `;;`
+    /// of the last item is optional.
+    fn case_command(&mut self) -> Result<Compound<'a>, ParseError<'a>> {
+        self.pos += 1;
+        let word = match self.peek_word() {
+            Some(word) => word,
+            None => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        self.skip_newlines();
+        self.expect(b"in")?;
+        
+        let mut first = None;
+        let mut last = None;
+        
+        loop {
+            self.skip_newlines();
+            if self.peek_word() == Some(b"esac") {
+                self.pos += 1;
+                break;
+            }
+            
+            if self.peek_op() == Some(Operator::LeftParen) {
+                self.pos += 1;
+            }
+            let start = self.pos;
+            loop {
+                if self.peek_word().is_none() {
+                    return Err(self.unexpected());
+                }
+                self.pos += 1;
+                match self.peek_op() {
+                    Some(Operator::Pipe) => self.pos += 1,
+                    Some(Operator::RightParen) => break,
+                    _ => return Err(self.unexpected()),
+                }
+            }
+            let patterns = &self.tokens[start..self.pos];
+            self.pos += 1;
+            
+            let body = self.list()?;
+            let item = self.add(NodeKind::CaseItem { patterns, body })?;
+            self.link(&mut first, &mut last, item);
+            
+            match self.peek() {
+                Some(Token::Op(Operator::DoubleSemicolon)) => self.pos += 1,
+                Some(Token::Word(b"esac")) => {}
+                _ => return Err(self.unexpected()),
+            }
+        }
+        
+        Ok(Compound::Case { word, first })
+    }
+}
@@ -0,0 +1,247 @@
+use super::lexer::{single_quoted_end, double_quoted_end, backquote_end, dollar_end};
+use crate::io::format_number;
+
+const BRACE_BUF_SIZE: usize = 1024;
+/// Brace groups expanded per word; the rest are left as they are.
+const MAX_BRACE_DEPTH: usize = 16;
+
+/// Index past the quoted string, escape or `$`-expansion at `word[i]`, or
+/// `None` if an ordinary character starts there.
+fn skip_quoted(word: &[u8], i: usize) -> Option<usize> {
+    let end = match word[i] {
+        b'\\' => Ok(i + 2),
+        b'\'' => single_quoted_end(word, i),
+        b'"' => double_quoted_end(word, i),
+        b'`' => backquote_end(word, i),
+        b'$' if matches!(word.get(i + 1), Some(b'(') | Some(b'{')) => dollar_end(word, i, false),
+        _ => return None,
+    };
+    Some(end.unwrap_or(word.len()).min(word.len()))
+}
+
+/// Finds the `}` matching the `{` at `open` and whether the group has a
+/// comma of its own.
+fn group_end(word: &[u8], open: usize) -> Option<(usize, bool)> {
+    let mut depth = 0;
+    let mut comma = false;
+    let mut i = open;
+    
+    while i < word.len() {
+        if let Some(end) = skip_quoted(word, i) {
+            i = end;
+            continue;
+        }
+        match word[i] {
+            b'{' => depth += 1,
+            b'}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some((i, comma));
+                }
+            }
+            b',' if depth == 1 => comma = true,
+            _ => {}
+        }
+        i += 1;
+    }
+    None
+}
+
+/// The range inside `{a..b}` or `{a..b..step}`: numbers or single letters.
+struct Sequence {
+    start: i64,
+    end: i64,
+    step: i64,
+    letters: bool,
+    /// Zero padding, set when an endpoint is written with a leading zero
+    width: usize,
+}
+
+fn parse_int(s: &[u8]) -> Option<i64> {
+    let (negative, digits) = match s.first() {
+        Some(b'-') => (true, &s[1..]),
+        Some(b'+') => (false, &s[1..]),
+        _ => (false, s),
+    };
+    if digits.is_empty() || digits.len() > 18 || !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    let value = digits.iter().fold(0i64, |acc, &b| acc * 10 + (b - b'0') as i64);
+    Some(if negative { -value } else { value })
+}
+
+fn parse_sequence(body: &[u8]) -> Option<Sequence> {
+    let mut parts: [&[u8]; 3] = [&[]; 3];
+    let mut count = 0;
+    let mut rest = body;
+    loop {
+        if count == 3 {
+            return None;
+        }
+        match rest.windows(2).position(|w| w == b"..") {
+            Some(pos) => {
+                parts[count] = &rest[..pos];
+                rest = &rest[pos + 2..];
+            }
+            None => {
+                parts[count] = rest;
+                count += 1;
+                break;
+            }
+        }
+        count += 1;
+    }
+    if count < 2 {
+        return None;
+    }
+    
+    let step = if count == 3 { parse_int(parts[2])?.checked_abs()?.max(1) } else { 1 };
+    
+    if let (Some(start), Some(end)) = (parse_int(parts[0]), parse_int(parts[1])) {
+        let padded = |s: &[u8]| {
+            let digits = if s.first() == Some(&b'-') { &s[1..] } else { s };
+            digits.len() > 1 && digits[0] == b'0'
+        };
+        let width = if padded(parts[0]) || padded(parts[1]) { parts[0].len().max(parts[1].len()) } else { 0 };
+        return Some(Sequence { start, end, step, letters: false, width });
+    }
+    
+    match (parts[0], parts[1]) {
+        ([a], [b]) if a.is_ascii_alphabetic() && b.is_ascii_alphabetic() => {
+            Some(Sequence { start: *a as i64, end: *b as i64, step, letters: true, width: 0 })
+        }
+        _ => None,
+    }
+}
+
+/// Writes item `value` of a sequence into `out` and returns its length.
+fn format_item(sequence: &Sequence, value: i64, out: &mut [u8; 24]) -> usize {
+    if sequence.letters {
+        out[0] = value as u8;
+        return 1;
+    }
+    
+    let mut digits = [0u8; 20];
+    let len = format_number(value, &mut digits);
+    let (sign, digits) = if value < 0 { (1, &digits[1..len]) } else { (0, &digits[..len]) };
+    let pad = sequence.width.saturating_sub(sign + digits.len());
+    
+    if value < 0 {
+        out[0] = b'-';
+    }
+    out[sign..sign + pad].fill(b'0');
+    out[sign + pad..sign + pad + digits.len()].copy_from_slice(digits);
+    sign + pad + digits.len()
+}
+
+/// Calls `f` with `prefix`, `middle` and `suffix` joined and brace-expanded.
+fn expand_joined<F>(prefix: &[u8], middle: &[u8], suffix: &[u8], depth: usize, f: &mut F) -> bool
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    // `expand` only takes words up to `BRACE_BUF_SIZE`, and a sequence
+    // item is at most 20 bytes longer than the `{a..b}` it replaces
+    let len = prefix.len() + middle.len() + suffix.len();
+    let mut buf = [0u8; BRACE_BUF_SIZE + 24];
+    buf[..prefix.len()].copy_from_slice(prefix);
+    buf[prefix.len()..prefix.len() + middle.len()].copy_from_slice(middle);
+    buf[prefix.len() + middle.len()..len].copy_from_slice(suffix);
+    expand(&buf[..len], depth + 1, f)
+}
+
+fn expand<F>(word: &[u8], depth: usize, f: &mut F) -> bool
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    if depth >= MAX_BRACE_DEPTH || word.len() > BRACE_BUF_SIZE {
+        return f(word);
+    }
+    
+    let mut i = 0;
+    while i < word.len() {
+        if let Some(end) = skip_quoted(word, i) {
+            i = end;
+            continue;
+        }
+        if word[i] != b'{' {
+            i += 1;
+            continue;
+        }
+        
+        let (close, comma) = match group_end(word, i) {
+            Some(group) => group,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        let prefix = &word[..i];
+        let body = &word[i + 1..close];
+        let suffix = &word[close + 1..];
+        
+        if comma {
+            let mut start = 0;
+            let mut j = 0;
+            let mut nested = 0;
+            while j <= body.len() {
+                if j < body.len() {
+                    if let Some(end) = skip_quoted(body, j) {
+                        j = end;
+                        continue;
+                    }
+                    match body[j] {
+                        b'{' => nested += 1,
+                        b'}' => nested -= 1,
+                        _ => {}
+                    }
+                }
+                if j == body.len() || (body[j] == b',' && nested == 0) {
+                    if !expand_joined(prefix, &body[start..j], suffix, depth, f) {
+                        return false;
+                    }
+                    start = j + 1;
+                }
+                j += 1;
+            }
+            return true;
+        }
+        
+        if let Some(sequence) = parse_sequence(body) {
+            let mut value = sequence.start;
+            loop {
+                let mut item = [0u8; 24];
+                let len = format_item(&sequence, value, &mut item);
+                if !expand_joined(prefix, &item[..len], suffix, depth, f) {
+                    return false;
+                }
+                if value == sequence.end {
+                    return true;
+                }
+                let next = if sequence.start < sequence.end {
+                    value.checked_add(sequence.step).filter(|&v| v <= sequence.end)
+                } else {
+                    value.checked_sub(sequence.step).filter(|&v| v >= sequence.end)
+                };
+                match next {
+                    Some(next) => value = next,
+                    None => return true,
+                }
+            }
+        }
+        
+        // Not a brace expansion, like `{}` or `{a}`: the `{` is literal
+        i += 1;
+    }
+    
+    f(word)
+}
+
+/// Brace expansion, the first step of word expansion: `a{b,c}d` becomes
+/// `abd` and `acd`, `{1..3}` becomes `1 2 3`. Calls `f` with every
+/// resulting word until it returns `false`.
+pub fn expand_braces<F>(word: &[u8], mut f: F) -> bool
+where
+    F: FnMut(&[u8]) -> bool,
+{
+    expand(word, 0, &mut f)
+}
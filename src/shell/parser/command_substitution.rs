@@ -0,0 +1,72 @@
+use crate::syscalls::{pipe2, read, close, waitpid, sys_exit, EINTR, O_CLOEXEC};
+use crate::shell::executor::{run_line, redirect_stdio, report_error, decode_wait_status, fork_shell, ExecState};
+use crate::shell::storage::Params;
+use crate::io::{LineBuffer, Stdio};
+
+const READ_SIZE: usize = 4096;
+
+/// Runs `command` in a forked copy of the shell, so builtins can be
+/// captured too, and appends its standard output to `out` with trailing
+/// newlines removed. The command sees `params`, whose `$?` is then set to
+/// its status. Returns `false`, after reporting it, if the output didn't
+/// fit in memory.
+pub fn run_substitution(command: &[u8], io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> bool {
+    let mut fds = [-1i32; 2];
+    if pipe2(&mut fds, O_CLOEXEC) < 0 {
+        report_error(io, b"pipe failed");
+        return true;
+    }
+    
+    let pid = fork_shell();
+    
+    if pid == 0 {
+        redirect_stdio(&Stdio { stdin: io.stdin, stdout: fds[1], stderr: io.stderr });
+        let mut state = ExecState::new();
+        state.params = *params;
+        sys_exit(run_line(command, &Stdio::standard(), &mut state));
+    }
+    
+    close(fds[1]);
+    
+    if pid < 0 {
+        close(fds[0]);
+        report_error(io, b"fork failed");
+        return true;
+    }
+    
+    let start = out.len();
+    let mut fits = true;
+    let mut discard = [0u8; 256];
+    loop {
+        // Once memory runs out the rest is read and dropped, so the
+        // command isn't left blocked on a full pipe
+        fits = fits && out.reserve(READ_SIZE);
+        let n = if fits {
+            read(fds[0], out.spare())
+        } else {
+            read(fds[0], &mut discard)
+        };
+        if n == -EINTR {
+            continue;
+        }
+        if n <= 0 {
+            break;
+        }
+        if fits {
+            out.extend_by(n as usize);
+        }
+    }
+    close(fds[0]);
+    
+    let mut status: i32 = 0;
+    while waitpid(pid as i32, &mut status, 0) == -EINTR {}
+    params.status = decode_wait_status(status);
+    
+    if !fits {
+        report_error(io, b"out of memory");
+        return false;
+    }
+    let trailing = out.as_slice()[start..].iter().rev().take_while(|&&b| b == b'\n').count();
+    out.truncate(out.len() - trailing);
+    true
+}
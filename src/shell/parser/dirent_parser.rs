@@ -45,6 +45,7 @@ impl<'a> DirentParser<'a> {
         
         let entry = DirentEntry {
             name: &self.buf[name_start..name_end],
+            kind: self.buf[self.pos + 18],
         };
         
         self.pos += reclen;
@@ -54,4 +55,6 @@ impl<'a> DirentParser<'a> {
 
 pub struct DirentEntry<'a> {
     pub name: &'a [u8],
+    /// The `d_type` of the entry, such as `DT_DIR`
+    pub kind: u8,
 }
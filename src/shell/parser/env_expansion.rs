@@ -1,30 +1,97 @@
-use crate::shell::storage::ENV_STORAGE;
+use crate::shell::storage::{ENV_STORAGE, SHELL_NAME, Params, shell_pid};
+use crate::io::{format_number, LineBuffer};
 
-pub fn expand_env_vars(input: &[u8], output: &mut [u8]) -> usize {
-    let mut out_idx = 0;
+pub fn is_name_start(b: u8) -> bool {
+    b.is_ascii_alphabetic() || b == b'_'
+}
+
+pub fn is_name_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b == b'_'
+}
+
+/// Whether `word` is a valid variable or function name.
+pub fn is_name(word: &[u8]) -> bool {
+    word.first().is_some_and(|&b| is_name_start(b)) && word.iter().all(|&b| is_name_byte(b))
+}
+
+/// Parameters that are a single character after `$`, like `$?` or `$1`.
+pub fn is_special_param(b: u8) -> bool {
+    matches!(b, b'?' | b'$' | b'!' | b'#' | b'@' | b'*') || b.is_ascii_digit()
+}
+
+fn push_number(n: i64, out: &mut LineBuffer) -> Result<bool, &'static str> {
+    let mut digits = [0u8; 20];
+    let len = format_number(n, &mut digits);
+    if out.push(&digits[..len]) { Ok(true) } else { Err("out of memory") }
+}
+
+/// Appends the value of parameter `name` to `out`: a variable, a
+/// positional parameter or one of the special parameters. Returns `false`
+/// if it is unset, and an error if there is no room for the value.
+pub fn lookup_param(name: &[u8], params: &Params, out: &mut LineBuffer) -> Result<bool, &'static str> {
+    let args = &params.args;
+    match name {
+        b"?" => push_number(params.status as i64, out),
+        b"$" => push_number(shell_pid() as i64, out),
+        b"!" => match params.background_pid {
+            0 => Ok(false),
+            pid => push_number(pid as i64, out),
+        },
+        b"#" => push_number(args.count() as i64, out),
+        b"@" | b"*" => {
+            if args.count() == 0 {
+                return Ok(false);
+            }
+            let copied = args.iter().enumerate().all(|(i, arg)| (i == 0 || out.push(b" ")) && out.push(arg));
+            if copied { Ok(true) } else { Err("out of memory") }
+        }
+        _ if !name.is_empty() && name.iter().all(|b| b.is_ascii_digit()) => {
+            let n = name.iter().fold(0usize, |acc, &b| acc.saturating_mul(10).saturating_add((b - b'0') as usize));
+            let copied = match (n, args.get(n)) {
+                (0, _) => SHELL_NAME.get(out),
+                (_, Some(arg)) => out.push(arg),
+                (_, None) => return Ok(false),
+            };
+            if copied { Ok(true) } else { Err("out of memory") }
+        }
+        _ => match ENV_STORAGE.copy(name, out)? {
+            Some((_, has_value)) => Ok(has_value),
+            None => Ok(false),
+        },
+    }
+}
+
+/// Expands `$NAME` and the special parameters in `input`, appending the
+/// result to `output`. Unset parameters expand to nothing; a `$` that
+/// starts no parameter is kept.
+pub fn expand_env_vars(input: &[u8], params: &Params, output: &mut LineBuffer) -> Result<(), &'static str> {
     let mut i = 0;
     
     while i < input.len() && input[i] != 0 {
-        if input[i] == b'$' && i + 1 < input.len() {
+        if input[i] == b'$' && i + 1 < input.len() && is_special_param(input[i + 1]) {
+            lookup_param(&input[i + 1..i + 2], params, output)?;
+            i += 2;
+        } else if input[i] == b'$' && i + 1 < input.len() && is_name_start(input[i + 1]) {
             i += 1;
             let var_start = i;
-            while i < input.len() && (input[i].is_ascii_alphanumeric() || input[i] == b'_') {
+            while i < input.len() && is_name_byte(input[i]) {
                 i += 1;
             }
             
-            if i > var_start {
-                let var_name = &input[var_start..i];
-                let remaining = &mut output[out_idx..];
-                let len = ENV_STORAGE.get(var_name, remaining);
-                out_idx += len;
-            }
+            lookup_param(&input[var_start..i], params, output)?;
         } else {
-            if out_idx >= output.len() { return out_idx; }
-            output[out_idx] = input[i];
-            out_idx += 1;
-            i += 1;
+            let start = i;
+            while i < input.len() && input[i] != 0 && input[i] != b'$' {
+                i += 1;
+            }
+            // A `$` that starts no parameter
+            let end = if i == start { i + 1 } else { i };
+            if !output.push(&input[start..end]) {
+                return Err("out of memory");
+            }
+            i = end;
         }
     }
     
-    out_idx
+    Ok(())
 }
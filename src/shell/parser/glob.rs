@@ -0,0 +1,130 @@
+use super::dirent_parser::DirentParser;
+use super::pattern::pattern_matches;
+use super::token_list::TokenList;
+use crate::syscalls::{open, close, getdents64, access, O_RDONLY, O_DIRECTORY, O_CLOEXEC, F_OK};
+
+const GLOB_PATH_SIZE: usize = 1024;
+
+/// Whether `pattern` has a `*`, `?` or `[` that is not escaped.
+fn has_wildcards(pattern: &[u8]) -> bool {
+    let mut i = 0;
+    while i < pattern.len() {
+        match pattern[i] {
+            b'\\' => i += 2,
+            b'*' | b'?' | b'[' => return true,
+            _ => i += 1,
+        }
+    }
+    false
+}
+
+/// Appends the paths matching `pattern` to `tokens`, sorted, and returns
+/// how many there were.
+pub fn glob(pattern: &[u8], tokens: &mut TokenList) -> usize {
+    let first = tokens.len();
+    let mut path = [0u8; GLOB_PATH_SIZE];
+    let mut len = 0;
+    
+    if pattern.first() == Some(&b'/') {
+        path[0] = b'/';
+        len = 1;
+    }
+    
+    walk(&mut path, len, skip_slashes(pattern), tokens);
+    tokens.sort_from(first);
+    tokens.len() - first
+}
+
+fn skip_slashes(pattern: &[u8]) -> &[u8] {
+    let start = pattern.iter().position(|&b| b != b'/').unwrap_or(pattern.len());
+    &pattern[start..]
+}
+
+/// Appends `bytes` to the path, keeping room for a terminating NUL.
+fn append(path: &mut [u8; GLOB_PATH_SIZE], len: &mut usize, bytes: &[u8]) -> bool {
+    if *len + bytes.len() >= GLOB_PATH_SIZE {
+        return false;
+    }
+    path[*len..*len + bytes.len()].copy_from_slice(bytes);
+    *len += bytes.len();
+    true
+}
+
+fn exists(path: &mut [u8; GLOB_PATH_SIZE], len: usize) -> bool {
+    path[len] = 0;
+    len > 0 && access(&path[..len + 1], F_OK) == 0
+}
+
+/// Matches `pattern` one path component at a time below `path[..len]`,
+/// which is empty for the current directory or ends with a `/`.
+fn walk(path: &mut [u8; GLOB_PATH_SIZE], len: usize, pattern: &[u8], tokens: &mut TokenList) {
+    if pattern.is_empty() {
+        if exists(path, len) {
+            tokens.push_slice(&path[..len]);
+            tokens.finish();
+        }
+        return;
+    }
+    
+    let end = pattern.iter().position(|&b| b == b'/').unwrap_or(pattern.len());
+    let component = &pattern[..end];
+    let slash = end < pattern.len();
+    let rest = skip_slashes(&pattern[end..]);
+    
+    if !has_wildcards(component) {
+        let mut len = len;
+        let mut i = 0;
+        while i < component.len() {
+            if component[i] == b'\\' && i + 1 < component.len() {
+                i += 1;
+            }
+            if !append(path, &mut len, &component[i..i + 1]) {
+                return;
+            }
+            i += 1;
+        }
+        if slash && !append(path, &mut len, b"/") {
+            return;
+        }
+        walk(path, len, rest, tokens);
+        return;
+    }
+    
+    path[len] = 0;
+    let dir: &[u8] = if len == 0 { b".\0" } else { &path[..len + 1] };
+    let fd = open(dir, O_RDONLY | O_DIRECTORY | O_CLOEXEC, 0);
+    if fd < 0 {
+        return;
+    }
+    
+    let mut buf = [0u8; 1024];
+    loop {
+        let nread = getdents64(fd as i32, &mut buf);
+        if nread <= 0 {
+            break;
+        }
+        
+        let mut parser = DirentParser::new(&buf[..nread as usize]);
+        while let Some(entry) = parser.next() {
+            let name = entry.name;
+            if name.is_empty() || name == b"." || name == b".." {
+                continue;
+            }
+            // Dotfiles only match a pattern that starts with a `.` itself
+            if name[0] == b'.' && component[0] != b'.' {
+                continue;
+            }
+            if !pattern_matches(component, name) {
+                continue;
+            }
+            
+            let mut entry_len = len;
+            if !append(path, &mut entry_len, name) || (slash && !append(path, &mut entry_len, b"/")) {
+                continue;
+            }
+            walk(path, entry_len, rest, tokens);
+        }
+    }
+    
+    close(fd as i32);
+}
@@ -0,0 +1,438 @@
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t' || b == b'\r'
+}
+
+fn is_operator_start(b: u8) -> bool {
+    matches!(b, b'|' | b'<' | b'>' | b';' | b'&' | b'\n' | b'(' | b')')
+}
+
+/// Tokens in one parsed chunk of input, e.g. a command line or a compound
+/// command typed over several lines.
+pub const MAX_INPUT_TOKENS: usize = 256;
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Operator {
+    Pipe,
+    And,
+    Or,
+    Semicolon,
+    Background,
+    Newline,
+    /// `;;`, ending a `case` item
+    DoubleSemicolon,
+    LeftParen,
+    RightParen,
+}
+
+impl Operator {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Operator::Pipe => b"|",
+            Operator::And => b"&&",
+            Operator::Or => b"||",
+            Operator::Semicolon => b";",
+            Operator::Background => b"&",
+            Operator::Newline => b"newline",
+            Operator::DoubleSemicolon => b";;",
+            Operator::LeftParen => b"(",
+            Operator::RightParen => b")",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RedirectKind {
+    /// `<file`
+    Input,
+    /// `>file`
+    Output,
+    /// `>>file`
+    Append,
+    /// `<&N`
+    DupInput,
+    /// `>&N`
+    DupOutput,
+    /// `&>file`, stdout and stderr to the same file
+    OutputAll,
+    /// `&>>file`
+    AppendAll,
+    /// `<<WORD` or, with `strip_tabs`, `<<-WORD`. The lines up to WORD are
+    /// the input, with expansions done unless WORD was quoted (`literal`).
+    HereDoc { strip_tabs: bool, literal: bool },
+    /// `<<<word`, the expanded word and a newline as input
+    HereString,
+}
+
+impl RedirectKind {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            RedirectKind::Input => b"<",
+            RedirectKind::Output => b">",
+            RedirectKind::Append => b">>",
+            RedirectKind::DupInput => b"<&",
+            RedirectKind::DupOutput => b">&",
+            RedirectKind::OutputAll => b"&>",
+            RedirectKind::AppendAll => b"&>>",
+            RedirectKind::HereDoc { strip_tabs: false, .. } => b"<<",
+            RedirectKind::HereDoc { strip_tabs: true, .. } => b"<<-",
+            RedirectKind::HereString => b"<<<",
+        }
+    }
+    
+    /// The fd redirected when no number is written before the operator.
+    pub fn default_fd(&self) -> i32 {
+        match self {
+            RedirectKind::Input | RedirectKind::DupInput | RedirectKind::HereDoc { .. } | RedirectKind::HereString => 0,
+            _ => 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum Token<'a> {
+    /// Raw word with quotes and backslashes still in place. The word after
+    /// a here-document redirection holds the lines of the document instead,
+    /// ending with the delimiter line.
+    Word(&'a [u8]),
+    Op(Operator),
+    /// Redirection of `fd`; the target is the next word.
+    Redirect(i32, RedirectKind),
+}
+
+/// Splits a command line into raw words and operators.
+///
+/// Quotes and backslashes are kept in word slices; they only decide where a
+/// word ends. Quote removal happens in `expand_word`.
+pub struct Lexer<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Lexer<'a> {
+    pub fn new(input: &'a [u8]) -> Self {
+        Self { input, pos: 0 }
+    }
+    
+    pub fn next_token(&mut self) -> Result<Option<Token<'a>>, &'static str> {
+        let input = self.input;
+        
+        while self.pos < input.len() && is_blank(input[self.pos]) {
+            self.pos += 1;
+        }
+        
+        // A `#` starting a word comments out the rest of the line
+        if self.pos < input.len() && input[self.pos] == b'#' {
+            while self.pos < input.len() && input[self.pos] != b'\n' {
+                self.pos += 1;
+            }
+        }
+        
+        if self.pos >= input.len() || input[self.pos] == 0 {
+            return Ok(None);
+        }
+        
+        if let Some(token) = self.redirect() {
+            return Ok(Some(token));
+        }
+        
+        if let Some(op) = self.operator() {
+            return Ok(Some(Token::Op(op)));
+        }
+        
+        let start = self.pos;
+        
+        while self.pos < input.len() && input[self.pos] != 0
+            && !is_blank(input[self.pos]) && !is_operator_start(input[self.pos])
+        {
+            self.pos = match input[self.pos] {
+                b'\\' => (self.pos + 2).min(input.len()),
+                b'\'' => single_quoted_end(input, self.pos)?,
+                b'"' => double_quoted_end(input, self.pos)?,
+                b'`' => backquote_end(input, self.pos)?,
+                b'$' => dollar_end(input, self.pos, false)?,
+                _ => self.pos + 1,
+            };
+        }
+        
+        Ok(Some(Token::Word(&input[start..self.pos])))
+    }
+    
+    fn operator(&mut self) -> Option<Operator> {
+        let rest = &self.input[self.pos..];
+        let (op, len) = if rest.starts_with(b"&&") {
+            (Operator::And, 2)
+        } else if rest.starts_with(b"||") {
+            (Operator::Or, 2)
+        } else if rest.starts_with(b"|") {
+            (Operator::Pipe, 1)
+        } else if rest.starts_with(b";;") {
+            (Operator::DoubleSemicolon, 2)
+        } else if rest.starts_with(b";") {
+            (Operator::Semicolon, 1)
+        } else if rest.starts_with(b"&") {
+            (Operator::Background, 1)
+        } else if rest.starts_with(b"\n") {
+            (Operator::Newline, 1)
+        } else if rest.starts_with(b"(") {
+            (Operator::LeftParen, 1)
+        } else if rest.starts_with(b")") {
+            (Operator::RightParen, 1)
+        } else {
+            return None;
+        };
+        
+        self.pos += len;
+        Some(op)
+    }
+    
+    /// Recognizes `<`, `>`, `>>`, `<&`, `>&`, `&>`, `&>>`, `<<`, `<<-` and
+    /// `<<<`, optionally preceded by a file descriptor number such as `2>`.
+    fn redirect(&mut self) -> Option<Token<'a>> {
+        let input = self.input;
+        let mut pos = self.pos;
+        
+        let mut fd: Option<i32> = None;
+        while pos < input.len() && input[pos].is_ascii_digit() {
+            let digit = (input[pos] - b'0') as i32;
+            fd = Some(fd.unwrap_or(0).saturating_mul(10).saturating_add(digit));
+            pos += 1;
+        }
+        
+        let rest = &input[pos..];
+        let (kind, len) = if rest.starts_with(b"&>>") && fd.is_none() {
+            (RedirectKind::AppendAll, 3)
+        } else if rest.starts_with(b"&>") && fd.is_none() {
+            (RedirectKind::OutputAll, 2)
+        } else if rest.starts_with(b">>") {
+            (RedirectKind::Append, 2)
+        } else if rest.starts_with(b">&") {
+            (RedirectKind::DupOutput, 2)
+        } else if rest.starts_with(b"<<<") {
+            (RedirectKind::HereString, 3)
+        } else if rest.starts_with(b"<<-") {
+            (RedirectKind::HereDoc { strip_tabs: true, literal: false }, 3)
+        } else if rest.starts_with(b"<<") {
+            (RedirectKind::HereDoc { strip_tabs: false, literal: false }, 2)
+        } else if rest.starts_with(b"<&") {
+            (RedirectKind::DupInput, 2)
+        } else if rest.starts_with(b">") {
+            (RedirectKind::Output, 1)
+        } else if rest.starts_with(b"<") {
+            (RedirectKind::Input, 1)
+        } else {
+            return None;
+        };
+        
+        self.pos = pos + len;
+        Some(Token::Redirect(fd.unwrap_or(kind.default_fd()), kind))
+    }
+    
+    /// Reads the lines of the here-document redirected by `tokens`, which
+    /// are its redirection and delimiter word, from the current position up
+    /// to the delimiter line. The word becomes the text of those lines.
+    fn here_doc(&mut self, tokens: &mut [Token<'a>]) -> Result<(), &'static str> {
+        let (fd, strip_tabs, delimiter) = match *tokens {
+            [Token::Redirect(fd, RedirectKind::HereDoc { strip_tabs, .. }), Token::Word(delimiter)] => (fd, strip_tabs, delimiter),
+            _ => return Ok(()),
+        };
+        
+        let input = self.input;
+        let start = self.pos;
+        while self.pos < input.len() {
+            let end = input[self.pos..].iter().position(|&b| b == b'\n').map_or(input.len(), |n| self.pos + n);
+            let mut line = &input[self.pos..end];
+            if strip_tabs {
+                while let [b'\t', rest @ ..] = line {
+                    line = rest;
+                }
+            }
+            self.pos = (end + 1).min(input.len());
+            
+            if is_delimiter(line, delimiter) {
+                let literal = delimiter.iter().any(|&b| matches!(b, b'\'' | b'"' | b'\\'));
+                tokens[0] = Token::Redirect(fd, RedirectKind::HereDoc { strip_tabs, literal });
+                tokens[1] = Token::Word(&input[start..end]);
+                return Ok(());
+            }
+        }
+        
+        Err("unterminated here-document")
+    }
+}
+
+/// Whether `line` is the here-document delimiter `word` without its quotes.
+fn is_delimiter(line: &[u8], word: &[u8]) -> bool {
+    let mut rest = line;
+    let mut i = 0;
+    
+    while i < word.len() {
+        let b = match word[i] {
+            b'\'' | b'"' => {
+                i += 1;
+                continue;
+            }
+            b'\\' if i + 1 < word.len() => {
+                i += 1;
+                word[i]
+            }
+            b => b,
+        };
+        match rest {
+            [first, tail @ ..] if *first == b => rest = tail,
+            _ => return false,
+        }
+        i += 1;
+    }
+    
+    rest.is_empty()
+}
+
+/// Index just past the `'` closing the quote at `start`.
+pub fn single_quoted_end(input: &[u8], start: usize) -> Result<usize, &'static str> {
+    match input[start + 1..].iter().position(|&b| b == b'\'') {
+        Some(offset) => Ok(start + offset + 2),
+        None => Err("unterminated single quote"),
+    }
+}
+
+/// Index just past the `"` closing the quote at `start`. Substitutions
+/// inside may contain quotes of their own.
+pub fn double_quoted_end(input: &[u8], start: usize) -> Result<usize, &'static str> {
+    let mut pos = start + 1;
+    
+    while pos < input.len() {
+        pos = match input[pos] {
+            b'\\' => pos + 2,
+            b'"' => return Ok(pos + 1),
+            b'`' => backquote_end(input, pos)?,
+            b'$' => dollar_end(input, pos, true)?,
+            _ => pos + 1,
+        };
+    }
+    
+    Err("unterminated double quote")
+}
+
+/// Index just past the `` ` `` closing the one at `start`.
+pub fn backquote_end(input: &[u8], start: usize) -> Result<usize, &'static str> {
+    let mut pos = start + 1;
+    
+    while pos < input.len() {
+        pos = match input[pos] {
+            b'\\' => pos + 2,
+            b'`' => return Ok(pos + 1),
+            _ => pos + 1,
+        };
+    }
+    
+    Err("unterminated backquote")
+}
+
+/// Index just past the `)` closing the `$(` at `start`, skipping nested
+/// substitutions, quotes and parentheses.
+pub fn substitution_end(input: &[u8], start: usize) -> Result<usize, &'static str> {
+    let mut pos = start + 2;
+    let mut depth = 1;
+    
+    while pos < input.len() {
+        pos = match input[pos] {
+            b'\\' => pos + 2,
+            b'\'' => single_quoted_end(input, pos)?,
+            b'"' => double_quoted_end(input, pos)?,
+            b'`' => backquote_end(input, pos)?,
+            b'$' => dollar_end(input, pos, false)?,
+            b'(' => {
+                depth += 1;
+                pos + 1
+            }
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Ok(pos + 1);
+                }
+                pos + 1
+            }
+            _ => pos + 1,
+        };
+    }
+    
+    Err("unterminated command substitution")
+}
+
+/// Index just past the `}` closing the `${` at `start`. Inside double
+/// quotes a `'` is an ordinary character.
+pub fn brace_end(input: &[u8], start: usize, quoted: bool) -> Result<usize, &'static str> {
+    let mut pos = start + 2;
+    
+    while pos < input.len() {
+        pos = match input[pos] {
+            b'\\' => pos + 2,
+            b'\'' if !quoted => single_quoted_end(input, pos)?,
+            b'"' => double_quoted_end(input, pos)?,
+            b'`' => backquote_end(input, pos)?,
+            b'$' => dollar_end(input, pos, quoted)?,
+            b'}' => return Ok(pos + 1),
+            _ => pos + 1,
+        };
+    }
+    
+    Err("unterminated parameter expansion")
+}
+
+/// Index just past a `$(...)` or `${...}` at `start`, or past the `$` alone.
+pub fn dollar_end(input: &[u8], start: usize, quoted: bool) -> Result<usize, &'static str> {
+    match input.get(start + 1) {
+        Some(b'(') => substitution_end(input, start),
+        Some(b'{') => brace_end(input, start, quoted),
+        _ => Ok(start + 1),
+    }
+}
+
+/// Here-documents one line may start.
+const MAX_HERE_DOCS: usize = 16;
+
+/// Splits `line` into tokens and returns how many were stored in `out`.
+/// The bodies of here-documents are read after the line that starts them.
+pub fn lex<'a>(line: &'a [u8], out: &mut [Token<'a>]) -> Result<usize, &'static str> {
+    let mut lexer = Lexer::new(line);
+    let mut count = 0;
+    // Delimiter words whose here-documents start after the next newline
+    let mut here_docs = [0usize; MAX_HERE_DOCS];
+    let mut here_doc_count = 0;
+    
+    while let Some(token) = lexer.next_token()? {
+        if count >= out.len() {
+            return Err("too many words");
+        }
+        out[count] = token;
+        count += 1;
+        
+        match token {
+            Token::Word(_) if count > 1 && matches!(out[count - 2], Token::Redirect(_, RedirectKind::HereDoc { .. })) => {
+                if here_doc_count == MAX_HERE_DOCS {
+                    return Err("too many here-documents");
+                }
+                here_docs[here_doc_count] = count - 1;
+                here_doc_count += 1;
+            }
+            Token::Op(Operator::Newline) => {
+                for &word in &here_docs[..here_doc_count] {
+                    lexer.here_doc(&mut out[word - 1..word + 1])?;
+                }
+                here_doc_count = 0;
+            }
+            _ => {}
+        }
+    }
+    
+    if here_doc_count > 0 {
+        return Err("unterminated here-document");
+    }
+    Ok(count)
+}
+
+/// Whether a `lex` error only means the input stops inside a quote or
+/// substitution, which more lines may close.
+pub fn is_unterminated(err: &str) -> bool {
+    err.starts_with("unterminated")
+}
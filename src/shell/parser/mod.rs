@@ -1,7 +1,27 @@
 mod env_expansion;
+mod alias_expansion;
+mod brace_expansion;
+mod tilde_expansion;
 mod dirent_parser;
 mod path_finder;
+mod token_list;
+mod lexer;
+mod ast;
+mod word_expansion;
+mod command_substitution;
+mod parameter_expansion;
+mod pattern;
+mod arithmetic;
+mod glob;
 
-pub use env_expansion::expand_env_vars;
 pub use dirent_parser::{DirentParser};
+pub use tilde_expansion::expand_tilde;
 pub use path_finder::find_in_path;
+pub use env_expansion::is_name;
+pub use alias_expansion::{expand_aliases, is_alias_name, ALIAS_LINE_SIZE};
+pub use token_list::{TokenList, MAX_TOKENS};
+pub use lexer::{lex, Token, Operator, RedirectKind, MAX_INPUT_TOKENS};
+pub use ast::{parse, needs_more_input, is_reserved_word, Ast, NodeId, NodeKind, Compound, ParseError};
+pub use word_expansion::{expand_words, expand_flat, expand_pattern, expand_here_doc};
+pub use pattern::pattern_matches;
+pub use arithmetic::evaluate_arithmetic;
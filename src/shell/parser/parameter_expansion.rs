@@ -0,0 +1,158 @@
+use super::env_expansion::{lookup_param, is_name_start, is_name_byte, is_special_param};
+use super::lexer::brace_end;
+use super::pattern::pattern_matches;
+use super::word_expansion::{expand_flat, append};
+use crate::syscalls::write;
+use crate::shell::executor::report_error;
+use crate::shell::storage::{ENV_STORAGE, Params};
+use crate::io::{format_number, LineBuffer, Stdio};
+
+/// Expands the `${...}` starting at `word[i]`, appending the result to
+/// `out`. Returns the index just past the closing brace, or `None` if an
+/// error was reported.
+pub fn expand_parameter(word: &[u8], i: usize, quoted: bool, io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<usize> {
+    let end = brace_end(word, i, quoted).unwrap_or(word.len());
+    let body = &word[i + 2..(end - 1).max(i + 2)];
+    expand_body(body, io, params, out)?;
+    Some(end)
+}
+
+/// Length of the parameter name at the start of `body`: a variable name,
+/// a positional parameter number or a single special character.
+fn name_len(body: &[u8]) -> usize {
+    match body.first() {
+        Some(b) if b.is_ascii_digit() => body.iter().take_while(|b| b.is_ascii_digit()).count(),
+        Some(&b) if is_special_param(b) => 1,
+        Some(&b) if is_name_start(b) => body.iter().take_while(|&&b| is_name_byte(b)).count(),
+        _ => 0,
+    }
+}
+
+/// Appends the value of `name` to `value`. Returns whether it is set, or
+/// `None` if an error was reported.
+fn lookup(name: &[u8], io: &Stdio, params: &Params, value: &mut LineBuffer) -> Option<bool> {
+    match lookup_param(name, params, value) {
+        Ok(found) => Some(found),
+        Err(err) => {
+            report_error(io, err.as_bytes());
+            None
+        }
+    }
+}
+
+fn bad_substitution(io: &Stdio, body: &[u8]) -> Option<()> {
+    write(io.stderr, b"reshell: ${");
+    write(io.stderr, body);
+    write(io.stderr, b"}: bad substitution\n");
+    None
+}
+
+fn expand_body(body: &[u8], io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<()> {
+    let mut value = LineBuffer::new();
+    
+    // `${#NAME}` is the length of the value in characters; `${#}` is `$#`
+    if body.len() > 1 && body[0] == b'#' {
+        let name = &body[1..];
+        if name_len(name) != name.len() {
+            return bad_substitution(io, body);
+        }
+        lookup(name, io, params, &mut value)?;
+        let chars = value.as_slice().iter().filter(|&&b| b & 0xc0 != 0x80).count();
+        let mut digits = [0u8; 20];
+        let digits_len = format_number(chars as i64, &mut digits);
+        return append(out, &digits[..digits_len], io);
+    }
+    
+    let name = &body[..name_len(body)];
+    if name.is_empty() {
+        return bad_substitution(io, body);
+    }
+    
+    let found = lookup(name, io, params, &mut value)?;
+    let value = value.as_slice();
+    
+    let rest = &body[name.len()..];
+    if rest.is_empty() {
+        return append(out, value, io);
+    }
+    
+    let colon = rest[0] == b':';
+    let rest = if colon { &rest[1..] } else { rest };
+    let (op, operand) = match rest.split_first() {
+        Some((&op, operand)) => (op, operand),
+        None => return bad_substitution(io, body),
+    };
+    
+    // With a colon, a variable set to the empty string counts as unset
+    let missing = !found || (colon && value.is_empty());
+    
+    match op {
+        b'-' if missing => expand_flat(operand, io, params, out),
+        b'=' if missing => {
+            if !is_name_start(name[0]) {
+                write(io.stderr, b"reshell: $");
+                write(io.stderr, name);
+                write(io.stderr, b": cannot assign in this way\n");
+                return None;
+            }
+            let start = out.len();
+            expand_flat(operand, io, params, out)?;
+            if let Err(err) = ENV_STORAGE.set(name, &out.as_slice()[start..]) {
+                write(io.stderr, b"reshell: ");
+                write(io.stderr, name);
+                write(io.stderr, b": ");
+                write(io.stderr, err.as_bytes());
+                write(io.stderr, b"\n");
+                return None;
+            }
+            Some(())
+        }
+        b'?' if missing => {
+            let start = out.len();
+            expand_flat(operand, io, params, out)?;
+            let message = &out.as_slice()[start..];
+            write(io.stderr, b"reshell: ");
+            write(io.stderr, name);
+            write(io.stderr, b": ");
+            if message.is_empty() {
+                write(io.stderr, b"parameter null or not set");
+            } else {
+                write(io.stderr, message);
+            }
+            write(io.stderr, b"\n");
+            None
+        }
+        b'+' if missing => Some(()),
+        b'+' => expand_flat(operand, io, params, out),
+        b'-' | b'=' | b'?' => append(out, value, io),
+        b'#' | b'%' if !colon => {
+            let longest = operand.first() == Some(&op);
+            let operand = if longest { &operand[1..] } else { operand };
+            let mut pattern = LineBuffer::new();
+            expand_flat(operand, io, params, &mut pattern)?;
+            let kept = remove_match(value, pattern.as_slice(), op == b'#', longest);
+            append(out, kept, io)
+        }
+        _ => bad_substitution(io, body),
+    }
+}
+
+/// Removes the shortest or longest prefix (`#`, `##`) or suffix (`%`, `%%`)
+/// of `value` that matches `pattern`.
+fn remove_match<'a>(value: &'a [u8], pattern: &[u8], prefix: bool, longest: bool) -> &'a [u8] {
+    let len = value.len();
+    
+    for step in 0..=len {
+        // Prefixes grow from the start and suffixes from the end; longest
+        // matches are tried from the other side
+        let cut = if prefix == longest { len - step } else { step };
+        if prefix && pattern_matches(pattern, &value[..cut]) {
+            return &value[cut..];
+        }
+        if !prefix && pattern_matches(pattern, &value[cut..]) {
+            return &value[..cut];
+        }
+    }
+    
+    value
+}
@@ -1,43 +1,45 @@
+use crate::syscalls::{access, X_OK};
+use crate::shell::storage::ENV_STORAGE;
+use crate::io::LineBuffer;
+
+/// Where commands are looked for when `PATH` is unset.
+const DEFAULT_PATH: &[u8] = b"/bin:/usr/bin:/usr/local/bin";
+
+/// Writes the NUL-terminated path to run `cmd` from to `out_buf`: `cmd`
+/// itself if it has a slash, otherwise the first executable file of that
+/// name in the directories of `PATH`. Returns `false` if there is none.
 pub fn find_in_path(cmd: &[u8], out_buf: &mut [u8]) -> bool {
-    const PATHS: &[&[u8]] = &[
-        b"/bin/",
-        b"/usr/bin/",
-        b"/usr/local/bin/",
-    ];
-    
-    if cmd.len() > 0 && cmd[0] == b'/' {
-        let mut idx = 0;
-        for &b in cmd {
-            if idx >= out_buf.len() - 1 {
-                break;
-            }
-            out_buf[idx] = b;
-            idx += 1;
-        }
-        out_buf[idx] = 0;
-        return true;
+    if cmd.is_empty() {
+        return false;
+    }
+    if cmd.contains(&b'/') {
+        return copy_path(&[cmd], out_buf);
     }
     
-    for path in PATHS {
-        let mut idx = 0;
-        for &b in *path {
-            if idx >= out_buf.len() - cmd.len() - 1 {
-                break;
-            }
-            out_buf[idx] = b;
-            idx += 1;
-        }
-        
-        for &b in cmd {
-            if idx >= out_buf.len() - 1 {
-                break;
-            }
-            out_buf[idx] = b;
-            idx += 1;
+    let mut path = LineBuffer::new();
+    let dirs = if ENV_STORAGE.get_all(b"PATH", &mut path) { path.as_slice() } else { DEFAULT_PATH };
+    for dir in dirs.split(|&b| b == b':') {
+        // An empty entry stands for the current directory
+        let dir: &[u8] = if dir.is_empty() { b"." } else { dir };
+        if copy_path(&[dir, b"/", cmd], out_buf) && access(out_buf, X_OK) == 0 {
+            return true;
         }
-        out_buf[idx] = 0;
-        
-        return true;
     }
     false
 }
+
+/// Joins `parts` into `out_buf` with a NUL at the end. Returns `false` if
+/// they don't fit.
+fn copy_path(parts: &[&[u8]], out_buf: &mut [u8]) -> bool {
+    let len: usize = parts.iter().map(|part| part.len()).sum();
+    if len >= out_buf.len() {
+        return false;
+    }
+    let mut idx = 0;
+    for part in parts {
+        out_buf[idx..idx + part.len()].copy_from_slice(part);
+        idx += part.len();
+    }
+    out_buf[idx] = 0;
+    true
+}
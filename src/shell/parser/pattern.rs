@@ -0,0 +1,112 @@
+/// Matches `text` against a shell pattern: `*`, `?`, bracket expressions
+/// like `[a-z]`, `[!0-9]` or `[[:alpha:]]`, and backslash escapes.
+pub fn pattern_matches(pattern: &[u8], text: &[u8]) -> bool {
+    let mut p = 0;
+    let mut t = 0;
+    // Pattern index after the last `*` and the text index it was tried at
+    let mut backtrack: Option<(usize, usize)> = None;
+    
+    while t < text.len() {
+        let next = if p < pattern.len() {
+            match pattern[p] {
+                b'*' => {
+                    p += 1;
+                    backtrack = Some((p, t));
+                    continue;
+                }
+                b'?' => Some(p + 1),
+                b'[' => match bracket(pattern, p, text[t]) {
+                    Some((true, end)) => Some(end),
+                    Some((false, _)) => None,
+                    None if text[t] == b'[' => Some(p + 1),
+                    None => None,
+                },
+                b'\\' if p + 1 < pattern.len() => {
+                    if pattern[p + 1] == text[t] { Some(p + 2) } else { None }
+                }
+                c => {
+                    if c == text[t] { Some(p + 1) } else { None }
+                }
+            }
+        } else {
+            None
+        };
+        
+        match (next, backtrack) {
+            (Some(end), _) => {
+                p = end;
+                t += 1;
+            }
+            (None, Some((star_p, star_t))) => {
+                // Let the last `*` swallow one more byte and retry
+                p = star_p;
+                t = star_t + 1;
+                backtrack = Some((star_p, star_t + 1));
+            }
+            (None, None) => return false,
+        }
+    }
+    
+    pattern[p..].iter().all(|&b| b == b'*')
+}
+
+/// Matches `c` against the bracket expression starting at `pattern[start]`.
+/// Returns whether it matched and the index past the closing `]`, or
+/// `None` if the bracket is never closed.
+fn bracket(pattern: &[u8], start: usize, c: u8) -> Option<(bool, usize)> {
+    let mut i = start + 1;
+    let negate = matches!(pattern.get(i), Some(b'!') | Some(b'^'));
+    if negate {
+        i += 1;
+    }
+    
+    let mut matched = false;
+    let mut first = true;
+    
+    while i < pattern.len() {
+        if pattern[i] == b']' && !first {
+            return Some((matched != negate, i + 1));
+        }
+        first = false;
+        
+        if pattern[i..].starts_with(b"[:") {
+            if let Some(len) = pattern[i + 2..].windows(2).position(|w| w == b":]") {
+                matched |= class_matches(&pattern[i + 2..i + 2 + len], c);
+                i += len + 4;
+                continue;
+            }
+        }
+        
+        let low = if pattern[i] == b'\\' && i + 1 < pattern.len() {
+            i += 1;
+            pattern[i]
+        } else {
+            pattern[i]
+        };
+        
+        if pattern.get(i + 1) == Some(&b'-') && i + 2 < pattern.len() && pattern[i + 2] != b']' {
+            let high = pattern[i + 2];
+            matched |= low <= c && c <= high;
+            i += 3;
+        } else {
+            matched |= low == c;
+            i += 1;
+        }
+    }
+    
+    None
+}
+
+fn class_matches(class: &[u8], c: u8) -> bool {
+    match class {
+        b"alpha" => c.is_ascii_alphabetic(),
+        b"digit" => c.is_ascii_digit(),
+        b"alnum" => c.is_ascii_alphanumeric(),
+        b"upper" => c.is_ascii_uppercase(),
+        b"lower" => c.is_ascii_lowercase(),
+        b"space" => c.is_ascii_whitespace(),
+        b"punct" => c.is_ascii_punctuation(),
+        b"xdigit" => c.is_ascii_hexdigit(),
+        _ => false,
+    }
+}
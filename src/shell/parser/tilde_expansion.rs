@@ -0,0 +1,31 @@
+use crate::shell::passwd::find_user;
+use crate::shell::storage::ENV_STORAGE;
+use crate::io::LineBuffer;
+
+fn is_login_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-')
+}
+
+/// Tilde expansion at the start of a word: `~` and `~/path` use `HOME`,
+/// `~user` the home directory of that user. Appends the directory to
+/// `out` and returns the number of bytes of `word` it replaces, or `None`
+/// if the word is left alone.
+pub fn expand_tilde(word: &[u8], out: &mut LineBuffer) -> Option<usize> {
+    if word.first() != Some(&b'~') {
+        return None;
+    }
+    
+    let end = word.iter().position(|&b| b == b'/').unwrap_or(word.len());
+    let user = &word[1..end];
+    // A quoted or expanded prefix like `~"x"` or `~$USER` is not a user name
+    if !user.iter().all(|&b| is_login_byte(b)) {
+        return None;
+    }
+    
+    let found = if user.is_empty() {
+        ENV_STORAGE.get_all(b"HOME", out)
+    } else {
+        out.push(find_user(user)?.home())
+    };
+    found.then_some(end)
+}
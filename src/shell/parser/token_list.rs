@@ -0,0 +1,130 @@
+use crate::io::LineBuffer;
+
+pub const MAX_TOKENS: usize = 128;
+
+/// List of up to `MAX_TOKENS` expanded words.
+///
+/// Every word is stored NUL-terminated in a shared byte buffer so it can be
+/// handed to `execve` without copying. The buffer grows, so the pointers
+/// from `as_ptr` are only good until the next word is added.
+pub struct TokenList {
+    buf: LineBuffer,
+    starts: [usize; MAX_TOKENS],
+    lens: [usize; MAX_TOKENS],
+    count: usize,
+    current: Option<usize>,
+    overflow: bool,
+}
+
+impl TokenList {
+    pub const fn new() -> Self {
+        Self {
+            buf: LineBuffer::new(),
+            starts: [0; MAX_TOKENS],
+            lens: [0; MAX_TOKENS],
+            count: 0,
+            current: None,
+            overflow: false,
+        }
+    }
+    
+    pub fn len(&self) -> usize {
+        self.count
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+    
+    pub fn overflowed(&self) -> bool {
+        self.overflow
+    }
+    
+    pub fn get(&self, idx: usize) -> &[u8] {
+        let start = self.starts[idx];
+        &self.buf.as_slice()[start..start + self.lens[idx]]
+    }
+    
+    /// The word including its terminating NUL, for path arguments.
+    pub fn get_cstr(&self, idx: usize) -> &[u8] {
+        let start = self.starts[idx];
+        &self.buf.as_slice()[start..start + self.lens[idx] + 1]
+    }
+    
+    /// Pointer to the NUL-terminated word, suitable for an `argv` array.
+    pub fn as_ptr(&self, idx: usize) -> *const u8 {
+        self.buf.as_slice()[self.starts[idx]..].as_ptr()
+    }
+    
+    /// Starts a word even if no bytes follow, so `""` yields an empty argument.
+    pub fn begin(&mut self) {
+        if self.current.is_none() {
+            self.current = Some(self.buf.len());
+        }
+    }
+    
+    pub fn push(&mut self, b: u8) {
+        self.push_slice(&[b]);
+    }
+    
+    pub fn push_slice(&mut self, s: &[u8]) {
+        self.begin();
+        if !self.buf.push(s) {
+            self.overflow = true;
+        }
+    }
+    
+    /// The word under construction, empty if there is none.
+    pub fn current_word(&self) -> &[u8] {
+        match self.current {
+            Some(start) => &self.buf.as_slice()[start..],
+            None => &[],
+        }
+    }
+    
+    /// Drops the word under construction, if any.
+    pub fn discard(&mut self) {
+        if let Some(start) = self.current.take() {
+            self.buf.truncate(start);
+        }
+    }
+    
+    /// Terminates the word under construction, if any.
+    pub fn finish(&mut self) {
+        let start = match self.current.take() {
+            Some(start) => start,
+            None => return,
+        };
+        
+        let len = self.buf.len() - start;
+        if self.count >= MAX_TOKENS || !self.buf.push(&[0]) {
+            self.overflow = true;
+            return;
+        }
+        
+        self.starts[self.count] = start;
+        self.lens[self.count] = len;
+        self.count += 1;
+    }
+    
+    /// Sorts the words from index `first` on in byte order.
+    pub fn sort_from(&mut self, first: usize) {
+        for i in first + 1..self.count {
+            let mut j = i;
+            while j > first && self.get(j) < self.get(j - 1) {
+                self.starts.swap(j, j - 1);
+                self.lens.swap(j, j - 1);
+                j -= 1;
+            }
+        }
+    }
+    
+    /// Fills `out` with a slice per word and returns the number of words.
+    pub fn words<'a>(&'a self, out: &mut [&'a [u8]]) -> usize {
+        let n = self.count.min(out.len());
+        for (i, word) in out.iter_mut().enumerate().take(n) {
+            *word = self.get(i);
+        }
+        n
+    }
+}
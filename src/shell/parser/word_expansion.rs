@@ -0,0 +1,447 @@
+use super::env_expansion::expand_env_vars;
+use super::command_substitution::run_substitution;
+use super::parameter_expansion::expand_parameter;
+use super::arithmetic::evaluate_arithmetic;
+use super::lexer::{Token, backquote_end, substitution_end};
+use super::token_list::TokenList;
+use super::glob::glob;
+use super::brace_expansion::expand_braces;
+use super::tilde_expansion::expand_tilde;
+use crate::shell::storage::Params;
+use crate::shell::executor::report_error;
+use crate::syscalls::write;
+use crate::io::{format_number, LineBuffer, Stdio};
+
+/// Destination of expanded text: the word list of a command, a flat
+/// buffer for the operand of a `${...}` expansion, or the file holding a
+/// here-document.
+trait Output {
+    /// Appends quoted text.
+    fn push_slice(&mut self, s: &[u8]);
+    /// Appends unquoted text or the result of an unquoted expansion.
+    fn push_fields(&mut self, s: &[u8]);
+    /// Starts a word even if no bytes follow.
+    fn begin(&mut self);
+    /// Separates the fields of `"$@"`.
+    fn break_word(&mut self);
+}
+
+/// Words of a command. Alongside the word under construction it keeps a
+/// pattern with the quoted `*`, `?`, `[` and `\` escaped, which replaces the
+/// word with the paths it matches if it has an unquoted wildcard.
+struct WordList<'a> {
+    tokens: &'a mut TokenList,
+    pattern: LineBuffer,
+    glob: bool,
+    /// Set once memory for the pattern runs out
+    full: bool,
+}
+
+impl<'a> WordList<'a> {
+    fn new(tokens: &'a mut TokenList) -> Self {
+        Self { tokens, pattern: LineBuffer::new(), glob: false, full: false }
+    }
+    
+    fn push_pattern(&mut self, b: u8) {
+        if !self.pattern.push(&[b]) {
+            self.full = true;
+        }
+    }
+    
+    fn push(&mut self, b: u8, quoted: bool) {
+        self.tokens.push(b);
+        let wildcard = matches!(b, b'*' | b'?' | b'[');
+        if quoted && (wildcard || b == b'\\') {
+            self.push_pattern(b'\\');
+        }
+        self.push_pattern(b);
+        self.glob |= wildcard && !quoted;
+    }
+    
+    /// Terminates the word under construction, globbing it if needed. A
+    /// pattern that matches nothing is kept as it is.
+    fn finish(&mut self) {
+        if self.glob && !self.full {
+            let mut literal = LineBuffer::new();
+            self.full = !literal.push(self.tokens.current_word());
+            
+            self.tokens.discard();
+            if glob(self.pattern.as_slice(), self.tokens) == 0 {
+                self.tokens.push_slice(literal.as_slice());
+            }
+        }
+        
+        self.tokens.finish();
+        self.pattern.clear();
+        self.glob = false;
+    }
+}
+
+impl Output for WordList<'_> {
+    fn push_slice(&mut self, s: &[u8]) {
+        for &b in s {
+            self.push(b, true);
+        }
+    }
+    
+    /// Splits the text into separate words at blanks.
+    fn push_fields(&mut self, s: &[u8]) {
+        for &b in s {
+            if b == b' ' || b == b'\t' || b == b'\n' {
+                self.finish();
+            } else {
+                self.push(b, false);
+            }
+        }
+    }
+    
+    fn begin(&mut self) {
+        self.tokens.begin();
+    }
+    
+    fn break_word(&mut self) {
+        self.finish();
+        self.tokens.begin();
+    }
+}
+
+/// Collects an expansion into one string, without field splitting. For a
+/// pattern, quoted wildcards are escaped so they only match themselves.
+struct FlatBuffer<'a> {
+    buf: &'a mut LineBuffer,
+    pattern: bool,
+    /// Set once memory runs out
+    full: bool,
+}
+
+impl FlatBuffer<'_> {
+    fn push(&mut self, bytes: &[u8]) {
+        if !self.buf.push(bytes) {
+            self.full = true;
+        }
+    }
+}
+
+impl Output for FlatBuffer<'_> {
+    fn push_slice(&mut self, s: &[u8]) {
+        if !self.pattern {
+            return self.push(s);
+        }
+        for &b in s {
+            if matches!(b, b'*' | b'?' | b'[' | b'\\') {
+                self.push(b"\\");
+            }
+            self.push(&[b]);
+        }
+    }
+    
+    fn push_fields(&mut self, s: &[u8]) {
+        self.push(s);
+    }
+    
+    fn begin(&mut self) {}
+    
+    fn break_word(&mut self) {
+        self.push_slice(b" ");
+    }
+}
+
+/// Writes expanded text straight to a file, so here-documents aren't
+/// limited to the size of a buffer.
+struct FileOutput {
+    fd: i32,
+}
+
+impl Output for FileOutput {
+    fn push_slice(&mut self, s: &[u8]) {
+        write(self.fd, s);
+    }
+    
+    fn push_fields(&mut self, s: &[u8]) {
+        write(self.fd, s);
+    }
+    
+    fn begin(&mut self) {}
+    
+    fn break_word(&mut self) {
+        write(self.fd, b" ");
+    }
+}
+
+fn is_quote_or_escape(b: u8) -> bool {
+    b == b'\\' || b == b'\'' || b == b'"'
+}
+
+/// `` ` ``, `$(`, `$((` or `${`: expansions that need more than `expand_env_vars`.
+fn is_expansion_start(word: &[u8], i: usize) -> bool {
+    word[i] == b'`' || (word[i] == b'$' && matches!(word.get(i + 1), Some(b'(') | Some(b'{')))
+}
+
+/// Appends `bytes` to `out`. Returns `None`, after reporting it, if there
+/// is no room.
+pub fn append(out: &mut LineBuffer, bytes: &[u8], io: &Stdio) -> Option<()> {
+    if !out.push(bytes) {
+        report_error(io, b"out of memory");
+        return None;
+    }
+    Some(())
+}
+
+/// Runs the `$(...)` or `` `...` `` starting at `word[i]`, appending its
+/// output to `out`. Returns the index just past the substitution.
+fn command_substitution(word: &[u8], i: usize, io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<usize> {
+    if word[i] == b'$' {
+        let end = substitution_end(word, i).unwrap_or(word.len());
+        let body = &word[i + 2..(end - 1).max(i + 2)];
+        return run_substitution(body, io, params, out).then_some(end);
+    }
+    
+    let end = backquote_end(word, i).unwrap_or(word.len());
+    let body = &word[i + 1..(end - 1).max(i + 1)];
+    
+    // Inside backquotes a backslash only escapes `$`, `` ` `` and itself
+    let mut command = LineBuffer::new();
+    let mut j = 0;
+    while j < body.len() {
+        if body[j] == b'\\' && j + 1 < body.len() && matches!(body[j + 1], b'$' | b'`' | b'\\') {
+            j += 1;
+        }
+        append(&mut command, &body[j..j + 1], io)?;
+        j += 1;
+    }
+    
+    run_substitution(command.as_slice(), io, params, out).then_some(end)
+}
+
+/// Expands and evaluates the expression of a `$((...))`, appending the
+/// result to `out`.
+fn arithmetic_expansion(body: &[u8], io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<()> {
+    let mut expr = LineBuffer::new();
+    expand_flat(body, io, params, &mut expr)?;
+    
+    match evaluate_arithmetic(expr.as_slice()) {
+        Ok(value) => {
+            let mut digits = [0u8; 20];
+            let len = format_number(value, &mut digits);
+            append(out, &digits[..len], io)
+        }
+        Err(err) => {
+            write(io.stderr, b"reshell: ");
+            write(io.stderr, expr.as_slice());
+            write(io.stderr, b": ");
+            write(io.stderr, err.as_bytes());
+            write(io.stderr, b"\n");
+            None
+        }
+    }
+}
+
+/// Expands the `${...}`, `$((...))`, `$(...)` or `` `...` `` at `word[i]`,
+/// appending the result to `out`. Returns the index just past the
+/// expansion, or `None` if an error was reported.
+fn expansion(word: &[u8], i: usize, quoted: bool, io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<usize> {
+    if word.get(i + 1) == Some(&b'{') {
+        return expand_parameter(word, i, quoted, io, params, out);
+    }
+    
+    // `$((` closed by `))` is arithmetic, anything else a subshell
+    if word[i..].starts_with(b"$((") {
+        let end = substitution_end(word, i).unwrap_or(word.len());
+        if end >= i + 5 && word[end - 2] == b')' {
+            arithmetic_expansion(&word[i + 3..end - 2], io, params, out)?;
+            return Some(end);
+        }
+    }
+    
+    command_substitution(word, i, io, params, out)
+}
+
+/// Expands the `$NAME`s in `text` into `out`, which is cleared first.
+fn expand_vars(text: &[u8], io: &Stdio, params: &Params, out: &mut LineBuffer) -> Option<()> {
+    out.clear();
+    if let Err(err) = expand_env_vars(text, params, out) {
+        report_error(io, err.as_bytes());
+        return None;
+    }
+    Some(())
+}
+
+/// Performs tilde expansion, parameter expansion, command substitution,
+/// field splitting and quote removal on `word`, appending the result to
+/// `out`.
+fn expand_into<O: Output>(word: &[u8], out: &mut O, io: &Stdio, params: &mut Params) -> Option<()> {
+    let mut i = 0;
+    let mut expanded = LineBuffer::new();
+    
+    if let Some(end) = expand_tilde(word, &mut expanded) {
+        out.push_slice(expanded.as_slice());
+        i = end;
+    }
+    
+    while i < word.len() {
+        if is_expansion_start(word, i) {
+            expanded.clear();
+            i = expansion(word, i, false, io, params, &mut expanded)?;
+            out.push_fields(expanded.as_slice());
+            continue;
+        }
+        
+        match word[i] {
+            b'\\' => {
+                let end = (i + 2).min(word.len());
+                // A backslash-newline joins two lines and disappears
+                if word[end - 1] != b'\n' {
+                    out.push_slice(&word[end - 1..end]);
+                }
+                i = end;
+            }
+            b'\'' => {
+                out.begin();
+                let start = i + 1;
+                i = start;
+                while i < word.len() && word[i] != b'\'' {
+                    i += 1;
+                }
+                out.push_slice(&word[start..i]);
+                i += 1;
+            }
+            b'"' => {
+                // `"$@"` with no positional parameters is no word at all
+                if word[i..].starts_with(b"\"$@\"") && params.args.count() == 0 {
+                    i += 4;
+                    continue;
+                }
+                out.begin();
+                i = expand_double_quoted(word, i + 1, false, out, io, params)?;
+            }
+            _ => {
+                let start = i;
+                while i < word.len() && !is_quote_or_escape(word[i]) && !is_expansion_start(word, i) {
+                    i += 1;
+                }
+                expand_vars(&word[start..i], io, params, &mut expanded)?;
+                out.push_fields(expanded.as_slice());
+            }
+        }
+    }
+    
+    Some(())
+}
+
+/// Expands the body of a double-quoted string starting at `i` and returns
+/// the index just past the closing quote. No field splitting happens here,
+/// except that `"$@"` keeps each positional parameter a separate word. In
+/// a here-document (`here_doc`) a `"` is an ordinary character.
+fn expand_double_quoted<O: Output>(word: &[u8], mut i: usize, here_doc: bool, out: &mut O, io: &Stdio, params: &mut Params) -> Option<usize> {
+    let mut expanded = LineBuffer::new();
+    while i < word.len() && (here_doc || word[i] != b'"') {
+        if word[i] == b'\\' && i + 1 < word.len() {
+            match word[i + 1] {
+                b'$' | b'`' | b'\\' => out.push_slice(&word[i + 1..i + 2]),
+                b'"' if !here_doc => out.push_slice(&word[i + 1..i + 2]),
+                b'\n' => {}
+                _ => out.push_slice(&word[i..i + 2]),
+            }
+            i += 2;
+            continue;
+        }
+        
+        if is_expansion_start(word, i) {
+            expanded.clear();
+            i = expansion(word, i, true, io, params, &mut expanded)?;
+            out.push_slice(expanded.as_slice());
+            continue;
+        }
+        
+        if word[i..].starts_with(b"$@") {
+            for (n, arg) in params.args.iter().enumerate() {
+                if n > 0 {
+                    out.break_word();
+                }
+                out.push_slice(arg);
+            }
+            i += 2;
+            continue;
+        }
+        
+        let start = i;
+        while i < word.len()
+            && (here_doc || word[i] != b'"')
+            && word[i] != b'\\'
+            && !is_expansion_start(word, i)
+            && !word[i..].starts_with(b"$@")
+        {
+            i += 1;
+            // A here-document goes line by line so it isn't all held in memory
+            if here_doc && word[i - 1] == b'\n' {
+                break;
+            }
+        }
+        expand_vars(&word[start..i], io, params, &mut expanded)?;
+        out.push_slice(expanded.as_slice());
+    }
+    
+    Some(i + 1)
+}
+
+fn expand_flat_into(word: &[u8], io: &Stdio, params: &mut Params, out: &mut LineBuffer, pattern: bool) -> Option<()> {
+    let mut flat = FlatBuffer { buf: out, pattern, full: false };
+    expand_into(word, &mut flat, io, params)?;
+    if flat.full {
+        report_error(io, b"out of memory");
+        return None;
+    }
+    Some(())
+}
+
+/// Expands `word` into a single string without field splitting, as done
+/// for the operands of `${...}`, and appends it to `out`. Returns `None`
+/// if an error was reported.
+pub fn expand_flat(word: &[u8], io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<()> {
+    expand_flat_into(word, io, params, out, false)
+}
+
+/// Expands a `case` pattern like `expand_flat`, escaping the quoted `*`,
+/// `?`, `[` and `\` so that only the unquoted ones act as wildcards.
+pub fn expand_pattern(word: &[u8], io: &Stdio, params: &mut Params, out: &mut LineBuffer) -> Option<()> {
+    expand_flat_into(word, io, params, out, true)
+}
+
+/// Expands the body of a here-document, which works like a double-quoted
+/// string without the quotes, and writes the result to `fd`. Returns
+/// `false` if an error was reported.
+pub fn expand_here_doc(body: &[u8], io: &Stdio, params: &mut Params, fd: i32) -> bool {
+    expand_double_quoted(body, 0, true, &mut FileOutput { fd }, io, params).is_some()
+}
+
+/// Expands the words of a simple command into `tokens`. Errors are
+/// reported to `io.stderr` and make this return `false`.
+pub fn expand_words(words: &[Token], tokens: &mut TokenList, io: &Stdio, params: &mut Params) -> bool {
+    let mut list = WordList::new(tokens);
+    let mut failed = false;
+    for token in words {
+        if let Token::Word(word) = token {
+            expand_braces(word, |word| {
+                if expand_into(word, &mut list, io, params).is_none() {
+                    failed = true;
+                    return false;
+                }
+                list.finish();
+                !list.full && !list.tokens.overflowed()
+            });
+            if failed {
+                return false;
+            }
+        }
+    }
+    
+    if list.full {
+        report_error(io, b"out of memory");
+        return false;
+    }
+    if tokens.overflowed() {
+        report_error(io, b"command line too long");
+        return false;
+    }
+    true
+}
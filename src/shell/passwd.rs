@@ -0,0 +1,87 @@
+use crate::syscalls::{open, read, close, EINTR, O_RDONLY, O_CLOEXEC};
+
+const PASSWD_LINE_SIZE: usize = 512;
+
+/// A line of `/etc/passwd`, which looks like
+/// `name:password:uid:gid:gecos:home:shell`.
+pub struct PasswdEntry {
+    line: [u8; PASSWD_LINE_SIZE],
+    len: usize,
+}
+
+impl PasswdEntry {
+    fn field(&self, n: usize) -> &[u8] {
+        self.line[..self.len].split(|&b| b == b':').nth(n).unwrap_or(&[])
+    }
+    
+    pub fn name(&self) -> &[u8] {
+        self.field(0)
+    }
+    
+    pub fn home(&self) -> &[u8] {
+        self.field(5)
+    }
+    
+    pub fn shell(&self) -> &[u8] {
+        self.field(6)
+    }
+}
+
+/// The entry of user `name`.
+pub fn find_user(name: &[u8]) -> Option<PasswdEntry> {
+    find(|entry| entry.name() == name)
+}
+
+/// The entry of the user whose id is `uid`.
+pub fn find_uid(uid: u32) -> Option<PasswdEntry> {
+    let mut digits = [0u8; 20];
+    let len = crate::io::format_number(uid as i64, &mut digits);
+    find(|entry| entry.field(2) == &digits[..len])
+}
+
+/// The first entry `matches` accepts.
+fn find<F>(matches: F) -> Option<PasswdEntry> where F: Fn(&PasswdEntry) -> bool {
+    let fd = open(b"/etc/passwd\0", O_RDONLY | O_CLOEXEC, 0);
+    if fd < 0 {
+        return None;
+    }
+    
+    let mut entry = PasswdEntry { line: [0u8; PASSWD_LINE_SIZE], len: 0 };
+    // Bytes of the line so far, counting those too many to keep
+    let mut line_len = 0;
+    let mut buf = [0u8; 1024];
+    let mut found = false;
+    
+    'read: loop {
+        let n = read(fd as i32, &mut buf);
+        if n == -EINTR {
+            continue;
+        }
+        // A last line without a newline is handled with an extra pass
+        let chunk: &[u8] = if n > 0 { &buf[..n as usize] } else { b"\n" };
+        
+        for &b in chunk {
+            if b != b'\n' {
+                if line_len < PASSWD_LINE_SIZE {
+                    entry.line[line_len] = b;
+                }
+                line_len += 1;
+                continue;
+            }
+            
+            entry.len = line_len.min(PASSWD_LINE_SIZE);
+            if entry.len > 0 && matches(&entry) {
+                found = true;
+                break 'read;
+            }
+            line_len = 0;
+        }
+        
+        if n <= 0 {
+            break;
+        }
+    }
+    
+    close(fd as i32);
+    if found { Some(entry) } else { None }
+}
@@ -0,0 +1,115 @@
+use crate::syscalls::{pipe2, close, restore_default_signals, O_CLOEXEC};
+use crate::shell::parser::{Ast, NodeId, NodeKind, Token};
+use crate::shell::executor::{run_command, exec_command, redirect_stdio, report_error, fork_shell, ExecState};
+use crate::shell::jobs::{join_process_group, MAX_JOB_PROCS};
+use crate::io::Stdio;
+
+const MAX_STAGES: usize = MAX_JOB_PROCS;
+
+/// Runs pipeline `id`, `a | b | c`. A foreground pipeline with a single
+/// stage runs in the shell process so builtins like `cd` keep their effect;
+/// otherwise every stage is forked into one process group. Returns the exit
+/// status of the last stage, inverted by `!`, or 0 for a background job.
+pub fn run_pipeline(ast: &Ast, id: NodeId, io: &Stdio, state: &mut ExecState, background: bool) -> i32 {
+    let (first, negate, text) = match ast.node(id).kind {
+        NodeKind::Pipeline { first, negate, text, .. } => (first, negate, text),
+        _ => return 0,
+    };
+    
+    let mut commands: [NodeId; MAX_STAGES] = [0; MAX_STAGES];
+    let mut stages = 0;
+    let mut next = Some(first);
+    
+    while let Some(command) = next {
+        if stages >= MAX_STAGES {
+            report_error(io, b"too many pipeline stages");
+            return 1;
+        }
+        commands[stages] = command;
+        stages += 1;
+        next = ast.node(command).next;
+    }
+    
+    let status = if stages == 1 && !background {
+        run_command(ast, first, io, state)
+    } else {
+        spawn_stages(ast, &commands[..stages], text, io, state, background)
+    };
+    
+    if background {
+        0
+    } else if negate {
+        (status == 0) as i32
+    } else {
+        status
+    }
+}
+
+/// Forks one process per stage, connected by pipes, and waits for them
+/// unless they form a background job.
+fn spawn_stages(ast: &Ast, commands: &[NodeId], text: &[Token], io: &Stdio, state: &mut ExecState, background: bool) -> i32 {
+    let stages = commands.len();
+    let take_terminal = state.job_control && !background;
+    
+    let mut pids = [0i32; MAX_STAGES];
+    let mut spawned = 0;
+    let mut input = io.stdin;
+    
+    for (i, &command) in commands.iter().enumerate() {
+        let last = i + 1 == stages;
+        
+        let mut fds = [-1i32; 2];
+        if !last && pipe2(&mut fds, O_CLOEXEC) < 0 {
+            report_error(io, b"pipe failed");
+            break;
+        }
+        
+        let output = if last { io.stdout } else { fds[1] };
+        let pid = fork_shell();
+        
+        if pid == 0 {
+            join_process_group(0, pids[0], take_terminal);
+            restore_default_signals();
+            redirect_stdio(&Stdio { stdin: input, stdout: output, stderr: io.stderr });
+            exec_command(ast, command, state);
+        }
+        
+        if input != io.stdin {
+            close(input);
+        }
+        if !last {
+            close(fds[1]);
+        }
+        
+        if pid < 0 {
+            report_error(io, b"fork failed");
+            if !last {
+                close(fds[0]);
+            }
+            input = io.stdin;
+            break;
+        }
+        
+        join_process_group(pid as i32, pids[0], false);
+        pids[spawned] = pid as i32;
+        spawned += 1;
+        input = if last { io.stdin } else { fds[0] };
+    }
+    
+    if input != io.stdin {
+        close(input);
+    }
+    
+    if spawned == 0 {
+        return 1;
+    }
+    
+    if background {
+        state.params.background_pid = pids[spawned - 1];
+        state.jobs.start_background(pids[0], &pids[..spawned], text, io);
+        return 0;
+    }
+    
+    let status = state.jobs.run_foreground(pids[0], &pids[..spawned], text, io, state.job_control);
+    if spawned < stages { 1 } else { status }
+}
@@ -0,0 +1,181 @@
+use crate::syscalls::{getcwd, geteuid, uname, clock_gettime, TimeSpec, Utsname, CLOCK_REALTIME};
+use crate::shell::storage::ENV_STORAGE;
+use crate::io::format_number;
+
+/// Room for a prompt once its escapes are replaced.
+pub const PROMPT_SIZE: usize = 1024;
+/// Marks the start and end of the parts of a prompt that `\[` and `\]`
+/// say take no room on the screen, so the line editor can leave them out
+/// when it works out where the cursor is.
+pub const HIDDEN_START: u8 = 0x01;
+pub const HIDDEN_END: u8 = 0x02;
+
+/// `PS1` when it is unset, on the console and in web sessions.
+pub const CONSOLE_PS1: &[u8] = b"reshell> ";
+pub const SESSION_PS1: &[u8] = b"$ ";
+const DEFAULT_PS2: &[u8] = b"> ";
+const PATH_SIZE: usize = 4096;
+
+/// The prompt being built. Whatever doesn't fit is left out.
+struct Prompt<'a> {
+    buf: &'a mut [u8],
+    len: usize,
+}
+
+impl Prompt<'_> {
+    fn push(&mut self, s: &[u8]) {
+        let len = s.len().min(self.buf.len() - self.len);
+        self.buf[self.len..self.len + len].copy_from_slice(&s[..len]);
+        self.len += len;
+    }
+    
+    fn push_number(&mut self, n: i64, width: usize) {
+        let mut digits = [0u8; 20];
+        let len = format_number(n, &mut digits);
+        for _ in len..width {
+            self.push(b"0");
+        }
+        self.push(&digits[..len]);
+    }
+    
+    /// `\w` and `\W`: the current directory, or its last part, with the
+    /// home directory shown as `~`.
+    fn push_directory(&mut self, last_part: bool) {
+        let mut cwd = [0u8; PATH_SIZE];
+        let len = getcwd(&mut cwd);
+        if len <= 0 {
+            return;
+        }
+        let cwd = &cwd[..len as usize];
+        
+        let mut home = [0u8; PATH_SIZE];
+        let home_len = ENV_STORAGE.get(b"HOME", &mut home).unwrap_or(0);
+        let home = &home[..home_len];
+        let under_home = home_len > 1 && cwd.starts_with(home) && matches!(cwd.get(home_len), None | Some(b'/'));
+        
+        if last_part {
+            if under_home && cwd.len() == home_len {
+                self.push(b"~");
+            } else {
+                let start = cwd.iter().rposition(|&b| b == b'/').map_or(0, |slash| slash + 1);
+                self.push(if start == cwd.len() { cwd } else { &cwd[start..] });
+            }
+        } else if under_home {
+            self.push(b"~");
+            self.push(&cwd[home_len..]);
+        } else {
+            self.push(cwd);
+        }
+    }
+    
+    /// `\h` and `\H`: the host name, up to its first dot or in full.
+    fn push_host(&mut self, full: bool) {
+        let mut names = Utsname {
+            sysname: [0u8; 65],
+            nodename: [0u8; 65],
+            release: [0u8; 65],
+            version: [0u8; 65],
+            machine: [0u8; 65],
+            domainname: [0u8; 65],
+        };
+        if uname(&mut names) < 0 {
+            return;
+        }
+        let host = &names.nodename;
+        let end = host.iter().position(|&b| b == 0 || (!full && b == b'.')).unwrap_or(host.len());
+        self.push(&host[..end]);
+    }
+    
+    /// `\t`: the time as `HH:MM:SS`, in UTC since there is no time zone
+    /// database to read.
+    fn push_time(&mut self) {
+        let mut now = TimeSpec { tv_sec: 0, tv_nsec: 0 };
+        if clock_gettime(CLOCK_REALTIME, &mut now) < 0 {
+            return;
+        }
+        let seconds = now.tv_sec.rem_euclid(86400);
+        self.push_number(seconds / 3600, 2);
+        self.push(b":");
+        self.push_number(seconds / 60 % 60, 2);
+        self.push(b":");
+        self.push_number(seconds % 60, 2);
+    }
+}
+
+/// Writes the prompt to show to `out` and returns its length: `PS2` while
+/// a command waits for more lines, `PS1` otherwise, or `default_ps1` if
+/// `PS1` is unset. These escapes are replaced:
+///
+/// - `\u` the user name, `\h` the host name up to the first dot and `\H`
+///   all of it
+/// - `\w` the current directory and `\W` its last part, with the home
+///   directory shown as `~`
+/// - `\$` `#` for root and `$` for anyone else
+/// - `\t` the time, `\?` the exit status of the last command, which is
+///   `status`, `\s` the name of the shell
+/// - `\e` an escape, `\a` a bell, `\n` a newline, `\\` a backslash and
+///   `\NNN` the character with octal code NNN
+/// - `\[` and `\]` around text such as color sequences that takes no
+///   room, which end up as `HIDDEN_START` and `HIDDEN_END`
+pub fn render_prompt(continuing: bool, default_ps1: &[u8], status: i32, out: &mut [u8]) -> usize {
+    let (name, default) = if continuing { (b"PS2", DEFAULT_PS2) } else { (b"PS1", default_ps1) };
+    let mut format = [0u8; PROMPT_SIZE];
+    let format = match ENV_STORAGE.get(name, &mut format) {
+        Some(len) => &format[..len],
+        None => default,
+    };
+    
+    let mut prompt = Prompt { buf: out, len: 0 };
+    let mut i = 0;
+    while i < format.len() {
+        if format[i] != b'\\' || i + 1 == format.len() {
+            prompt.push(&format[i..i + 1]);
+            i += 1;
+            continue;
+        }
+        
+        let escape = format[i + 1];
+        i += 2;
+        match escape {
+            b'u' => {
+                let mut user = [0u8; 256];
+                let len = ENV_STORAGE.get(b"USER", &mut user).unwrap_or(0);
+                prompt.push(&user[..len]);
+            }
+            b'h' | b'H' => prompt.push_host(escape == b'H'),
+            b'w' | b'W' => prompt.push_directory(escape == b'W'),
+            b'$' => prompt.push(if geteuid() == 0 { b"#" } else { b"$" }),
+            b't' => prompt.push_time(),
+            b'?' => prompt.push_number(status as i64, 0),
+            b's' => prompt.push(b"reshell"),
+            b'e' => prompt.push(b"\x1b"),
+            b'a' => prompt.push(b"\x07"),
+            b'n' => prompt.push(b"\n"),
+            b'\\' => prompt.push(b"\\"),
+            b'[' => prompt.push(&[HIDDEN_START]),
+            b']' => prompt.push(&[HIDDEN_END]),
+            b'0'..=b'7' => {
+                let digits = format[i - 1..].iter().take(3).take_while(|b| (b'0'..=b'7').contains(b)).count();
+                let code = format[i - 1..i - 1 + digits].iter().fold(0u32, |code, &b| code * 8 + (b - b'0') as u32);
+                prompt.push(&[code as u8]);
+                i += digits - 1;
+            }
+            _ => prompt.push(&format[i - 2..i]),
+        }
+    }
+    prompt.len
+}
+
+/// `render_prompt` for a terminal that shows the prompt as it is, without
+/// the `HIDDEN_START` and `HIDDEN_END` marks.
+pub fn render_plain_prompt(continuing: bool, default_ps1: &[u8], status: i32, out: &mut [u8]) -> usize {
+    let len = render_prompt(continuing, default_ps1, status, out);
+    let mut kept = 0;
+    for i in 0..len {
+        if out[i] != HIDDEN_START && out[i] != HIDDEN_END {
+            out[kept] = out[i];
+            kept += 1;
+        }
+    }
+    kept
+}
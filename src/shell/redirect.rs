@@ -0,0 +1,299 @@
+use crate::syscalls::{open, close, write, lseek, memfd_create, strerror, O_RDONLY, O_WRONLY, O_CREAT, O_TRUNC, O_APPEND, O_CLOEXEC, MFD_CLOEXEC, SEEK_SET, STDIN, STDOUT, STDERR};
+use crate::shell::parser::{expand_words, expand_flat, expand_here_doc, is_name, Token, RedirectKind, TokenList, MAX_TOKENS};
+use crate::shell::executor::report_error;
+use crate::shell::storage::Params;
+use crate::io::{LineBuffer, Stdio};
+
+const MAX_REDIRECTS: usize = 16;
+const MAX_ASSIGNMENTS: usize = 16;
+const FILE_MODE: u32 = 0o666;
+
+/// Why the redirections of a command could not be set up. Either way the
+/// error has been reported.
+pub enum RedirectError {
+    /// A target or here-document could not be expanded, which ends a
+    /// shell that isn't interactive
+    Expansion,
+    /// A file could not be opened, or the like
+    Failed,
+}
+
+#[derive(Clone, Copy)]
+struct Redirect<'a> {
+    fd: i32,
+    kind: RedirectKind,
+    target: Token<'a>,
+}
+
+/// Whether `word` is a `NAME=value` assignment.
+fn is_assignment(word: &[u8]) -> bool {
+    word.iter().position(|&b| b == b'=').is_some_and(|equals| is_name(&word[..equals]))
+}
+
+/// A simple command with its redirections and leading `NAME=value`
+/// assignments split off from its words.
+pub struct SimpleCommand<'a> {
+    words: [Token<'a>; MAX_TOKENS],
+    word_count: usize,
+    assignments: [&'a [u8]; MAX_ASSIGNMENTS],
+    assignment_count: usize,
+    redirects: [Redirect<'a>; MAX_REDIRECTS],
+    redirect_count: usize,
+}
+
+impl<'a> SimpleCommand<'a> {
+    pub fn parse(tokens: &[Token<'a>]) -> Result<Self, &'static str> {
+        let mut cmd = Self {
+            words: [Token::Word(&[]); MAX_TOKENS],
+            word_count: 0,
+            assignments: [&[]; MAX_ASSIGNMENTS],
+            assignment_count: 0,
+            redirects: [Redirect { fd: 0, kind: RedirectKind::Input, target: Token::Word(&[]) }; MAX_REDIRECTS],
+            redirect_count: 0,
+        };
+        
+        let mut i = 0;
+        while i < tokens.len() {
+            match tokens[i] {
+                Token::Redirect(fd, kind) => {
+                    let target = match tokens.get(i + 1) {
+                        Some(&Token::Word(word)) => Token::Word(word),
+                        _ => return Err("syntax error near unexpected token `newline'"),
+                    };
+                    if cmd.redirect_count >= MAX_REDIRECTS {
+                        return Err("too many redirections");
+                    }
+                    cmd.redirects[cmd.redirect_count] = Redirect { fd, kind, target };
+                    cmd.redirect_count += 1;
+                    i += 2;
+                }
+                Token::Word(word) if cmd.word_count == 0 && is_assignment(word) => {
+                    if cmd.assignment_count >= MAX_ASSIGNMENTS {
+                        return Err("too many assignments");
+                    }
+                    cmd.assignments[cmd.assignment_count] = word;
+                    cmd.assignment_count += 1;
+                    i += 1;
+                }
+                token => {
+                    if cmd.word_count >= MAX_TOKENS {
+                        return Err("too many words");
+                    }
+                    cmd.words[cmd.word_count] = token;
+                    cmd.word_count += 1;
+                    i += 1;
+                }
+            }
+        }
+        
+        Ok(cmd)
+    }
+    
+    pub fn words(&self) -> &[Token<'a>] {
+        &self.words[..self.word_count]
+    }
+    
+    /// The `NAME=value` words before the command name, as written.
+    pub fn assignments(&self) -> &[&'a [u8]] {
+        &self.assignments[..self.assignment_count]
+    }
+    
+    /// Opens the redirection targets and returns the streams the command
+    /// should use, expanding the targets with `params`. Opened files are
+    /// recorded in `files` so the caller can close them once the command
+    /// is done.
+    pub fn apply_redirects(&self, io: &Stdio, params: &mut Params, files: &mut OpenFiles) -> Result<Stdio, RedirectError> {
+        let mut result = *io;
+        
+        for redirect in &self.redirects[..self.redirect_count] {
+            if redirect.fd > STDERR {
+                return bad_fd(io, redirect.fd);
+            }
+            
+            if let RedirectKind::HereDoc { .. } | RedirectKind::HereString = redirect.kind {
+                let source = here_document(io, redirect, params, files)?;
+                assign(&mut result, redirect.fd, source);
+                continue;
+            }
+            
+            let mut target = TokenList::new();
+            if !expand_words(&[redirect.target], &mut target, io, params) {
+                return Err(RedirectError::Expansion);
+            }
+            if target.len() != 1 {
+                report_error(io, b"ambiguous redirect");
+                return Err(RedirectError::Failed);
+            }
+            
+            let source = match redirect.kind {
+                RedirectKind::DupInput | RedirectKind::DupOutput => {
+                    match parse_fd(target.get(0)) {
+                        Some(fd) => match stream(&result, fd) {
+                            Some(source) => source,
+                            None => return bad_fd(io, fd),
+                        },
+                        None if redirect.kind == RedirectKind::DupOutput => {
+                            // `>&file` is another spelling of `&>file`
+                            let fd = open_target(io, target.get_cstr(0), RedirectKind::OutputAll, files).ok_or(RedirectError::Failed)?;
+                            result.stdout = fd;
+                            result.stderr = fd;
+                            continue;
+                        }
+                        None => {
+                            report_error(io, b"file descriptor expected");
+                            return Err(RedirectError::Failed);
+                        }
+                    }
+                }
+                kind => open_target(io, target.get_cstr(0), kind, files).ok_or(RedirectError::Failed)?,
+            };
+            
+            match redirect.kind {
+                RedirectKind::OutputAll | RedirectKind::AppendAll => {
+                    result.stdout = source;
+                    result.stderr = source;
+                }
+                _ => assign(&mut result, redirect.fd, source),
+            }
+        }
+        
+        Ok(result)
+    }
+}
+
+/// File descriptors opened for redirections of one command.
+pub struct OpenFiles {
+    fds: [i32; MAX_REDIRECTS],
+    count: usize,
+}
+
+impl OpenFiles {
+    pub const fn new() -> Self {
+        Self {
+            fds: [-1; MAX_REDIRECTS],
+            count: 0,
+        }
+    }
+    
+    pub fn close_all(&mut self) {
+        for i in 0..self.count {
+            close(self.fds[i]);
+        }
+        self.count = 0;
+    }
+}
+
+fn open_target(io: &Stdio, path: &[u8], kind: RedirectKind, files: &mut OpenFiles) -> Option<i32> {
+    let flags = match kind {
+        RedirectKind::Input => O_RDONLY,
+        RedirectKind::Append | RedirectKind::AppendAll => O_WRONLY | O_CREAT | O_APPEND,
+        _ => O_WRONLY | O_CREAT | O_TRUNC,
+    };
+    
+    let fd = open(path, flags | O_CLOEXEC, FILE_MODE);
+    if fd < 0 {
+        write(io.stderr, b"reshell: ");
+        write(io.stderr, &path[..path.len() - 1]);
+        write(io.stderr, b": ");
+        write(io.stderr, strerror(fd));
+        write(io.stderr, b"\n");
+        return None;
+    }
+    
+    files.fds[files.count] = fd as i32;
+    files.count += 1;
+    Some(fd as i32)
+}
+
+/// Writes the text of a here-document or here-string to an in-memory file
+/// and returns that file, rewound, to be read as input.
+fn here_document(io: &Stdio, redirect: &Redirect, params: &mut Params, files: &mut OpenFiles) -> Result<i32, RedirectError> {
+    let text = match redirect.target {
+        Token::Word(text) => text,
+        _ => return Err(RedirectError::Failed),
+    };
+    
+    let fd = memfd_create(b"reshell-heredoc\0", MFD_CLOEXEC);
+    if fd < 0 {
+        report_error(io, b"cannot create here-document");
+        return Err(RedirectError::Failed);
+    }
+    let fd = fd as i32;
+    files.fds[files.count] = fd;
+    files.count += 1;
+    
+    let written = match redirect.kind {
+        RedirectKind::HereDoc { strip_tabs, literal } => write_here_doc(io, text, strip_tabs, literal, params, fd),
+        _ => {
+            let mut word = LineBuffer::new();
+            expand_flat(text, io, params, &mut word).ok_or(RedirectError::Expansion)?;
+            write(fd, word.as_slice());
+            write(fd, b"\n");
+            true
+        }
+    };
+    if !written {
+        return Err(RedirectError::Expansion);
+    }
+    
+    lseek(fd, 0, SEEK_SET);
+    Ok(fd)
+}
+
+/// Writes the lines of a here-document, whose last line is its delimiter,
+/// to `fd`. Returns `false` if an expansion failed.
+fn write_here_doc(io: &Stdio, text: &[u8], strip_tabs: bool, literal: bool, params: &mut Params, fd: i32) -> bool {
+    let end = text.iter().rposition(|&b| b == b'\n').map_or(0, |n| n + 1);
+    let body = &text[..end];
+    
+    // In one piece, a command substitution may span several lines
+    if !strip_tabs {
+        return write_body(io, body, literal, params, fd);
+    }
+    
+    body.split_inclusive(|&b| b == b'\n').all(|line| {
+        let start = line.iter().position(|&b| b != b'\t').unwrap_or(line.len());
+        write_body(io, &line[start..], literal, params, fd)
+    })
+}
+
+fn write_body(io: &Stdio, text: &[u8], literal: bool, params: &mut Params, fd: i32) -> bool {
+    if literal {
+        write(fd, text);
+        return true;
+    }
+    expand_here_doc(text, io, params, fd)
+}
+
+fn assign(io: &mut Stdio, fd: i32, source: i32) {
+    match fd {
+        STDIN => io.stdin = source,
+        STDOUT => io.stdout = source,
+        _ => io.stderr = source,
+    }
+}
+
+fn parse_fd(word: &[u8]) -> Option<i32> {
+    if word.is_empty() || word.len() > 4 || !word.iter().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    Some(word.iter().fold(0, |acc, &b| acc * 10 + (b - b'0') as i32))
+}
+
+fn stream(io: &Stdio, fd: i32) -> Option<i32> {
+    match fd {
+        STDIN => Some(io.stdin),
+        STDOUT => Some(io.stdout),
+        STDERR => Some(io.stderr),
+        _ => None,
+    }
+}
+
+fn bad_fd(io: &Stdio, fd: i32) -> Result<Stdio, RedirectError> {
+    use crate::io::write_number;
+    
+    write(io.stderr, b"reshell: ");
+    write_number(io.stderr, fd as i64);
+    write(io.stderr, b": bad file descriptor\n");
+    Err(RedirectError::Failed)
+}
@@ -0,0 +1,90 @@
+use crate::syscalls::{open, close, read, write, strerror, EINTR, ENOENT, ENAMETOOLONG, O_RDONLY, O_CLOEXEC};
+use crate::shell::executor::{report_error, run_line, ExecState, Flow};
+use crate::shell::parser::needs_more_input;
+use crate::io::{LineBuffer, Stdio};
+
+const PATH_SIZE: usize = 256;
+
+/// Opens the script at `path` for reading. The error is the negative
+/// errno it can't be opened with, for `strerror`.
+pub fn open_script(path: &[u8]) -> Result<i32, isize> {
+    let mut path_buf = [0u8; PATH_SIZE];
+    if path.len() >= PATH_SIZE {
+        return Err(-ENAMETOOLONG);
+    }
+    path_buf[..path.len()].copy_from_slice(path);
+    
+    let fd = open(&path_buf[..path.len() + 1], O_RDONLY | O_CLOEXEC, 0);
+    if fd < 0 {
+        return Err(fd);
+    }
+    Ok(fd as i32)
+}
+
+/// Runs the startup file at `path` the way `.` would. There need not be
+/// one, so a missing file is skipped quietly. An `exit` in the file is
+/// left in `state.flow` for the caller.
+pub fn run_startup_file(path: &[u8], io: &Stdio, state: &mut ExecState) {
+    let fd = match open_script(path) {
+        Ok(fd) => fd,
+        Err(err) if err == -ENOENT => return,
+        Err(err) => {
+            write(io.stderr, b"reshell: ");
+            write(io.stderr, path);
+            write(io.stderr, b": ");
+            write(io.stderr, strerror(err));
+            write(io.stderr, b"\n");
+            return;
+        }
+    };
+    
+    state.source_depth += 1;
+    run_script(fd, io, state);
+    state.source_depth -= 1;
+    close(fd);
+    
+    if let Flow::Return(_) = state.flow {
+        state.flow = Flow::Normal;
+    }
+}
+
+/// Runs the commands read from `fd` one at a time, as if they were typed at
+/// the prompt, until the end of the file or until `exit`, `return` or
+/// Ctrl-C. Returns the status of the last command.
+pub fn run_script(fd: i32, io: &Stdio, state: &mut ExecState) -> i32 {
+    let mut command = LineBuffer::new();
+    let mut buf = [0u8; 512];
+    let mut status = state.params.status;
+    
+    loop {
+        let n = read(fd, &mut buf);
+        if n == -EINTR {
+            continue;
+        }
+        if n <= 0 {
+            break;
+        }
+        
+        for &b in &buf[..n as usize] {
+            if !command.push(&[b]) {
+                report_error(io, b"out of memory");
+                return 2;
+            }
+            
+            if b == b'\n' && !needs_more_input(command.as_slice()) {
+                status = run_line(command.as_slice(), io, state);
+                command.clear();
+                if state.flow != Flow::Normal {
+                    return status;
+                }
+            }
+        }
+    }
+    
+    // A last line without a newline, or a command the file left unfinished,
+    // which is reported as a syntax error
+    if !command.is_empty() {
+        status = run_line(command.as_slice(), io, state);
+    }
+    status
+}
@@ -1,7 +1,11 @@
-use core::sync::atomic::{AtomicUsize, AtomicBool, Ordering};
+use core::sync::atomic::{AtomicU8, AtomicUsize, AtomicBool, Ordering};
+use crate::syscalls::fork;
+use crate::shell::storage::{Params, SavedParams};
+use crate::shell::jobs::JobTable;
 
 const MAX_INPUT: usize = 512;
 const MAX_OUTPUT: usize = 4096;
+pub const MAX_PENDING: usize = 2048;
 
 pub struct ShellSession {
     // Input buffer (commands from client)
@@ -13,8 +17,16 @@ pub struct ShellSession {
     output_write_pos: AtomicUsize,
     output_read_pos: AtomicUsize,
     
+    // Lines of a command that needs more input, like an `if` without its `fi`
+    pending_buffer: [AtomicU8; MAX_PENDING],
+    pending_len: AtomicUsize,
+    
     // Session state
     active: AtomicBool,
+    // `$?` and the positional parameters, kept from one command to the next
+    params: SavedParams,
+    // Jobs started from the session
+    jobs: JobTable,
 }
 
 impl ShellSession {
@@ -25,7 +37,11 @@ impl ShellSession {
             output_buffer: [const { AtomicUsize::new(0) }; MAX_OUTPUT],
             output_write_pos: AtomicUsize::new(0),
             output_read_pos: AtomicUsize::new(0),
+            pending_buffer: [const { AtomicU8::new(0) }; MAX_PENDING],
+            pending_len: AtomicUsize::new(0),
             active: AtomicBool::new(false),
+            params: SavedParams::new(),
+            jobs: JobTable::new(),
         }
     }
     
@@ -33,10 +49,14 @@ impl ShellSession {
         self.input_len.store(0, Ordering::Release);
         self.output_write_pos.store(0, Ordering::Release);
         self.output_read_pos.store(0, Ordering::Release);
+        self.pending_len.store(0, Ordering::Release);
+        self.params.store(&Params::new());
         self.active.store(true, Ordering::Release);
     }
     
+    /// Closes the session. Its jobs keep running, disowned.
     pub fn deactivate(&self) {
+        self.jobs.disown_all();
         self.active.store(false, Ordering::Release);
     }
     
@@ -44,6 +64,23 @@ impl ShellSession {
         self.active.load(Ordering::Acquire)
     }
     
+    pub fn params(&self) -> Params {
+        self.params.load()
+    }
+    
+    pub fn set_params(&self, params: &Params) {
+        self.params.store(params);
+    }
+    
+    /// `$?` of the session, for its prompt.
+    pub fn last_status(&self) -> i32 {
+        self.params.status()
+    }
+    
+    pub fn jobs(&self) -> &JobTable {
+        &self.jobs
+    }
+    
     // Input methods (stdin simulation)
     pub fn append_input(&self, ch: u8) {
         let len = self.input_len.load(Ordering::Acquire);
@@ -76,6 +113,38 @@ impl ShellSession {
         self.input_len.load(Ordering::Acquire)
     }
     
+    // Pending command methods
+    /// Adds `data` to the pending command. Returns `false`, adding nothing,
+    /// if it doesn't fit.
+    pub fn append_pending(&self, data: &[u8]) -> bool {
+        let len = self.pending_len.load(Ordering::Acquire);
+        if len + data.len() > MAX_PENDING {
+            return false;
+        }
+        for (i, &byte) in data.iter().enumerate() {
+            self.pending_buffer[len + i].store(byte, Ordering::Release);
+        }
+        self.pending_len.store(len + data.len(), Ordering::Release);
+        true
+    }
+    
+    pub fn get_pending(&self, out: &mut [u8]) -> usize {
+        let len = self.pending_len.load(Ordering::Acquire).min(out.len());
+        for (b, stored) in out.iter_mut().zip(&self.pending_buffer[..len]) {
+            *b = stored.load(Ordering::Acquire);
+        }
+        len
+    }
+    
+    pub fn clear_pending(&self) {
+        self.pending_len.store(0, Ordering::Release);
+    }
+    
+    /// Whether the next line continues an unfinished command.
+    pub fn is_continuing(&self) -> bool {
+        self.pending_len.load(Ordering::Acquire) > 0
+    }
+    
     // Output methods (stdout simulation)
     pub fn write_output(&self, data: &[u8]) {
         let mut write_pos = self.output_write_pos.load(Ordering::Acquire);
@@ -102,8 +171,8 @@ impl ShellSession {
         let available = write_pos - read_pos;
         let copy_len = available.min(out.len());
         
-        for i in 0..copy_len {
-            out[i] = self.output_buffer[read_pos + i].load(Ordering::Acquire) as u8;
+        for (b, stored) in out.iter_mut().zip(&self.output_buffer[read_pos..read_pos + copy_len]) {
+            *b = stored.load(Ordering::Acquire) as u8;
         }
         
         self.output_read_pos.store(read_pos + copy_len, Ordering::Release);
@@ -127,6 +196,23 @@ pub fn get_session(idx: usize) -> Option<&'static ShellSession> {
     }
 }
 
+/// Calls `f` with every session, active or not.
+pub fn for_each_session<F>(f: F) where F: FnMut(&'static ShellSession) {
+    SESSIONS.iter().for_each(f);
+}
+
+/// Forks with the parameter lock of every session held, taken in order.
+pub fn fork_holding_sessions() -> isize {
+    fork_holding(0)
+}
+
+fn fork_holding(index: usize) -> isize {
+    match SESSIONS.get(index) {
+        Some(session) => session.params.hold(|| fork_holding(index + 1)),
+        None => fork(),
+    }
+}
+
 pub fn allocate_session() -> Option<usize> {
     for i in 0..MAX_SESSIONS {
         if !SESSIONS[i].is_active() {
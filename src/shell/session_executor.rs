@@ -1,232 +1,114 @@
-use crate::utils::{trim_newline, bytes_equal, split_first_word};
-use crate::shell::session::ShellSession;
+use crate::syscalls::{open, close, read, lseek, memfd_create, O_RDONLY, O_CLOEXEC, MFD_CLOEXEC, SEEK_SET};
+use crate::utils::trim_newline;
+use crate::shell::session::{ShellSession, MAX_PENDING};
+use crate::shell::executor::{run_line, ExecState, Flow};
+use crate::shell::history::{expand_history, record_command};
+use crate::shell::script::run_startup_file;
+use crate::shell::storage::ENV_STORAGE;
+use crate::shell::parser::needs_more_input;
+use crate::io::Stdio;
 
-pub fn execute_command_in_session(session: &ShellSession, cmd: &[u8]) {
-    let cmd = trim_newline(cmd);
-    
-    if cmd.is_empty() {
-        return;
-    }
+const PATH_SIZE: usize = 256;
 
-    // Special commands that shouldn't run in session context
-    if bytes_equal(cmd, b"exit") {
-        session.write_output(b"Session closed\n");
-        return;
-    }
-
-    let (program, args) = split_first_word(cmd);
-    
-    // Built-in commands - redirect output to session
-    if bytes_equal(program, b"pwd") {
-        execute_builtin_pwd(session);
-        return;
-    }
-    
-    if bytes_equal(program, b"cd") {
-        execute_builtin_cd(session, args);
-        return;
-    }
-    
-    if bytes_equal(program, b"ls") {
-        execute_builtin_ls(session, args);
-        return;
-    }
+/// Runs a line typed in a session. Lines are collected until they form a
+/// complete command, so `if`, loops and open quotes can span several.
+pub fn execute_command_in_session(session: &'static ShellSession, line: &[u8]) {
+    let mut expanded = [0u8; MAX_PENDING];
+    let line = match expand_history(line, &mut expanded) {
+        Ok(Some(len)) => {
+            session.write_output(&expanded[..len]);
+            session.write_output(b"\n");
+            &expanded[..len]
+        }
+        Ok(None) => line,
+        Err(err) => {
+            session.clear_pending();
+            err.report(|s| session.write_output(s));
+            return;
+        }
+    };
     
-    if bytes_equal(program, b"echo") {
-        execute_builtin_echo(session, args);
+    if !session.append_pending(line) || !session.append_pending(b"\n") {
+        session.clear_pending();
+        session.write_output(b"reshell: command too long\n");
         return;
     }
     
-    if bytes_equal(program, b"export") {
-        execute_builtin_export(session, args);
+    let mut command = [0u8; MAX_PENDING];
+    let len = session.get_pending(&mut command);
+    if needs_more_input(&command[..len]) {
         return;
     }
+    session.clear_pending();
     
-    if bytes_equal(program, b"env") {
-        execute_builtin_export(session, b"");
-        return;
-    }
+    let cmd = trim_newline(&command[..len]);
     
-    if bytes_equal(program, b"threads") {
-        execute_builtin_threads(session);
+    if cmd.is_empty() {
         return;
     }
-    
-    // External command - would need process isolation per session
-    // For now, just report
-    session.write_output(b"External commands not yet supported in session mode\n");
+    record_command(cmd);
+    run_captured(session, |io, state| {
+        run_line(cmd, io, state);
+    });
 }
 
-fn execute_builtin_pwd(session: &ShellSession) {
-    use crate::syscalls::getcwd;
-    let mut buf = [0u8; 512];
-    let len = getcwd(&mut buf);
-    if len > 0 {
-        session.write_output(&buf[..len as usize]);
-        session.write_output(b"\n");
-    } else {
-        session.write_output(b"pwd: error\n");
+/// Runs the file `ENV` names, if it is set, in a newly allocated session
+/// before its first prompt, to set the session up. Its output goes to the
+/// session like that of any command.
+pub fn init_session(session: &'static ShellSession) {
+    let mut path = [0u8; PATH_SIZE];
+    match ENV_STORAGE.get(b"ENV", &mut path) {
+        Some(len) if len > 0 => run_captured(session, |io, state| run_startup_file(&path[..len], io, state)),
+        _ => {}
     }
 }
 
-fn execute_builtin_cd(session: &ShellSession, path: &[u8]) {
-    use crate::syscalls::chdir;
-    use crate::utils::trim_spaces;
-    use crate::shell::parser::expand_env_vars;
-    
-    let path = trim_spaces(path);
-    
-    if path.is_empty() {
-        session.write_output(b"cd: missing argument\n");
+/// Runs `run` with the session's output as its standard output and error
+/// and nothing to read on its standard input.
+fn run_captured<F>(session: &'static ShellSession, run: F) where F: FnOnce(&Stdio, &mut ExecState) {
+    // Commands write into an in-memory file that is copied to the session
+    // afterwards, so builtins and child processes share one output path.
+    let out_fd = memfd_create(b"reshell-session\0", MFD_CLOEXEC);
+    if out_fd < 0 {
+        session.write_output(b"reshell: cannot capture output\n");
         return;
     }
-    
-    let mut expanded = [0u8; 512];
-    let len = expand_env_vars(path, &mut expanded);
-    let expanded_path = &expanded[..len];
-    
-    let mut path_with_null = [0u8; 512];
-    let copy_len = expanded_path.len().min(511);
-    path_with_null[..copy_len].copy_from_slice(&expanded_path[..copy_len]);
-    path_with_null[copy_len] = 0;
-    
-    let ret = chdir(&path_with_null);
-    if ret < 0 {
-        session.write_output(b"cd: ");
-        session.write_output(expanded_path);
-        session.write_output(b": No such directory\n");
-    }
-}
 
-fn execute_builtin_ls(session: &ShellSession, path: &[u8]) {
-    use crate::syscalls::{open, close, getdents64, O_RDONLY, O_DIRECTORY};
-    use crate::shell::parser::DirentParser;
-    use crate::utils::{trim_spaces, sort_entries};
-    
-    let path = trim_spaces(path);
-    
-    // Prepare path with null terminator
-    let mut path_with_null = [0u8; 512];
-    if path.is_empty() {
-        path_with_null[0] = b'.';
-        path_with_null[1] = 0;
-    } else {
-        let copy_len = path.len().min(511);
-        path_with_null[..copy_len].copy_from_slice(&path[..copy_len]);
-        path_with_null[copy_len] = 0;
-    }
-    
-    let fd = open(&path_with_null, O_RDONLY | O_DIRECTORY);
-    if fd < 0 {
-        session.write_output(b"ls: cannot open directory\n");
-        return;
+    let in_fd = open(b"/dev/null\0", O_RDONLY | O_CLOEXEC, 0);
+
+    let io = Stdio {
+        stdin: in_fd as i32,
+        stdout: out_fd as i32,
+        stderr: out_fd as i32,
+    };
+    let mut state = ExecState::new();
+    state.interactive = true;
+    state.params = session.params();
+    state.jobs = session.jobs();
+    run(&io, &mut state);
+    session.set_params(&state.params);
+    
+    // Like a prompt, the output tells of jobs that finished or stopped
+    state.jobs.list(&io, true);
+
+    if in_fd >= 0 {
+        close(in_fd as i32);
     }
     
-    let mut buf = [0u8; 4096];
-    let mut entries = [[0u8; 256]; 64];
-    let mut count = 0;
-    
+    lseek(out_fd as i32, 0, SEEK_SET);
+    let mut buf = [0u8; 1024];
     loop {
-        let n = getdents64(fd as i32, &mut buf);
+        let n = read(out_fd as i32, &mut buf);
         if n <= 0 {
             break;
         }
-        
-        let mut parser = DirentParser::new(&buf[..n as usize]);
-        while let Some(dirent) = parser.next() {
-            // Skip . and ..
-            if dirent.name.len() == 1 && dirent.name[0] == b'.' {
-                continue;
-            }
-            if dirent.name.len() == 2 && dirent.name[0] == b'.' && dirent.name[1] == b'.' {
-                continue;
-            }
-            
-            if count < 64 {
-                let name_len = dirent.name.len().min(256);
-                entries[count][..name_len].copy_from_slice(&dirent.name[..name_len]);
-                count += 1;
-            }
-        }
-    }
-    
-    close(fd as i32);
-    
-    sort_entries(&mut entries, count);
-    
-    for i in 0..count {
-        let name_len = entries[i].iter().position(|&c| c == 0).unwrap_or(256);
-        session.write_output(&entries[i][..name_len]);
-        session.write_output(b"  ");
+        session.write_output(&buf[..n as usize]);
     }
-    if count > 0 {
-        session.write_output(b"\n");
-    }
-}
 
-fn execute_builtin_echo(session: &ShellSession, args: &[u8]) {
-    use crate::shell::parser::expand_env_vars;
-    use crate::utils::trim_spaces;
-    
-    let args = trim_spaces(args);
+    close(out_fd as i32);
     
-    let mut expanded = [0u8; 512];
-    let len = expand_env_vars(args, &mut expanded);
-    
-    session.write_output(&expanded[..len]);
-    session.write_output(b"\n");
-}
-
-fn execute_builtin_export(session: &ShellSession, args: &[u8]) {
-    use crate::shell::ENV_STORAGE;
-    use crate::utils::trim_spaces;
-    
-    let args = trim_spaces(args);
-    
-    if args.is_empty() {
-        ENV_STORAGE.iter(|var| {
-            session.write_output(var);
-            session.write_output(b"\n");
-        });
-        return;
-    }
-    
-    if let Some(eq_pos) = args.iter().position(|&c| c == b'=') {
-        let key = &args[..eq_pos];
-        let value = &args[eq_pos + 1..];
-        ENV_STORAGE.set(key, value);
-    }
-}
-
-fn execute_builtin_threads(session: &ShellSession) {
-    use crate::system::thread::get_thread_stats;
-    
-    let (active, total) = get_thread_stats();
-    
-    session.write_output(b"Active threads: ");
-    write_number_to_session(session, active as i64);
-    session.write_output(b" / ");
-    write_number_to_session(session, total as i64);
-    session.write_output(b"\n");
-}
-
-fn write_number_to_session(session: &ShellSession, n: i64) {
-    if n == 0 {
-        session.write_output(b"0");
-        return;
-    }
-    
-    let mut num = n;
-    let mut digits = [0u8; 20];
-    let mut count = 0;
-    
-    while num > 0 {
-        digits[count] = b'0' + (num % 10) as u8;
-        num /= 10;
-        count += 1;
-    }
-    
-    for i in (0..count).rev() {
-        session.write_output(&[digits[i]]);
+    // `exit` must not shut down the shell from a web session
+    if let Flow::Exit(_) = state.flow {
+        session.write_output(b"Session closed\n");
     }
 }
@@ -0,0 +1,157 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+pub const MAX_ALIASES: usize = 32;
+pub const ALIAS_VALUE_SIZE: usize = 256;
+const ALIAS_NAME_SIZE: usize = 64;
+
+/// Aliases, replacing the first word of a command with their value.
+pub static ALIASES: AliasTable = AliasTable::new();
+
+struct Alias {
+    name: [u8; ALIAS_NAME_SIZE],
+    name_len: usize,
+    value: [u8; ALIAS_VALUE_SIZE],
+    value_len: usize,
+}
+
+impl Alias {
+    const fn new() -> Self {
+        Self {
+            name: [0u8; ALIAS_NAME_SIZE],
+            name_len: 0,
+            value: [0u8; ALIAS_VALUE_SIZE],
+            value_len: 0,
+        }
+    }
+    
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+    
+    fn value(&self) -> &[u8] {
+        &self.value[..self.value_len]
+    }
+}
+
+struct Aliases {
+    slots: [Alias; MAX_ALIASES],
+    count: usize,
+}
+
+impl Aliases {
+    fn find(&self, name: &[u8]) -> Option<usize> {
+        (0..self.count).find(|&i| self.slots[i].name() == name)
+    }
+}
+
+pub struct AliasTable {
+    data: UnsafeCell<Aliases>,
+    locked: AtomicBool,
+}
+
+unsafe impl Sync for AliasTable {}
+
+impl AliasTable {
+    pub const fn new() -> Self {
+        Self {
+            data: UnsafeCell::new(Aliases {
+                slots: [const { Alias::new() }; MAX_ALIASES],
+                count: 0,
+            }),
+            locked: AtomicBool::new(false),
+        }
+    }
+    
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Aliases) -> R,
+    {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        
+        let result = unsafe { f(&mut *self.data.get()) };
+        
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    
+    /// Runs `f` with the lock held, so `fork_shell` can keep every
+    /// thread away from the store while it forks.
+    pub fn hold<F, R>(&self, f: F) -> R where F: FnOnce() -> R {
+        self.with(|_| f())
+    }
+    
+    /// Defines or redefines alias `name`.
+    pub fn define(&self, name: &[u8], value: &[u8]) -> Result<(), &'static str> {
+        if name.len() > ALIAS_NAME_SIZE {
+            return Err("alias name too long");
+        }
+        if value.len() > ALIAS_VALUE_SIZE {
+            return Err("alias value too long");
+        }
+        
+        self.with(|aliases| {
+            let slot = match aliases.find(name) {
+                Some(slot) => slot,
+                None if aliases.count < MAX_ALIASES => {
+                    aliases.count += 1;
+                    aliases.count - 1
+                }
+                None => return Err("too many aliases"),
+            };
+            
+            let alias = &mut aliases.slots[slot];
+            alias.name[..name.len()].copy_from_slice(name);
+            alias.name_len = name.len();
+            alias.value[..value.len()].copy_from_slice(value);
+            alias.value_len = value.len();
+            Ok(())
+        })
+    }
+    
+    /// Removes alias `name`, keeping the others in definition order.
+    /// Returns `false` if there is no such alias.
+    pub fn remove(&self, name: &[u8]) -> bool {
+        self.with(|aliases| {
+            let slot = match aliases.find(name) {
+                Some(slot) => slot,
+                None => return false,
+            };
+            for i in slot..aliases.count - 1 {
+                aliases.slots.swap(i, i + 1);
+            }
+            aliases.count -= 1;
+            true
+        })
+    }
+    
+    pub fn clear(&self) {
+        self.with(|aliases| aliases.count = 0);
+    }
+    
+    pub fn is_empty(&self) -> bool {
+        self.with(|aliases| aliases.count == 0)
+    }
+    
+    /// Copies the value of alias `name` into `out`, `None` if there is no
+    /// such alias.
+    pub fn get(&self, name: &[u8], out: &mut [u8]) -> Option<usize> {
+        self.with(|aliases| {
+            let alias = &aliases.slots[aliases.find(name)?];
+            let len = alias.value_len.min(out.len());
+            out[..len].copy_from_slice(&alias.value[..len]);
+            Some(len)
+        })
+    }
+    
+    /// Calls `f` with the name and value of every alias, in definition order.
+    pub fn for_each<F>(&self, mut f: F) where F: FnMut(&[u8], &[u8]) {
+        self.with(|aliases| {
+            for alias in &aliases.slots[..aliases.count] {
+                f(alias.name(), alias.value());
+            }
+        });
+    }
+}
@@ -1,164 +1,291 @@
-use core::sync::atomic::{AtomicBool, AtomicUsize, AtomicPtr, Ordering};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::io::LineBuffer;
 
+/// Shell variables. Those marked `EXPORTED` make up the environment of
+/// the commands the shell runs.
 pub static ENV_STORAGE: EnvStorage = EnvStorage::new();
 
-pub struct EnvStorage {
-    vars: AtomicPtr<[[u8; 256]; 32]>,
-    count: AtomicUsize,
-    initialized: AtomicBool,
+/// The variable is passed on to commands.
+pub const EXPORTED: u8 = 1;
+/// The variable can't be changed or unset.
+pub const READONLY: u8 = 2;
+
+const POINTER_SIZE: usize = core::mem::size_of::<*const u8>();
+/// The environment when there is no memory for a longer one.
+const NO_VARIABLES: &[*const u8] = &[core::ptr::null()];
+
+struct Variables {
+    /// The variables one after the other: a byte of flags, `NAME=VALUE`,
+    /// or just `NAME` for one given flags but no value, and a NUL
+    buf: LineBuffer,
 }
 
-impl EnvStorage {
+/// The environment of a command about to be started, copied out of the
+/// storage so it stays the same whatever other threads do to the
+/// variables.
+pub struct Environment {
+    /// `NAME=VALUE` of the exported variables, each followed by a NUL
+    text: LineBuffer,
+    /// Pointers into `text`, ending with a null pointer
+    pointers: LineBuffer,
+}
+
+impl Environment {
     pub const fn new() -> Self {
         Self {
-            vars: AtomicPtr::new(core::ptr::null_mut()),
-            count: AtomicUsize::new(0),
-            initialized: AtomicBool::new(false),
+            text: LineBuffer::new(),
+            pointers: LineBuffer::new(),
         }
     }
     
-    fn ensure_init(&self) {
-        if self.initialized.load(Ordering::Acquire) {
-            return;
+    /// The pointers to the `NAME=VALUE` strings, followed by a null
+    /// pointer, for use as an `execve` environment.
+    pub fn envp(&self) -> &[*const u8] {
+        let count = self.pointers.len() / POINTER_SIZE;
+        if count == 0 {
+            return NO_VARIABLES;
         }
-        
-        use crate::syscalls::{sys_mmap, PROT_READ, PROT_WRITE, MAP_PRIVATE, MAP_ANONYMOUS, write, STDERR};
-        
-        let size = core::mem::size_of::<[[u8; 256]; 32]>();
-        let ptr = unsafe {
-            sys_mmap(
-                core::ptr::null_mut(),
-                size,
-                PROT_READ | PROT_WRITE,
-                MAP_PRIVATE | MAP_ANONYMOUS,
-                -1,
-                0
-            )
-        } as *mut [[u8; 256]; 32];
-        
-        if ptr.is_null() || ptr as isize == -1 {
-            write(STDERR, b"[ERROR] Failed to allocate ENV_STORAGE\n");
-            return;
-        }
-        
-        unsafe {
-            for i in 0..32 {
-                for j in 0..256 {
-                    (*ptr)[i][j] = 0;
-                }
-            }
+        // The pointers are in memory mapped whole pages, so they are
+        // aligned
+        unsafe { core::slice::from_raw_parts(self.pointers.as_slice().as_ptr() as *const *const u8, count) }
+    }
+}
+
+/// Splits the text of a variable into its name and value, if it has one.
+fn split(text: &[u8]) -> (&[u8], Option<&[u8]>) {
+    match text.iter().position(|&b| b == b'=') {
+        Some(equals) => (&text[..equals], Some(&text[equals + 1..])),
+        None => (text, None),
+    }
+}
+
+/// The variables in `buf`, as where each starts, its flags and its text.
+fn entries(buf: &LineBuffer) -> impl Iterator<Item = (usize, u8, &[u8])> {
+    let buf = buf.as_slice();
+    let mut at = 0;
+    core::iter::from_fn(move || {
+        let start = at;
+        let len = buf.get(start + 1..)?.iter().position(|&b| b == 0)?;
+        at = start + len + 2;
+        Some((start, buf[start], &buf[start + 1..start + 1 + len]))
+    })
+}
+
+impl Variables {
+    /// Where variable `name` starts in `buf`, its flags and the length of
+    /// its text.
+    fn find(&self, name: &[u8]) -> Option<(usize, u8, usize)> {
+        entries(&self.buf).find(|&(_, _, text)| split(text).0 == name).map(|(start, flags, text)| (start, flags, text.len()))
+    }
+    
+    fn text(&self, start: usize, len: usize) -> &[u8] {
+        &self.buf.as_slice()[start + 1..start + 1 + len]
+    }
+}
+
+pub struct EnvStorage {
+    data: UnsafeCell<Variables>,
+    locked: AtomicBool,
+}
+
+unsafe impl Sync for EnvStorage {}
+
+impl EnvStorage {
+    pub const fn new() -> Self {
+        Self {
+            data: UnsafeCell::new(Variables {
+                buf: LineBuffer::new(),
+            }),
+            locked: AtomicBool::new(false),
         }
-        
-        self.vars.store(ptr, Ordering::Release);
-        self.initialized.store(true, Ordering::Release);
     }
     
-    pub fn set(&self, name: &[u8], value: &[u8]) -> bool {
-        self.ensure_init();
-        
-        let vars_ptr = self.vars.load(Ordering::Acquire);
-        if vars_ptr.is_null() {
-            return false;
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Variables) -> R,
+    {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
         }
         
-        let count = self.count.load(Ordering::Acquire);
-        if count >= 32 {
-            return false;
-        }
+        let result = unsafe { f(&mut *self.data.get()) };
         
-        unsafe {
-            let vars = &mut *vars_ptr;
-            let buf = &mut vars[count];
-            
-            for i in 0..256 {
-                buf[i] = 0;
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    
+    /// Runs `f` with the lock held, so `fork_shell` can keep every
+    /// thread away from the store while it forks.
+    pub fn hold<F, R>(&self, f: F) -> R where F: FnOnce() -> R {
+        self.with(|_| f())
+    }
+    
+    /// Sets `name` to `value`. A new variable is not exported; one that
+    /// exists keeps its flags.
+    pub fn set(&self, name: &[u8], value: &[u8]) -> Result<(), &'static str> {
+        self.declare(name, Some(value), 0)
+    }
+    
+    /// Adds `flags` to variable `name`, and sets it to `value` if given.
+    /// A variable with flags but no value is still unset, until it gets
+    /// one.
+    pub fn declare(&self, name: &[u8], value: Option<&[u8]>, flags: u8) -> Result<(), &'static str> {
+        self.with(|vars| {
+            let old = vars.find(name);
+            let old_flags = old.map_or(0, |(_, flags, _)| flags);
+            if old_flags & READONLY != 0 && value.is_some() {
+                return Err("readonly variable");
             }
             
-            let mut idx = 0;
-            for &b in name {
-                if idx >= 128 { return false; }
-                buf[idx] = b;
-                idx += 1;
-            }
-            buf[idx] = b'=';
-            idx += 1;
+            let value = match value {
+                Some(value) => value,
+                // Only the flags change
+                None => match old {
+                    Some((start, _, _)) => {
+                        vars.buf.as_mut_slice()[start] |= flags;
+                        return Ok(());
+                    }
+                    None => return push_entry(&mut vars.buf, &[&[flags], name, &[0]]),
+                },
+            };
             
-            for &b in value {
-                if idx >= 255 { return false; }
-                buf[idx] = b;
-                idx += 1;
+            // The new text goes at the end, so make room before the old
+            // one is removed
+            if !vars.buf.reserve(name.len() + value.len() + 3) {
+                return Err("out of memory");
             }
-        }
-        
-        self.count.store(count + 1, Ordering::Release);
-        true
+            if let Some((start, _, len)) = old {
+                vars.buf.remove(start..start + len + 2);
+            }
+            push_entry(&mut vars.buf, &[&[old_flags | flags], name, b"=", value, &[0]])
+        })
     }
     
-    pub fn get(&self, name: &[u8], out_buf: &mut [u8]) -> usize {
-        self.ensure_init();
-        
-        let vars_ptr = self.vars.load(Ordering::Acquire);
-        if vars_ptr.is_null() {
-            return 0;
-        }
-        
-        let count = self.count.load(Ordering::Acquire);
-        
-        unsafe {
-            let vars = &*vars_ptr;
-            
-            for i in 0..count {
-                let var = &vars[i];
-                let mut j = 0;
-                let mut matched = true;
-                
-                while j < name.len() && j < 256 && var[j] != 0 {
-                    if var[j] != name[j] {
-                        matched = false;
-                        break;
-                    }
-                    j += 1;
-                }
-                
-                if matched && var[j] == b'=' {
-                    j += 1;
-                    let mut idx = 0;
-                    while var[j] != 0 && idx < out_buf.len() {
-                        out_buf[idx] = var[j];
-                        idx += 1;
-                        j += 1;
-                    }
-                    return idx;
-                }
+    /// Removes `name` along with its flags.
+    pub fn remove(&self, name: &[u8]) -> Result<(), &'static str> {
+        self.with(|vars| {
+            let (start, flags, len) = match vars.find(name) {
+                Some(found) => found,
+                None => return Ok(()),
+            };
+            if flags & READONLY != 0 {
+                return Err("readonly variable");
             }
-        }
-        0
+            vars.buf.remove(start..start + len + 2);
+            Ok(())
+        })
     }
     
-    pub fn iter<F>(&self, mut f: F) where F: FnMut(&[u8]) {
-        self.ensure_init();
-        
-        let vars_ptr = self.vars.load(Ordering::Acquire);
-        if vars_ptr.is_null() {
+    /// Copies the value of `name` into `out_buf`, `None` if it is unset.
+    /// A value too long for `out_buf` is cut short.
+    pub fn get(&self, name: &[u8], out_buf: &mut [u8]) -> Option<usize> {
+        self.with(|vars| {
+            let (start, _, len) = vars.find(name)?;
+            let value = split(vars.text(start, len)).1?;
+            let len = value.len().min(out_buf.len());
+            out_buf[..len].copy_from_slice(&value[..len]);
+            Some(len)
+        })
+    }
+    
+    /// Copies the whole value of `name` into `out`, however long. Returns
+    /// `false` if it is unset or there is no room.
+    pub fn get_all(&self, name: &[u8], out: &mut LineBuffer) -> bool {
+        self.with(|vars| {
+            let value = vars.find(name).and_then(|(start, _, len)| split(vars.text(start, len)).1);
+            match value {
+                Some(value) => out.push(value),
+                None => false,
+            }
+        })
+    }
+    
+    /// Appends the whole value of `name`, if it has one, to `out`, and
+    /// returns its flags and whether it has a value. `None` if there is no
+    /// such variable.
+    pub fn copy(&self, name: &[u8], out: &mut LineBuffer) -> Result<Option<(u8, bool)>, &'static str> {
+        self.with(|vars| {
+            let (start, flags, len) = match vars.find(name) {
+                Some(found) => found,
+                None => return Ok(None),
+            };
+            match split(vars.text(start, len)).1 {
+                Some(value) if !out.push(value) => Err("out of memory"),
+                value => Ok(Some((flags, value.is_some()))),
+            }
+        })
+    }
+    
+    /// Puts `name` back the way `copy` found it, flags and all, even if it
+    /// is readonly now. `None` removes it.
+    pub fn restore(&self, name: &[u8], saved: Option<(u8, Option<&[u8]>)>) -> Result<(), &'static str> {
+        self.with(|vars| {
+            let room = name.len() + saved.and_then(|(_, value)| value).map_or(0, |value| value.len()) + 3;
+            if !vars.buf.reserve(room) {
+                return Err("out of memory");
+            }
+            if let Some((start, _, len)) = vars.find(name) {
+                vars.buf.remove(start..start + len + 2);
+            }
+            match saved {
+                Some((flags, Some(value))) => push_entry(&mut vars.buf, &[&[flags], name, b"=", value, &[0]]),
+                Some((flags, None)) if flags != 0 => push_entry(&mut vars.buf, &[&[flags], name, &[0]]),
+                _ => Ok(()),
+            }
+        })
+    }
+    
+    /// Calls `f` with the name, value and flags of every variable. The
+    /// value is `None` for a variable that has flags but is unset. `f`
+    /// gets a copy, so it can be slow or change variables itself.
+    pub fn for_each<F>(&self, mut f: F) where F: FnMut(&[u8], Option<&[u8]>, u8) {
+        let mut copy = LineBuffer::new();
+        if !self.with(|vars| copy.push(vars.buf.as_slice())) {
             return;
         }
+        for (_, flags, text) in entries(&copy) {
+            let (name, value) = split(text);
+            f(name, value, flags);
+        }
+    }
+    
+    /// Copies the exported variables that have a value into `env`.
+    /// Returns `false` if there is no room.
+    pub fn environment(&self, env: &mut Environment) -> bool {
+        env.text.clear();
+        env.pointers.clear();
+        let copied = self.with(|vars| {
+            entries(&vars.buf)
+                .filter(|&(_, flags, text)| flags & EXPORTED != 0 && split(text).1.is_some())
+                .all(|(_, _, text)| env.text.push(text) && env.text.push(&[0]))
+        });
+        if !copied {
+            return false;
+        }
         
-        let count = self.count.load(Ordering::Acquire);
-        
-        unsafe {
-            let vars = &*vars_ptr;
-            
-            for i in 0..count {
-                let var = &vars[i];
-                let mut len = 0;
-                while len < 256 && var[len] != 0 {
-                    len += 1;
-                }
-                if len > 0 {
-                    f(&var[..len]);
-                }
+        // The text is complete, so it won't move again
+        let text = env.text.as_slice();
+        let mut start = 0;
+        while start < text.len() {
+            let len = text[start..].iter().position(|&b| b == 0).unwrap_or(text.len() - start);
+            if !env.pointers.push(&(text[start..].as_ptr() as usize).to_ne_bytes()) {
+                return false;
             }
+            start += len + 1;
         }
+        env.pointers.push(&0usize.to_ne_bytes())
     }
 }
 
+/// Appends the parts of a variable to `buf`.
+fn push_entry(buf: &mut LineBuffer, parts: &[&[u8]]) -> Result<(), &'static str> {
+    let len = parts.iter().map(|part| part.len()).sum();
+    if !buf.reserve(len) {
+        return Err("out of memory");
+    }
+    for part in parts {
+        buf.push(part);
+    }
+    Ok(())
+}
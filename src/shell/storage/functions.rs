@@ -0,0 +1,133 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::io::LineBuffer;
+
+pub const MAX_FUNCTIONS: usize = 32;
+const FUNCTION_BODY_SIZE: usize = 4096;
+const FUNCTION_NAME_SIZE: usize = 64;
+
+/// Shell functions, stored as the source text of their body so a call can
+/// parse and run it like a command line.
+pub static FUNCTIONS: FunctionTable = FunctionTable::new();
+
+struct Function {
+    name: [u8; FUNCTION_NAME_SIZE],
+    name_len: usize,
+    body: [u8; FUNCTION_BODY_SIZE],
+    body_len: usize,
+}
+
+impl Function {
+    const fn new() -> Self {
+        Self {
+            name: [0u8; FUNCTION_NAME_SIZE],
+            name_len: 0,
+            body: [0u8; FUNCTION_BODY_SIZE],
+            body_len: 0,
+        }
+    }
+    
+    fn name(&self) -> &[u8] {
+        &self.name[..self.name_len]
+    }
+}
+
+struct Functions {
+    slots: [Function; MAX_FUNCTIONS],
+    count: usize,
+}
+
+impl Functions {
+    fn find(&self, name: &[u8]) -> Option<usize> {
+        (0..self.count).find(|&i| self.slots[i].name() == name)
+    }
+}
+
+pub struct FunctionTable {
+    data: UnsafeCell<Functions>,
+    locked: AtomicBool,
+}
+
+unsafe impl Sync for FunctionTable {}
+
+impl FunctionTable {
+    pub const fn new() -> Self {
+        Self {
+            data: UnsafeCell::new(Functions {
+                slots: [const { Function::new() }; MAX_FUNCTIONS],
+                count: 0,
+            }),
+            locked: AtomicBool::new(false),
+        }
+    }
+    
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Functions) -> R,
+    {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        
+        let result = unsafe { f(&mut *self.data.get()) };
+        
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    
+    /// Runs `f` with the lock held, so `fork_shell` can keep every
+    /// thread away from the store while it forks.
+    pub fn hold<F, R>(&self, f: F) -> R where F: FnOnce() -> R {
+        self.with(|_| f())
+    }
+    
+    /// Defines or redefines function `name`.
+    pub fn define(&self, name: &[u8], body: &[u8]) -> Result<(), &'static str> {
+        if name.len() > FUNCTION_NAME_SIZE {
+            return Err("function name too long");
+        }
+        if body.len() > FUNCTION_BODY_SIZE {
+            return Err("function body too long");
+        }
+        
+        self.with(|functions| {
+            let slot = match functions.find(name) {
+                Some(slot) => slot,
+                None if functions.count < MAX_FUNCTIONS => {
+                    functions.count += 1;
+                    functions.count - 1
+                }
+                None => return Err("too many functions"),
+            };
+            
+            let function = &mut functions.slots[slot];
+            function.name[..name.len()].copy_from_slice(name);
+            function.name_len = name.len();
+            function.body[..body.len()].copy_from_slice(body);
+            function.body_len = body.len();
+            Ok(())
+        })
+    }
+    
+    /// Appends the body of function `name` to `out`. Returns `false` if
+    /// there is no such function, and an error if there is no room.
+    pub fn get(&self, name: &[u8], out: &mut LineBuffer) -> Result<bool, &'static str> {
+        self.with(|functions| match functions.find(name) {
+            Some(slot) => {
+                let function = &functions.slots[slot];
+                if out.push(&function.body[..function.body_len]) { Ok(true) } else { Err("out of memory") }
+            }
+            None => Ok(false),
+        })
+    }
+    
+    /// Calls `f` with the name and body of every function, in definition
+    /// order.
+    pub fn for_each<F>(&self, mut f: F) where F: FnMut(&[u8], &[u8]) {
+        self.with(|functions| {
+            for function in &functions.slots[..functions.count] {
+                f(function.name(), &function.body[..function.body_len]);
+            }
+        });
+    }
+}
@@ -0,0 +1,212 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+/// Commands kept; older ones are dropped as new ones come in.
+const MAX_HISTORY: usize = 500;
+const HISTORY_BUF_SIZE: usize = 64 * 1024;
+/// Longest command kept, which is the longest one the prompt accepts.
+pub const HISTORY_LINE_SIZE: usize = 4096;
+
+/// Commands run at the local prompt and in the web sessions, oldest first.
+/// Entries are numbered from 1 and keep their number when older ones are
+/// dropped.
+pub static HISTORY: HistoryTable = HistoryTable::new();
+
+struct History {
+    /// Entries, back to back in order
+    buf: [u8; HISTORY_BUF_SIZE],
+    lens: [usize; MAX_HISTORY],
+    count: usize,
+    /// Number of the oldest entry
+    first: usize,
+}
+
+impl History {
+    fn start(&self, index: usize) -> usize {
+        self.lens[..index].iter().sum()
+    }
+    
+    fn entry(&self, index: usize) -> &[u8] {
+        let start = self.start(index);
+        &self.buf[start..start + self.lens[index]]
+    }
+    
+    fn used(&self) -> usize {
+        self.start(self.count)
+    }
+    
+    /// Index of entry `number`, if it is still kept.
+    fn index(&self, number: usize) -> Option<usize> {
+        let index = number.checked_sub(self.first)?;
+        if index < self.count { Some(index) } else { None }
+    }
+    
+    fn remove(&mut self, index: usize) {
+        let start = self.start(index);
+        let len = self.lens[index];
+        let used = self.used();
+        self.buf.copy_within(start + len..used, start);
+        self.lens.copy_within(index + 1..self.count, index);
+        self.count -= 1;
+    }
+}
+
+fn copy_to(entry: &[u8], out: &mut [u8]) -> usize {
+    let len = entry.len().min(out.len());
+    out[..len].copy_from_slice(&entry[..len]);
+    len
+}
+
+pub struct HistoryTable {
+    data: UnsafeCell<History>,
+    locked: AtomicBool,
+}
+
+unsafe impl Sync for HistoryTable {}
+
+impl HistoryTable {
+    pub const fn new() -> Self {
+        Self {
+            data: UnsafeCell::new(History {
+                buf: [0u8; HISTORY_BUF_SIZE],
+                lens: [0; MAX_HISTORY],
+                count: 0,
+                first: 1,
+            }),
+            locked: AtomicBool::new(false),
+        }
+    }
+    
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut History) -> R,
+    {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        
+        let result = unsafe { f(&mut *self.data.get()) };
+        
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    
+    /// Runs `f` with the lock held, so `fork_shell` can keep every
+    /// thread away from the store while it forks.
+    pub fn hold<F, R>(&self, f: F) -> R where F: FnOnce() -> R {
+        self.with(|_| f())
+    }
+    
+    /// Appends `line`, dropping the oldest entries to make room. Empty
+    /// lines and repeats of the previous entry are not kept. Returns
+    /// whether `line` was added.
+    pub fn add(&self, line: &[u8]) -> bool {
+        if line.is_empty() || line.len() > HISTORY_LINE_SIZE {
+            return false;
+        }
+        
+        self.with(|history| {
+            if history.count > 0 && history.entry(history.count - 1) == line {
+                return false;
+            }
+            
+            while history.count == MAX_HISTORY || history.used() + line.len() > HISTORY_BUF_SIZE {
+                history.remove(0);
+                history.first += 1;
+            }
+            
+            let start = history.used();
+            history.buf[start..start + line.len()].copy_from_slice(line);
+            history.lens[history.count] = line.len();
+            history.count += 1;
+            true
+        })
+    }
+    
+    /// Removes entry `number`; the entries after it are renumbered.
+    /// Returns `false` if there is no such entry.
+    pub fn remove(&self, number: usize) -> bool {
+        self.with(|history| match history.index(number) {
+            Some(index) => {
+                history.remove(index);
+                true
+            }
+            None => false,
+        })
+    }
+    
+    /// Removes every entry; numbering starts over.
+    pub fn clear(&self) {
+        self.with(|history| {
+            history.count = 0;
+            history.first = 1;
+        });
+    }
+    
+    pub fn len(&self) -> usize {
+        self.with(|history| history.count)
+    }
+    
+    /// Number the next entry will get.
+    pub fn next_number(&self) -> usize {
+        self.with(|history| history.first + history.count)
+    }
+    
+    /// Copies entry `number` into `out`, `None` if it isn't kept.
+    pub fn get(&self, number: usize, out: &mut [u8]) -> Option<usize> {
+        self.with(|history| {
+            let index = history.index(number)?;
+            Some(copy_to(history.entry(index), out))
+        })
+    }
+    
+    /// Copies the newest entry starting with `prefix` into `out`.
+    pub fn find_prefix(&self, prefix: &[u8], out: &mut [u8]) -> Option<usize> {
+        self.with(|history| {
+            let mut end = history.used();
+            for index in (0..history.count).rev() {
+                let entry = &history.buf[end - history.lens[index]..end];
+                if entry.starts_with(prefix) {
+                    return Some(copy_to(entry, out));
+                }
+                end -= history.lens[index];
+            }
+            None
+        })
+    }
+    
+    /// Finds the entry containing `text` nearest to number `from`, which
+    /// is included, looking at older entries if `backward` and newer ones
+    /// otherwise. Copies it into `out` and returns its number and length.
+    pub fn search(&self, text: &[u8], from: usize, backward: bool, out: &mut [u8]) -> Option<(usize, usize)> {
+        self.with(|history| {
+            let contains = |index: usize| {
+                let entry = history.entry(index);
+                text.is_empty() || entry.windows(text.len()).any(|window| window == text)
+            };
+            let index = if backward {
+                let last = from.checked_sub(history.first)?.min(history.count.checked_sub(1)?);
+                (0..=last).rev().find(|&index| contains(index))?
+            } else {
+                (from.saturating_sub(history.first)..history.count).find(|&index| contains(index))?
+            };
+            Some((history.first + index, copy_to(history.entry(index), out)))
+        })
+    }
+    
+    /// Calls `f` with the number and text of every entry from `first` on,
+    /// oldest first.
+    pub fn for_each<F>(&self, first: usize, mut f: F) where F: FnMut(usize, &[u8]) {
+        self.with(|history| {
+            let mut start = 0;
+            for index in 0..history.count {
+                let len = history.lens[index];
+                let number = history.first + index;
+                if number >= first {
+                    f(number, &history.buf[start..start + len]);
+                }
+                start += len;
+            }
+        });
+    }
+}
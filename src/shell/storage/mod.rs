@@ -1,3 +1,15 @@
+mod aliases;
 mod env_storage;
+mod functions;
+mod history;
+mod params;
+mod positional;
+mod status;
 
-pub use env_storage::{ENV_STORAGE};
+pub use env_storage::{ENV_STORAGE, EXPORTED, READONLY, Environment};
+pub use positional::SHELL_NAME;
+pub use params::{Params, SavedParams, CONSOLE_PARAMS};
+pub use aliases::{ALIASES, ALIAS_VALUE_SIZE};
+pub use functions::FUNCTIONS;
+pub use history::{HISTORY, HISTORY_LINE_SIZE};
+pub use status::{set_shell_pid, shell_pid};
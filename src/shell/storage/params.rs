@@ -0,0 +1,77 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use super::positional::Args;
+
+/// The parameters that belong to one shell session rather than to the
+/// whole process, since web sessions run commands side by side. They
+/// travel in the `ExecState` of a command line and are kept in a
+/// `SavedParams` between lines.
+#[derive(Clone, Copy)]
+pub struct Params {
+    /// Exit status of the most recent pipeline, `$?`.
+    pub status: i32,
+    /// Pid of the most recent background job, `$!`, or 0 if none.
+    pub background_pid: i32,
+    /// `$1`, `$2`...
+    pub args: Args,
+}
+
+impl Params {
+    pub const fn new() -> Self {
+        Self { status: 0, background_pid: 0, args: Args::new() }
+    }
+}
+
+/// The parameters of the shell on the terminal, and of the script or
+/// `-c` command it runs instead.
+pub static CONSOLE_PARAMS: SavedParams = SavedParams::new();
+
+/// `Params` kept from one command line of a session to the next.
+pub struct SavedParams {
+    data: UnsafeCell<Params>,
+    locked: AtomicBool,
+}
+
+unsafe impl Sync for SavedParams {}
+
+impl SavedParams {
+    pub const fn new() -> Self {
+        Self {
+            data: UnsafeCell::new(Params::new()),
+            locked: AtomicBool::new(false),
+        }
+    }
+    
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Params) -> R,
+    {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        
+        let result = unsafe { f(&mut *self.data.get()) };
+        
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    
+    /// Runs `f` with the lock held, so `fork_shell` can keep every
+    /// thread away from the store while it forks.
+    pub fn hold<F, R>(&self, f: F) -> R where F: FnOnce() -> R {
+        self.with(|_| f())
+    }
+    
+    pub fn load(&self) -> Params {
+        self.with(|params| *params)
+    }
+    
+    pub fn store(&self, params: &Params) {
+        self.with(|saved| *saved = *params);
+    }
+    
+    /// `$?`, for the prompt.
+    pub fn status(&self) -> i32 {
+        self.with(|params| params.status)
+    }
+}
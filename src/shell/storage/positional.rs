@@ -0,0 +1,126 @@
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicBool, Ordering};
+use crate::io::LineBuffer;
+
+pub const MAX_POSITIONAL: usize = 32;
+const PARAMS_BUF_SIZE: usize = 2048;
+const NAME_SIZE: usize = 256;
+const DEFAULT_NAME: &[u8] = b"reshell";
+
+/// `$0`, the name of the shell or of the script it runs.
+pub static SHELL_NAME: ShellName = ShellName::new();
+
+/// `$1`, `$2`... A copy is kept while a function runs with its own.
+#[derive(Clone, Copy)]
+pub struct Args {
+    buf: [u8; PARAMS_BUF_SIZE],
+    starts: [usize; MAX_POSITIONAL],
+    lens: [usize; MAX_POSITIONAL],
+    count: usize,
+}
+
+impl Args {
+    pub const fn new() -> Self {
+        Self {
+            buf: [0u8; PARAMS_BUF_SIZE],
+            starts: [0; MAX_POSITIONAL],
+            lens: [0; MAX_POSITIONAL],
+            count: 0,
+        }
+    }
+    
+    /// Replaces the parameters with `args`. Returns `false`, changing
+    /// nothing, if they don't fit.
+    pub fn set(&mut self, args: &[&[u8]]) -> bool {
+        let total: usize = args.iter().map(|arg| arg.len()).sum();
+        if args.len() > MAX_POSITIONAL || total > PARAMS_BUF_SIZE {
+            return false;
+        }
+        
+        let mut used = 0;
+        for (i, arg) in args.iter().enumerate() {
+            self.buf[used..used + arg.len()].copy_from_slice(arg);
+            self.starts[i] = used;
+            self.lens[i] = arg.len();
+            used += arg.len();
+        }
+        self.count = args.len();
+        true
+    }
+    
+    /// `$#`
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    
+    /// `$n`, counting from 1, or `None` if there is no such parameter.
+    pub fn get(&self, n: usize) -> Option<&[u8]> {
+        let idx = n.checked_sub(1).filter(|&idx| idx < self.count)?;
+        Some(&self.buf[self.starts[idx]..self.starts[idx] + self.lens[idx]])
+    }
+    
+    /// `$1`, `$2`... in order.
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        (1..=self.count).filter_map(|n| self.get(n))
+    }
+}
+
+struct Name {
+    buf: [u8; NAME_SIZE],
+    len: usize,
+}
+
+pub struct ShellName {
+    data: UnsafeCell<Name>,
+    locked: AtomicBool,
+}
+
+unsafe impl Sync for ShellName {}
+
+impl ShellName {
+    const fn new() -> Self {
+        let mut buf = [0u8; NAME_SIZE];
+        let mut i = 0;
+        while i < DEFAULT_NAME.len() {
+            buf[i] = DEFAULT_NAME[i];
+            i += 1;
+        }
+        Self {
+            data: UnsafeCell::new(Name { buf, len: DEFAULT_NAME.len() }),
+            locked: AtomicBool::new(false),
+        }
+    }
+    
+    fn with<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce(&mut Name) -> R,
+    {
+        while self.locked.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            core::hint::spin_loop();
+        }
+        
+        let result = unsafe { f(&mut *self.data.get()) };
+        
+        self.locked.store(false, Ordering::Release);
+        result
+    }
+    
+    /// Runs `f` with the lock held, so `fork_shell` can keep every
+    /// thread away from the store while it forks.
+    pub fn hold<F, R>(&self, f: F) -> R where F: FnOnce() -> R {
+        self.with(|_| f())
+    }
+    
+    pub fn set(&self, name: &[u8]) {
+        self.with(|stored| {
+            let len = name.len().min(NAME_SIZE);
+            stored.buf[..len].copy_from_slice(&name[..len]);
+            stored.len = len;
+        });
+    }
+    
+    /// Appends the name to `out`. Returns `false` if there is no room.
+    pub fn get(&self, out: &mut LineBuffer) -> bool {
+        self.with(|stored| out.push(&stored.buf[..stored.len]))
+    }
+}
@@ -0,0 +1,12 @@
+use core::sync::atomic::{AtomicI32, Ordering};
+
+/// Pid of the shell itself, exposed as `$$`. Subshells keep the parent's.
+static SHELL_PID: AtomicI32 = AtomicI32::new(0);
+
+pub fn set_shell_pid(pid: i32) {
+    SHELL_PID.store(pid, Ordering::Release);
+}
+
+pub fn shell_pid() -> i32 {
+    SHELL_PID.load(Ordering::Acquire)
+}
@@ -0,0 +1,33 @@
+pub const EPERM: isize = 1;
+pub const ENOENT: isize = 2;
+pub const EINTR: isize = 4;
+pub const EBADF: isize = 9;
+pub const ECHILD: isize = 10;
+pub const EACCES: isize = 13;
+pub const EEXIST: isize = 17;
+pub const ENOTDIR: isize = 20;
+pub const EISDIR: isize = 21;
+pub const ENOSPC: isize = 28;
+pub const EROFS: isize = 30;
+pub const ENAMETOOLONG: isize = 36;
+pub const ELOOP: isize = 40;
+
+/// Human readable text for a negative syscall return value.
+pub fn strerror(ret: isize) -> &'static [u8] {
+    match -ret {
+        EPERM => b"Operation not permitted",
+        ENOENT => b"No such file or directory",
+        EINTR => b"Interrupted system call",
+        EBADF => b"Bad file descriptor",
+        ECHILD => b"No child processes",
+        EACCES => b"Permission denied",
+        EEXIST => b"File exists",
+        ENOTDIR => b"Not a directory",
+        EISDIR => b"Is a directory",
+        ENOSPC => b"No space left on device",
+        EROFS => b"Read-only file system",
+        ENAMETOOLONG => b"File name too long",
+        ELOOP => b"Too many levels of symbolic links",
+        _ => b"Unknown error",
+    }
+}
@@ -23,6 +23,18 @@ pub struct LinuxDirent64 {
     pub d_name: [u8; 0],
 }
 
+/// Values of `d_type`. Some filesystems only report `DT_UNKNOWN`.
+pub const DT_UNKNOWN: u8 = 0;
+pub const DT_DIR: u8 = 4;
+pub const DT_LNK: u8 = 10;
+
 pub fn getdents64(fd: i32, buf: &mut [u8]) -> isize {
     syscall3!(217, fd, buf.as_mut_ptr(), buf.len())
 }
+
+pub const F_OK: i32 = 0;
+pub const X_OK: i32 = 1;
+
+pub fn access(path: &[u8], mode: i32) -> isize {
+    syscall2!(21, path.as_ptr(), mode)
+}
@@ -13,16 +13,45 @@ pub fn read(fd: i32, buf: &mut [u8]) -> isize {
 }
 
 pub const O_RDONLY: i32 = 0;
+pub const O_WRONLY: i32 = 0x1;
+pub const O_CREAT: i32 = 0x40;
+pub const O_TRUNC: i32 = 0x200;
+pub const O_APPEND: i32 = 0x400;
 pub const O_DIRECTORY: i32 = 0x10000;
+pub const O_CLOEXEC: i32 = 0x80000;
 
-pub fn open(path: &[u8], flags: i32) -> isize {
-    syscall3!(2, path.as_ptr(), flags, 0)
+pub fn open(path: &[u8], flags: i32, mode: u32) -> isize {
+    syscall3!(2, path.as_ptr(), flags, mode)
 }
 
 pub fn close(fd: i32) -> isize {
     syscall1!(3, fd)
 }
 
+pub fn pipe2(fds: &mut [i32; 2], flags: i32) -> isize {
+    syscall2!(293, fds.as_mut_ptr(), flags)
+}
+
+pub fn dup(oldfd: i32) -> isize {
+    syscall1!(32, oldfd)
+}
+
+pub fn dup2(oldfd: i32, newfd: i32) -> isize {
+    syscall2!(33, oldfd, newfd)
+}
+
+pub const SEEK_SET: i32 = 0;
+
+pub fn lseek(fd: i32, offset: i64, whence: i32) -> isize {
+    syscall3!(8, fd, offset, whence)
+}
+
+pub const MFD_CLOEXEC: u32 = 0x0001;
+
+pub fn memfd_create(name: &[u8], flags: u32) -> isize {
+    syscall2!(319, name.as_ptr(), flags)
+}
+
 #[repr(C)]
 pub struct PollFd {
     pub fd: i32,
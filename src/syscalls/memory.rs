@@ -5,6 +5,7 @@ pub const PROT_WRITE: i32 = 0x2;
 pub const MAP_PRIVATE: i32 = 0x02;
 pub const MAP_ANONYMOUS: i32 = 0x20;
 pub const MAP_STACK: i32 = 0x20000;
+pub const MREMAP_MAYMOVE: i32 = 0x1;
 
 pub unsafe fn sys_mmap(
     addr: *mut u8,
@@ -16,3 +17,11 @@ pub unsafe fn sys_mmap(
 ) -> *mut u8 {
     syscall6!(9, addr, length, prot, flags, fd, offset) as *mut u8
 }
+
+pub unsafe fn sys_mremap(old_addr: *mut u8, old_length: usize, new_length: usize, flags: i32) -> *mut u8 {
+    syscall4!(25, old_addr, old_length, new_length, flags) as *mut u8
+}
+
+pub unsafe fn sys_munmap(addr: *mut u8, length: usize) -> isize {
+    syscall2!(11, addr, length)
+}
@@ -1,6 +1,7 @@
 #[macro_use]
 mod macros;
 
+pub mod errno;
 pub mod fs;
 pub mod io;
 pub mod memory;
@@ -9,6 +10,7 @@ pub mod process;
 pub mod signal;
 pub mod terminal;
 
+pub use errno::*;
 pub use process::*;
 pub use io::*;
 pub use fs::*;
@@ -11,16 +11,45 @@ pub fn sys_exit(code: i32) -> ! {
     }
 }
 
+/// Ends the whole process, server threads included, unlike `sys_exit`,
+/// which only ends the calling thread.
+pub fn exit_group(code: i32) -> ! {
+    unsafe {
+        core::arch::asm!(
+            "syscall",
+            in("rax") 231,
+            in("rdi") code,
+            options(noreturn)
+        );
+    }
+}
+
 pub fn fork() -> isize {
     syscall0!(57)
 }
 
-pub fn execve(path: &[u8], argv: &[*const u8]) -> isize {
-    syscall3!(59, path.as_ptr(), argv.as_ptr(), core::ptr::null::<*const u8>())
+pub fn execve(path: &[u8], argv: &[*const u8], envp: &[*const u8]) -> isize {
+    syscall3!(59, path.as_ptr(), argv.as_ptr(), envp.as_ptr())
+}
+
+pub const WNOHANG: i32 = 1;
+pub const WUNTRACED: i32 = 2;
+pub const WCONTINUED: i32 = 8;
+
+pub fn waitpid(pid: i32, status: &mut i32, options: i32) -> isize {
+    syscall4!(61, pid, status as *mut i32, options, 0)
+}
+
+pub fn setpgid(pid: i32, pgid: i32) -> isize {
+    syscall2!(109, pid, pgid)
 }
 
-pub fn waitpid(pid: i32, status: &mut i32) -> isize {
-    syscall3!(61, pid, status as *mut i32, 0)
+pub fn getpgrp() -> i32 {
+    syscall0!(111) as i32
+}
+
+pub fn kill(pid: i32, sig: i32) -> isize {
+    syscall2!(62, pid, sig)
 }
 
 pub const CLONE_VM: u64 = 0x00000100;
@@ -93,10 +122,38 @@ pub fn nanosleep(seconds: i64, nanoseconds: i64) -> isize {
     syscall2!(35, &req as *const TimeSpec, core::ptr::null_mut::<TimeSpec>())
 }
 
+pub const CLOCK_REALTIME: i32 = 0;
+
+pub fn clock_gettime(clock: i32, time: &mut TimeSpec) -> isize {
+    syscall2!(228, clock, time as *mut TimeSpec)
+}
+
 pub fn getpid() -> i32 {
     syscall0!(39) as i32
 }
 
+pub fn getuid() -> u32 {
+    syscall0!(102) as u32
+}
+
+pub fn geteuid() -> u32 {
+    syscall0!(107) as u32
+}
+
+#[repr(C)]
+pub struct Utsname {
+    pub sysname: [u8; 65],
+    pub nodename: [u8; 65],
+    pub release: [u8; 65],
+    pub version: [u8; 65],
+    pub machine: [u8; 65],
+    pub domainname: [u8; 65],
+}
+
+pub fn uname(buf: &mut Utsname) -> isize {
+    syscall1!(63, buf as *mut Utsname)
+}
+
 pub fn tgkill(tgid: i32, tid: i32, sig: i32) -> isize {
     syscall3!(234, tgid, tid, sig)
 }
@@ -8,9 +8,22 @@ pub fn request_shutdown() {
 }
 
 pub const SIGINT: i32 = 2;
+pub const SIGQUIT: i32 = 3;
 pub const SIGTERM: i32 = 15;
 pub const SIGPIPE: i32 = 13;
+pub const SIGCHLD: i32 = 17;
+pub const SIGCONT: i32 = 18;
+pub const SIGTSTP: i32 = 20;
+pub const SIGTTIN: i32 = 21;
+pub const SIGTTOU: i32 = 22;
+pub const SIG_DFL: usize = 0;
 pub const SIG_IGN: usize = 1;
+pub const SA_RESTART: u64 = 0x10000000;
+const SA_RESTORER: u64 = 0x04000000;
+
+pub const SIG_BLOCK: i32 = 0;
+pub const SIG_UNBLOCK: i32 = 1;
+pub const SIG_SETMASK: i32 = 2;
 
 #[repr(C)]
 pub struct SigAction {
@@ -35,46 +48,69 @@ pub fn rt_sigaction(signum: i32, act: *const SigAction, oldact: *mut SigAction)
     syscall4!(13, signum, act, oldact, 8)
 }
 
-extern "C" fn signal_restorer() {
-    unsafe {
-        core::arch::asm!(
-            "mov rax, 15",
-            "syscall",
-            options(noreturn)
-        );
-    }
+pub fn rt_sigprocmask(how: i32, set: *const u64, oldset: *mut u64) -> isize {
+    syscall4!(14, how, set, oldset, 8)
+}
+
+// rt_sigreturn expects the stack exactly as the kernel left it, so the
+// restorer is plain assembly without a function prologue.
+core::arch::global_asm!(
+    ".global signal_restorer",
+    "signal_restorer:",
+    "   mov rax, 15",
+    "   syscall",
+);
+
+unsafe extern "C" {
+    fn signal_restorer();
 }
 
 extern "C" fn signal_handler(_signum: i32) {
     SHUTDOWN_REQUESTED.store(true, Ordering::Release);
 }
 
+/// SIGTERM requests a graceful shutdown. Keyboard signals are ignored by
+/// the shell itself; they are meant for the foreground job, which gets the
+/// default dispositions back after fork.
 pub fn setup_signal_handlers() -> bool {
-    let mut sa = SigAction::new();
-    sa.sa_handler = signal_handler as usize;
-    sa.sa_flags = 0x04000000; // SA_RESTORER
-    sa.sa_restorer = signal_restorer as usize;
-    
-    if rt_sigaction(SIGINT, &sa as *const SigAction, core::ptr::null_mut()) < 0 {
-        return false;
-    }
-    
-    if rt_sigaction(SIGTERM, &sa as *const SigAction, core::ptr::null_mut()) < 0 {
+    if !set_signal_action(SIGTERM, signal_handler as usize, 0) {
         return false;
     }
     
-    let mut ignore = SigAction::new();
-    ignore.sa_handler = SIG_IGN;
-    ignore.sa_flags = 0x04000000;
-    ignore.sa_restorer = signal_restorer as usize;
-    
-    if rt_sigaction(SIGPIPE, &ignore as *const SigAction, core::ptr::null_mut()) < 0 {
-        return false;
+    for signum in [SIGINT, SIGQUIT, SIGTSTP, SIGPIPE] {
+        if !set_signal_action(signum, SIG_IGN, 0) {
+            return false;
+        }
     }
     
     true
 }
 
+/// Installs `handler` (a function address, `SIG_DFL` or `SIG_IGN`) for `signum`.
+pub fn set_signal_action(signum: i32, handler: usize, flags: u64) -> bool {
+    let mut sa = SigAction::new();
+    sa.sa_handler = handler;
+    sa.sa_flags = flags | SA_RESTORER;
+    sa.sa_restorer = signal_restorer as usize;
+    rt_sigaction(signum, &sa as *const SigAction, core::ptr::null_mut()) >= 0
+}
+
+/// Blocks or unblocks delivery of `signum` to the calling thread.
+pub fn mask_signal(how: i32, signum: i32) {
+    let set: u64 = 1 << (signum - 1);
+    rt_sigprocmask(how, &set, core::ptr::null_mut());
+}
+
+/// Gives a forked child the default signal dispositions and an empty
+/// signal mask before it runs a command.
+pub fn restore_default_signals() {
+    for signum in [SIGINT, SIGQUIT, SIGTERM, SIGPIPE, SIGCHLD, SIGTSTP, SIGTTIN, SIGTTOU] {
+        set_signal_action(signum, SIG_DFL, 0);
+    }
+    let empty: u64 = 0;
+    rt_sigprocmask(SIG_SETMASK, &empty, core::ptr::null_mut());
+}
+
 pub fn should_shutdown() -> bool {
     SHUTDOWN_REQUESTED.load(Ordering::Acquire)
 }
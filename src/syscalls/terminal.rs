@@ -2,8 +2,13 @@ use super::macros::*;
 
 pub const TCGETS: u64 = 0x5401;
 pub const TCSETS: u64 = 0x5402;
+pub const TIOCGPGRP: u64 = 0x540F;
+pub const TIOCSPGRP: u64 = 0x5410;
+pub const TIOCGWINSZ: u64 = 0x5413;
+pub const ISIG: u32 = 0x00000001;
 pub const ICANON: u32 = 0x00000002;
 pub const ECHO: u32 = 0x00000008;
+pub const IXON: u32 = 0x00000400;
 
 #[repr(C)]
 pub struct Termios {
@@ -18,6 +23,33 @@ pub struct Termios {
     pub c_ospeed: u32,
 }
 
+#[repr(C)]
+pub struct Winsize {
+    pub ws_row: u16,
+    pub ws_col: u16,
+    pub ws_xpixel: u16,
+    pub ws_ypixel: u16,
+}
+
 pub fn ioctl(fd: i32, request: u64, arg: u64) -> isize {
     syscall3!(16, fd, request, arg)
 }
+
+/// Makes `pgid` the foreground process group of the terminal on `fd`.
+pub fn tcsetpgrp(fd: i32, pgid: i32) -> isize {
+    ioctl(fd, TIOCSPGRP, &pgid as *const i32 as u64)
+}
+
+/// Foreground process group of the terminal on `fd`, or a negative errno.
+pub fn tcgetpgrp(fd: i32) -> isize {
+    let mut pgid: i32 = 0;
+    let ret = ioctl(fd, TIOCGPGRP, &mut pgid as *mut i32 as u64);
+    if ret < 0 { ret } else { pgid as isize }
+}
+
+/// Width of the terminal on `fd` in columns, or a negative errno.
+pub fn terminal_columns(fd: i32) -> isize {
+    let mut size = Winsize { ws_row: 0, ws_col: 0, ws_xpixel: 0, ws_ypixel: 0 };
+    let ret = ioctl(fd, TIOCGWINSZ, &mut size as *mut Winsize as u64);
+    if ret < 0 { ret } else { size.ws_col as isize }
+}
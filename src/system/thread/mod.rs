@@ -14,7 +14,9 @@ static HTTP_STACK: ThreadStack = ThreadStack::new_large();
 static HTTP_PORT: AtomicU16 = AtomicU16::new(0);
 
 const MAX_WS_THREADS: usize = 16;
-static WS_STACKS: [ThreadStack; MAX_WS_THREADS] = [const { ThreadStack::new() }; MAX_WS_THREADS];
+// Session threads run whole command lines, whose compound commands, nested
+// substitutions and function calls each take tens of KB of stack
+static WS_STACKS: [ThreadStack; MAX_WS_THREADS] = [const { ThreadStack::new_session() }; MAX_WS_THREADS];
 static WS_CLIENT_FDS: [AtomicI32; MAX_WS_THREADS] = [const { AtomicI32::new(0) }; MAX_WS_THREADS];
 static WS_THREAD_ACTIVE: [AtomicBool; MAX_WS_THREADS] = [const { AtomicBool::new(false) }; MAX_WS_THREADS];
 
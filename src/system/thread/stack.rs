@@ -7,17 +7,17 @@ pub struct ThreadStack {
 }
 
 impl ThreadStack {
-    pub const fn new() -> Self {
+    pub const fn new_large() -> Self {
         Self {
             ptr: AtomicPtr::new(core::ptr::null_mut()),
-            size: 131072,  // 128 KB
+            size: 524288,  // 512 KB for server threads
         }
     }
     
-    pub const fn new_large() -> Self {
+    pub const fn new_session() -> Self {
         Self {
             ptr: AtomicPtr::new(core::ptr::null_mut()),
-            size: 524288,  // 512 KB for server threads
+            size: 4194304,  // 4 MB for session threads, only touched pages are used
         }
     }
     
@@ -18,30 +18,6 @@ pub fn trim_newline(buf: &[u8]) -> &[u8] {
     &buf[..end]
 }
 
-pub fn trim_spaces(s: &[u8]) -> &[u8] {
-    let mut start = 0;
-    let mut end = s.len();
-    
-    while start < s.len() && s[start] == b' ' {
-        start += 1;
-    }
-    
-    while end > start && (s[end - 1] == b' ' || s[end - 1] == 0) {
-        end -= 1;
-    }
-    
-    &s[start..end]
-}
-
-pub fn split_first_word(input: &[u8]) -> (&[u8], &[u8]) {
-    for i in 0..input.len() {
-        if input[i] == b' ' {
-            return (&input[..i], &input[i + 1..]);
-        }
-    }
-    (input, &[])
-}
-
 fn bytes_less_than(a: &[u8], b: &[u8]) -> bool {
     let min_len = if a.len() < b.len() { a.len() } else { b.len() };
     